@@ -0,0 +1,94 @@
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes the data with the standard alphabet and padding.
+///
+/// As defined by [rfc4648 section 4](https://tools.ietf.org/html/rfc4648#section-4).
+pub(crate) fn encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(ALPHABET[(n >> (18 - i * 6) & 0x3f) as usize] as char),
+                false => text.push('='),
+            };
+        }
+    }
+
+    text
+}
+
+/// Decodes text of the standard alphabet with padding. Returns `None` for invalid input.
+pub(crate) fn decode(text: &str) -> Option<Vec<u8>> {
+    let bytes = text.as_bytes();
+    if bytes.len() % 4 != 0 {
+        return None;
+    }
+    let mut data = Vec::with_capacity(bytes.len() / 4 * 3);
+
+    for (index, chunk) in bytes.chunks(4).enumerate() {
+        let last = index == bytes.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for b in chunk[..4 - padding].iter() {
+            n = n << 6 | ALPHABET.iter().position(|a| a == b)? as u32;
+        }
+        n <<= 6 * padding;
+        data.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8][..3 - padding]);
+    }
+
+    Some(data)
+}
+
+/// Encodes the data with the URL and filename safe alphabet without padding.
+///
+/// As defined by [rfc4648 section 5](https://tools.ietf.org/html/rfc4648#section-5).
+pub(crate) fn encode_url(data: &[u8]) -> String {
+    encode(data).trim_end_matches('=').replace('+', "-").replace('/', "_")
+}
+
+/// Decodes text of the URL and filename safe alphabet without padding. Returns `None` for
+/// invalid input.
+pub(crate) fn decode_url(text: &str) -> Option<Vec<u8>> {
+    if text.contains(['+', '/', '=']) || text.len() % 4 == 1 {
+        return None;
+    }
+    let mut text = text.replace('-', "+").replace('_', "/");
+    while text.len() % 4 != 0 {
+        text.push('=');
+    }
+    decode(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_and_decodes() {
+        let vectors = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+        for (data, text) in vectors.iter() {
+            assert_eq!(encode(data.as_bytes()), *text);
+            assert_eq!(decode(text).unwrap(), data.as_bytes());
+        }
+        assert!(decode("Zm9").is_none());
+        assert!(decode("Zg==Zg==").is_none());
+        assert!(decode("Z!==").is_none());
+    }
+
+    #[test]
+    fn encodes_and_decodes_url() {
+        assert_eq!(encode_url(&[0xfb, 0xff, 0x01]), "-_8B");
+        assert_eq!(encode_url(b"fo"), "Zm8");
+        assert_eq!(decode_url("-_8B").unwrap(), [0xfb, 0xff, 0x01]);
+        assert_eq!(decode_url("Zm8").unwrap(), b"fo");
+        assert!(decode_url("Zm8=").is_none());
+        assert!(decode_url("+/8B").is_none());
+        assert!(decode_url("Zm9vY").is_none());
+    }
+}
@@ -0,0 +1,673 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use async_std::fs;
+use async_std::path::PathBuf;
+use async_std::prelude::*;
+use crate::{CacheControl, Freshness, Method, Status};
+use crate::parse::split_list;
+
+/// Future returned by `CacheStorage` operations.
+pub type StorageFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+/// Backend holding serialized cache entries under opaque, file-name safe keys.
+pub trait CacheStorage {
+    fn keys(&self) -> StorageFuture<'_, Vec<String>>;
+    fn get<'a>(&'a self, key: &'a str) -> StorageFuture<'a, Option<Vec<u8>>>;
+    fn put<'a>(&'a mut self, key: &'a str, data: &'a [u8]) -> StorageFuture<'a, ()>;
+    fn remove<'a>(&'a mut self, key: &'a str) -> StorageFuture<'a, ()>;
+}
+
+/// Cache storage keeping entries in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    entries: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryStorage {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CacheStorage for MemoryStorage {
+
+    fn keys(&self) -> StorageFuture<'_, Vec<String>> {
+        Box::pin(async move { Ok(self.entries.keys().cloned().collect()) })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> StorageFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move { Ok(self.entries.get(key).cloned()) })
+    }
+
+    fn put<'a>(&'a mut self, key: &'a str, data: &'a [u8]) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            self.entries.insert(key.to_string(), data.to_vec());
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a mut self, key: &'a str) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            self.entries.remove(key);
+            Ok(())
+        })
+    }
+}
+
+/// Cache storage keeping one file per entry in a directory.
+#[derive(Debug, Clone)]
+pub struct DirectoryStorage {
+    path: PathBuf,
+}
+
+impl DirectoryStorage {
+
+    /// Uses the directory at `path` which is created if missing.
+    pub async fn new<P>(path: P) -> Result<Self, Error>
+        where
+        P: Into<PathBuf>,
+    {
+        let path = path.into();
+        fs::create_dir_all(&path).await?;
+        Ok(Self { path })
+    }
+}
+
+impl CacheStorage for DirectoryStorage {
+
+    fn keys(&self) -> StorageFuture<'_, Vec<String>> {
+        Box::pin(async move {
+            let mut keys = Vec::new();
+            let mut entries = fs::read_dir(&self.path).await?;
+            while let Some(entry) = entries.next().await {
+                let name = entry?.file_name().to_string_lossy().to_string();
+                if name.ends_with(".entry") {
+                    keys.push(name.trim_end_matches(".entry").to_string());
+                }
+            }
+            Ok(keys)
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> StorageFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move {
+            match fs::read(self.path.join(format!("{}.entry", key))).await {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn put<'a>(&'a mut self, key: &'a str, data: &'a [u8]) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            let tmp = self.path.join(format!("{}.tmp", key));
+            fs::write(&tmp, data).await?;
+            fs::rename(tmp, self.path.join(format!("{}.entry", key))).await
+        })
+    }
+
+    fn remove<'a>(&'a mut self, key: &'a str) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            match fs::remove_file(self.path.join(format!("{}.entry", key))).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+/// Response stored in a `Cache`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    pub status: Status,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// When the request was sent.
+    pub request_time: SystemTime,
+    /// When the response was received.
+    pub response_time: SystemTime,
+}
+
+impl CachedResponse {
+
+    pub fn new(status: Status, headers: Vec<(String, String)>, body: Vec<u8>, request_time: SystemTime, response_time: SystemTime) -> Self {
+        Self { status, headers, body, request_time, response_time }
+    }
+
+    /// Returns the values of all header fields with the `name` joined with commas.
+    pub fn header(&self, name: &str) -> Option<String> {
+        header_value(&self.headers, name)
+    }
+
+    pub fn freshness(&self) -> Freshness {
+        let mut freshness = Freshness::new();
+        for (name, value) in self.headers.iter() {
+            freshness.set_header(name, value).ok(); // malformed directives are ignored
+        }
+        freshness
+    }
+
+    /// Returns the value for the `Age` header of the served response.
+    pub fn age(&self, now: SystemTime) -> Duration {
+        self.freshness().current_age(self.request_time, self.response_time, now)
+    }
+
+    /// Returns the `If-None-Match` and `If-Modified-Since` headers for validating the response
+    /// with the origin server.
+    pub fn validators(&self) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+        if let Some(etag) = self.header("etag") {
+            headers.push((String::from("If-None-Match"), etag));
+        }
+        if let Some(modified) = self.header("last-modified") {
+            headers.push((String::from("If-Modified-Since"), modified));
+        }
+        headers
+    }
+}
+
+/// Result of a cache lookup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup {
+
+    /// The response can be served without contacting the origin server.
+    Fresh(CachedResponse),
+
+    /// The response must be validated by sending the request with `CachedResponse::validators`
+    /// and passing a `304 Not Modified` answer to `Cache::revalidate`.
+    Stale(CachedResponse),
+
+    Miss,
+}
+
+#[derive(Debug, Clone)]
+struct Variant {
+    id: String,
+    vary: Vec<(String, Option<String>)>,
+    size: usize,
+    used: u64,
+    response_time: SystemTime,
+}
+
+/// HTTP cache with `Vary` support and least-recently-used eviction.
+///
+/// Responses are keyed by method and URI and then by the request header values named in their
+/// `Vary` header. A `shared` cache (e.g. a proxy) does not store private responses.
+///
+/// See [rfc7234](https://tools.ietf.org/html/rfc7234) for more details.
+#[derive(Debug)]
+pub struct Cache<S>
+    where
+    S: CacheStorage,
+{
+    storage: S,
+    shared: bool,
+    capacity: usize,
+    size: usize,
+    tick: u64,
+    index: HashMap<String, Vec<Variant>>,
+}
+
+impl<S> Cache<S>
+    where
+    S: CacheStorage,
+{
+
+    /// Creates a cache holding up to `capacity` bytes of serialized entries. Entries already
+    /// present in the storage are indexed.
+    pub async fn new(storage: S, capacity: usize, shared: bool) -> Result<Self, Error> {
+        let mut cache = Self { storage, shared, capacity, size: 0, tick: 0, index: HashMap::new() };
+
+        for id in cache.storage.keys().await? {
+            match cache.storage.get(&id).await?.map(|data| decode_entry(&data)) {
+                Some(Ok((key, vary, response))) if entry_id(&key, &vary) == id => {
+                    let size = encode_entry(&key, &vary, &response).len();
+                    cache.size += size;
+                    cache.index.entry(key).or_default().push(Variant { id, vary, size, used: 0, response_time: response.response_time });
+                },
+                _ => cache.storage.remove(&id).await?,
+            };
+        }
+        cache.evict(0).await?;
+
+        Ok(cache)
+    }
+
+    /// The number of bytes held by the cache.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.values().map(|variants| variants.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Looks up a stored response for a request.
+    pub async fn lookup(&mut self, method: Method, uri: &str, headers: &[(String, String)], now: SystemTime) -> Result<Lookup, Error> {
+        let request = request_cache_control(headers);
+        let keys = match method {
+            Method::Head => vec![primary_key(Method::Head, uri), primary_key(Method::Get, uri)],
+            Method::Get => vec![primary_key(Method::Get, uri)],
+            _ => return Ok(Lookup::Miss),
+        };
+
+        for key in keys {
+            let variant = self.index.get_mut(&key).and_then(|variants| {
+                variants.iter_mut()
+                    .filter(|variant| vary_matches(&variant.vary, headers))
+                    .max_by_key(|variant| variant.response_time)
+            });
+            let (id, vary) = match variant {
+                Some(variant) => {
+                    self.tick += 1;
+                    variant.used = self.tick;
+                    (variant.id.clone(), variant.vary.clone())
+                },
+                None => continue,
+            };
+
+            let response = match self.storage.get(&id).await?.map(|data| decode_entry(&data)) {
+                Some(Ok((k, v, response))) if k == key && v == vary => response,
+                _ => {
+                    self.forget_variant(&key, &id);
+                    continue;
+                },
+            };
+            let age = response.age(now);
+            return match response.freshness().is_fresh(&request, response.status, self.shared, age) {
+                true => Ok(Lookup::Fresh(response)),
+                false => Ok(Lookup::Stale(response)),
+            };
+        }
+
+        Ok(Lookup::Miss)
+    }
+
+    /// Stores a response to a request. Returns `false` if the response is not storable.
+    pub async fn store(&mut self, method: Method, uri: &str, headers: &[(String, String)], response: CachedResponse) -> Result<bool, Error> {
+        let authorization = header_value(headers, "authorization").is_some();
+        if request_cache_control(headers).no_store || !response.freshness().is_storable(method, response.status, self.shared, authorization) {
+            return Ok(false);
+        }
+
+        let names: Vec<String> = response.header("vary")
+            .map(|vary| split_list(&vary).into_iter().map(|name| name.to_lowercase()).collect())
+            .unwrap_or_default();
+        if names.iter().any(|name| name == "*") {
+            return Ok(false);
+        }
+        let vary: Vec<(String, Option<String>)> = names.into_iter()
+            .map(|name| { let value = header_value(headers, &name); (name, value) })
+            .collect();
+
+        let key = primary_key(method, uri);
+        let data = encode_entry(&key, &vary, &response);
+        if data.len() > self.capacity {
+            return Ok(false);
+        }
+
+        let id = entry_id(&key, &vary);
+        let owners: Vec<String> = self.index.iter()
+            .filter(|(_, variants)| variants.iter().any(|variant| variant.id == id))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for owner in owners {
+            self.remove_variant(&owner, &id).await?; // the same or a colliding entry
+        }
+        self.evict(data.len()).await?;
+        self.storage.put(&id, &data).await?;
+
+        self.tick += 1;
+        self.size += data.len();
+        self.index.entry(key).or_default().push(Variant {
+            id,
+            vary,
+            size: data.len(),
+            used: self.tick,
+            response_time: response.response_time,
+        });
+
+        Ok(true)
+    }
+
+    /// Updates the stored response after the origin server answered a validation request with
+    /// `304 Not Modified` and returns the updated response. The `headers` of the `304`
+    /// response replace the stored ones.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7234#section-4.3.4) for more details.
+    pub async fn revalidate(&mut self, method: Method, uri: &str, headers: &[(String, String)], not_modified: &[(String, String)], request_time: SystemTime, response_time: SystemTime) -> Result<Option<CachedResponse>, Error> {
+        let key = primary_key(method, uri);
+        let variant = self.index.get(&key).and_then(|variants| {
+            variants.iter().find(|variant| vary_matches(&variant.vary, headers))
+        });
+        let (id, vary) = match variant {
+            Some(variant) => (variant.id.clone(), variant.vary.clone()),
+            None => return Ok(None),
+        };
+        let mut response = match self.storage.get(&id).await?.map(|data| decode_entry(&data)) {
+            Some(Ok((k, v, response))) if k == key && v == vary => response,
+            _ => {
+                self.forget_variant(&key, &id);
+                return Ok(None);
+            },
+        };
+
+        let updates: Vec<&(String, String)> = not_modified.iter()
+            .filter(|(name, _)| !name.eq_ignore_ascii_case("content-length"))
+            .collect();
+        response.headers.retain(|(name, _)| !updates.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)));
+        response.headers.extend(updates.into_iter().cloned());
+        response.request_time = request_time;
+        response.response_time = response_time;
+
+        self.remove_variant(&key, &id).await?;
+        let data = encode_entry(&key, &vary, &response);
+        self.evict(data.len()).await?;
+        self.storage.put(&id, &data).await?;
+        self.tick += 1;
+        self.size += data.len();
+        self.index.entry(key).or_default().push(Variant { id, vary, size: data.len(), used: self.tick, response_time });
+
+        Ok(Some(response))
+    }
+
+    /// Removes all responses stored for the URI. Call this after a successful response to an
+    /// unsafe request (e.g. `POST`) targeting the URI.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7234#section-4.4) for more details.
+    pub async fn invalidate(&mut self, uri: &str) -> Result<(), Error> {
+        for method in [Method::Get, Method::Head].iter() {
+            if let Some(variants) = self.index.remove(&primary_key(*method, uri)) {
+                for variant in variants {
+                    self.size -= variant.size;
+                    self.storage.remove(&variant.id).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove_variant(&mut self, key: &str, id: &str) -> Result<(), Error> {
+        if let Some(variants) = self.index.get_mut(key) {
+            if let Some(index) = variants.iter().position(|variant| variant.id == id) {
+                let variant = variants.remove(index);
+                self.size -= variant.size;
+                self.storage.remove(&variant.id).await?;
+            }
+            if variants.is_empty() {
+                self.index.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops a variant from the index without touching the storage which holds another entry
+    /// under its id.
+    fn forget_variant(&mut self, key: &str, id: &str) {
+        if let Some(variants) = self.index.get_mut(key) {
+            if let Some(index) = variants.iter().position(|variant| variant.id == id) {
+                self.size -= variants.remove(index).size;
+            }
+            if variants.is_empty() {
+                self.index.remove(key);
+            }
+        }
+    }
+
+    /// Removes least recently used entries until `size` more bytes fit.
+    async fn evict(&mut self, size: usize) -> Result<(), Error> {
+        while self.size + size > self.capacity {
+            let oldest = self.index.iter()
+                .flat_map(|(key, variants)| variants.iter().map(move |variant| (key, variant)))
+                .min_by_key(|(_, variant)| variant.used)
+                .map(|(key, variant)| (key.clone(), variant.id.clone()));
+            match oldest {
+                Some((key, id)) => self.remove_variant(&key, &id).await?,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+}
+
+fn primary_key(method: Method, uri: &str) -> String {
+    format!("{} {}", method, uri)
+}
+
+/// Returns a file-name safe FNV-1a hash of the primary key and the selecting headers. Entries
+/// hold the full key which is compared on every read since the hash may collide.
+fn entry_id(key: &str, vary: &[(String, Option<String>)]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| for b in bytes.iter() {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    };
+    feed(key.as_bytes());
+    for (name, value) in vary.iter() {
+        feed(b"\n");
+        feed(name.as_bytes());
+        feed(b":");
+        feed(value.as_deref().unwrap_or("\0").as_bytes());
+    }
+    format!("{:016x}", hash)
+}
+
+fn header_value(headers: &[(String, String)], name: &str) -> Option<String> {
+    let values: Vec<&str> = headers.iter()
+        .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.trim())
+        .collect();
+    match values.is_empty() {
+        true => None,
+        false => Some(values.join(", ")),
+    }
+}
+
+fn request_cache_control(headers: &[(String, String)]) -> CacheControl {
+    let mut control = match header_value(headers, "cache-control") {
+        Some(value) => CacheControl::from_str(&value).unwrap_or_default(),
+        None => CacheControl::new(),
+    };
+    let pragma = header_value(headers, "pragma").map(|p| p.to_lowercase().contains("no-cache")).unwrap_or(false);
+    if header_value(headers, "cache-control").is_none() && pragma {
+        control.no_cache = Some(Vec::new());
+    }
+    control
+}
+
+fn vary_matches(vary: &[(String, Option<String>)], headers: &[(String, String)]) -> bool {
+    let normalize = |value: Option<String>| value.map(|v| split_list(&v).join(","));
+    vary.iter().all(|(name, value)| normalize(header_value(headers, name)) == normalize(value.clone()))
+}
+
+fn encode_entry(key: &str, vary: &[(String, Option<String>)], response: &CachedResponse) -> Vec<u8> {
+    let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let mut data = format!("{}\r\n{} {} {} {}\r\n", key, response.status, secs(response.request_time), secs(response.response_time), vary.len());
+    for (name, value) in vary.iter() {
+        match value {
+            Some(value) => data.push_str(&format!("{}: {}\r\n", name, value)),
+            None => data.push_str(&format!("{}\r\n", name)),
+        };
+    }
+    for (name, value) in response.headers.iter() {
+        data.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    data.push_str("\r\n");
+
+    let mut data = data.into_bytes();
+    data.extend_from_slice(&response.body);
+    data
+}
+
+#[allow(clippy::type_complexity)]
+fn decode_entry(data: &[u8]) -> Result<(String, Vec<(String, Option<String>)>, CachedResponse), Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "The cache entry is corrupted.");
+    let end = data.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(invalid)?;
+    let head = std::str::from_utf8(&data[..end]).map_err(|_| invalid())?;
+    let mut lines = head.split("\r\n");
+
+    let key = lines.next().ok_or_else(invalid)?.to_string();
+    let meta: Vec<&str> = lines.next().ok_or_else(invalid)?.split(' ').collect();
+    if meta.len() != 4 {
+        return Err(invalid());
+    }
+    let status = Status::from_str(meta[0])?;
+    let time = |v: &str| v.parse::<u64>().map(|secs| UNIX_EPOCH + Duration::from_secs(secs)).map_err(|_| invalid());
+    let (request_time, response_time) = (time(meta[1])?, time(meta[2])?);
+    let count = meta[3].parse::<usize>().map_err(|_| invalid())?; // selecting headers
+
+    let (mut vary, mut headers) = (Vec::new(), Vec::new());
+    for (index, line) in lines.enumerate() {
+        let (name, value) = match line.find(": ") {
+            Some(index) => (line[..index].to_string(), Some(line[index + 2..].to_string())),
+            None => (line.to_string(), None),
+        };
+        match (index < count, value) {
+            (true, value) => vary.push((name, value)),
+            (false, Some(value)) => headers.push((name, value)),
+            (false, None) => return Err(invalid()),
+        };
+    }
+    if vary.len() != count {
+        return Err(invalid());
+    }
+
+    let response = CachedResponse::new(status, headers, data[end + 4..].to_vec(), request_time, response_time);
+    Ok((key, vary, response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    fn response(items: &[(&str, &str)], body: &str, time: SystemTime) -> CachedResponse {
+        CachedResponse::new(Status::Ok, headers(items), body.as_bytes().to_vec(), time, time)
+    }
+
+    #[async_std::test]
+    async fn serves_fresh_and_stale_responses() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let mut cache = Cache::new(MemoryStorage::new(), 4096, true).await.unwrap();
+        let res = response(&[("Cache-Control", "max-age=60"), ("ETag", "\"v1\"")], "hello", now);
+        assert!(cache.store(Method::Get, "/a", &[], res.clone()).await.unwrap());
+
+        assert_eq!(cache.lookup(Method::Get, "/a", &[], now + Duration::from_secs(30)).await.unwrap(), Lookup::Fresh(res.clone()));
+        assert_eq!(cache.lookup(Method::Head, "/a", &[], now).await.unwrap(), Lookup::Fresh(res.clone()));
+        assert_eq!(cache.lookup(Method::Get, "/b", &[], now).await.unwrap(), Lookup::Miss);
+        let request = headers(&[("Cache-Control", "no-cache")]);
+        assert_eq!(cache.lookup(Method::Get, "/a", &request, now).await.unwrap(), Lookup::Stale(res.clone()));
+
+        let later = now + Duration::from_secs(90);
+        match cache.lookup(Method::Get, "/a", &[], later).await.unwrap() {
+            Lookup::Stale(stale) => assert_eq!(stale.validators(), headers(&[("If-None-Match", "\"v1\"")])),
+            lookup => panic!("unexpected {:?}", lookup),
+        };
+        let updated = cache.revalidate(Method::Get, "/a", &[], &headers(&[("Cache-Control", "max-age=120")]), later, later).await.unwrap().unwrap();
+        assert_eq!(updated.header("cache-control"), Some(String::from("max-age=120")));
+        assert_eq!(updated.body, b"hello");
+        assert!(matches!(cache.lookup(Method::Get, "/a", &[], later + Duration::from_secs(60)).await.unwrap(), Lookup::Fresh(_)));
+
+        cache.invalidate("/a").await.unwrap();
+        assert_eq!(cache.lookup(Method::Get, "/a", &[], later).await.unwrap(), Lookup::Miss);
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[async_std::test]
+    async fn honours_no_store_and_private() {
+        let now = UNIX_EPOCH;
+        let mut cache = Cache::new(MemoryStorage::new(), 4096, true).await.unwrap();
+        assert!(!cache.store(Method::Get, "/", &[], response(&[("Cache-Control", "no-store")], "", now)).await.unwrap());
+        assert!(!cache.store(Method::Get, "/", &[], response(&[("Cache-Control", "private, max-age=60")], "", now)).await.unwrap());
+        assert!(!cache.store(Method::Post, "/", &[], response(&[("Cache-Control", "max-age=60")], "", now)).await.unwrap());
+        let request = headers(&[("Cache-Control", "no-store")]);
+        assert!(!cache.store(Method::Get, "/", &request, response(&[("Cache-Control", "max-age=60")], "", now)).await.unwrap());
+        let mut cache = Cache::new(MemoryStorage::new(), 4096, false).await.unwrap();
+        assert!(cache.store(Method::Get, "/", &[], response(&[("Cache-Control", "private, max-age=60")], "", now)).await.unwrap());
+    }
+
+    #[async_std::test]
+    async fn selects_variants() {
+        let now = UNIX_EPOCH;
+        let mut cache = Cache::new(MemoryStorage::new(), 4096, true).await.unwrap();
+        let gzip = headers(&[("Accept-Encoding", "gzip, br")]);
+        let plain = headers(&[("Accept-Encoding", "identity")]);
+        let vary = [("Cache-Control", "max-age=60"), ("Vary", "Accept-Encoding")];
+        cache.store(Method::Get, "/", &gzip, response(&vary, "gzip", now)).await.unwrap();
+        cache.store(Method::Get, "/", &plain, response(&vary, "plain", now)).await.unwrap();
+        assert_eq!(cache.len(), 2);
+
+        let request = headers(&[("accept-encoding", "gzip,br")]);
+        assert_eq!(cache.lookup(Method::Get, "/", &request, now).await.unwrap(), Lookup::Fresh(response(&vary, "gzip", now)));
+        assert_eq!(cache.lookup(Method::Get, "/", &plain, now).await.unwrap(), Lookup::Fresh(response(&vary, "plain", now)));
+        assert_eq!(cache.lookup(Method::Get, "/", &[], now).await.unwrap(), Lookup::Miss);
+        assert!(!cache.store(Method::Get, "/", &[], response(&[("Cache-Control", "max-age=60"), ("Vary", "*")], "", now)).await.unwrap());
+    }
+
+    #[async_std::test]
+    async fn verifies_stored_key() {
+        let now = UNIX_EPOCH;
+        let mut cache = Cache::new(MemoryStorage::new(), 4096, true).await.unwrap();
+        let fresh = [("Cache-Control", "max-age=60")];
+        cache.store(Method::Get, "/a", &[], response(&fresh, "a", now)).await.unwrap();
+        let id = entry_id("GET /a", &[]);
+        cache.storage.put(&id, &encode_entry("GET /b", &[], &response(&fresh, "b", now))).await.unwrap(); // colliding entry
+        assert_eq!(cache.lookup(Method::Get, "/a", &[], now).await.unwrap(), Lookup::Miss);
+        assert!(cache.is_empty());
+    }
+
+    #[async_std::test]
+    async fn keeps_headers_resembling_vary_entries() {
+        let now = UNIX_EPOCH;
+        let mut cache = Cache::new(MemoryStorage::new(), 4096, true).await.unwrap();
+        let res = response(&[("Cache-Control", "max-age=60"), ("~x", "y"), ("Vary", "~x")], "", now);
+        let request = headers(&[("~x", "1")]);
+        cache.store(Method::Get, "/", &request, res.clone()).await.unwrap();
+        assert_eq!(cache.lookup(Method::Get, "/", &request, now).await.unwrap(), Lookup::Fresh(res));
+        assert_eq!(cache.lookup(Method::Get, "/", &[], now).await.unwrap(), Lookup::Miss);
+    }
+
+    #[async_std::test]
+    async fn evicts_least_recently_used() {
+        let now = UNIX_EPOCH;
+        let fresh = [("Cache-Control", "max-age=60")];
+        let size = encode_entry("GET /a", &[], &response(&fresh, "0123456789", now)).len();
+        let mut cache = Cache::new(MemoryStorage::new(), size * 2, true).await.unwrap();
+        cache.store(Method::Get, "/a", &[], response(&fresh, "0123456789", now)).await.unwrap();
+        cache.store(Method::Get, "/b", &[], response(&fresh, "0123456789", now)).await.unwrap();
+        cache.lookup(Method::Get, "/a", &[], now).await.unwrap();
+        cache.store(Method::Get, "/c", &[], response(&fresh, "0123456789", now)).await.unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(matches!(cache.lookup(Method::Get, "/a", &[], now).await.unwrap(), Lookup::Fresh(_)));
+        assert_eq!(cache.lookup(Method::Get, "/b", &[], now).await.unwrap(), Lookup::Miss);
+        assert!(!cache.store(Method::Get, "/d", &[], response(&fresh, &"x".repeat(size * 2), now)).await.unwrap());
+    }
+
+    #[async_std::test]
+    async fn persists_in_directory() {
+        let now = UNIX_EPOCH;
+        let path = std::env::temp_dir().join(format!("async-httplib-cache-{}", std::process::id()));
+        let res = response(&[("Cache-Control", "max-age=60")], "hello", now);
+        let mut cache = Cache::new(DirectoryStorage::new(&path).await.unwrap(), 4096, true).await.unwrap();
+        cache.store(Method::Get, "/a", &[], res.clone()).await.unwrap();
+
+        let mut cache = Cache::new(DirectoryStorage::new(&path).await.unwrap(), 4096, true).await.unwrap();
+        assert_eq!(cache.lookup(Method::Get, "/a", &[], now).await.unwrap(), Lookup::Fresh(res));
+        cache.invalidate("/a").await.unwrap();
+        assert!(DirectoryStorage::new(&path).await.unwrap().keys().await.unwrap().is_empty());
+        fs::remove_dir_all(path).await.unwrap();
+    }
+}
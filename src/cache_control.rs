@@ -0,0 +1,425 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use crate::{HttpDate, Method, Status};
+use crate::media::{is_token_char, read_quoted_string, read_token, skip_whitespace};
+
+/// Directives of the `Cache-Control` request and response headers.
+///
+/// As defined by [rfc7234 section 5.2](https://tools.ietf.org/html/rfc7234#section-5.2).
+/// Directive names are case-insensitive. Unknown directives are kept as extensions.
+/// [Read more](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Cache-Control)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+    /// `Some(None)` when the directive has no value (any staleness is accepted).
+    pub max_stale: Option<Option<u64>>,
+    pub min_fresh: Option<u64>,
+    /// `Some` with an empty list when the directive has no field names.
+    pub no_cache: Option<Vec<String>>,
+    pub no_store: bool,
+    pub no_transform: bool,
+    pub only_if_cached: bool,
+    pub must_revalidate: bool,
+    pub proxy_revalidate: bool,
+    pub must_understand: bool,
+    pub public: bool,
+    /// `Some` with an empty list when the directive has no field names.
+    pub private: Option<Vec<String>>,
+    pub immutable: bool,
+    pub stale_while_revalidate: Option<u64>,
+    pub stale_if_error: Option<u64>,
+    pub extensions: Vec<(String, Option<String>)>,
+}
+
+impl CacheControl {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges directives of an additional `Cache-Control` header field.
+    pub fn merge(&mut self, value: &str) -> Result<(), Error> {
+        let bytes = value.as_bytes();
+        let mut index = 0;
+
+        loop {
+            index = skip_whitespace(bytes, index);
+            if index == bytes.len() {
+                break;
+            } else if bytes[index] == b',' {
+                index += 1;
+                continue;
+            }
+
+            let name = read_token(bytes, &mut index).to_lowercase();
+            if name.is_empty() {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("The cache control `{}` is invalid.", value)));
+            }
+            let argument = match bytes.get(index) {
+                Some(b'=') if bytes.get(index + 1) == Some(&b'"') => {
+                    index += 1;
+                    match read_quoted_string(bytes, &mut index) {
+                        Some(argument) => Some(argument),
+                        None => return Err(Error::new(ErrorKind::InvalidInput, format!("The cache control `{}` is invalid.", value))),
+                    }
+                },
+                Some(b'=') => {
+                    index += 1;
+                    Some(read_token(bytes, &mut index))
+                },
+                _ => None,
+            };
+            index = skip_whitespace(bytes, index);
+            if index < bytes.len() && bytes[index] != b',' {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("The cache control `{}` is invalid.", value)));
+            }
+
+            self.set_directive(&name, argument);
+        }
+
+        Ok(())
+    }
+
+    fn set_directive(&mut self, name: &str, argument: Option<String>) {
+        let seconds = || argument.as_deref().and_then(parse_delta_seconds);
+        let fields = || argument.as_deref()
+            .map(|a| a.split(',').map(|f| f.trim().to_lowercase()).filter(|f| !f.is_empty()).collect())
+            .unwrap_or_default();
+        match name {
+            "max-age" => self.max_age = seconds().or(Some(0)), // invalid values mean stale
+            "s-maxage" => self.s_maxage = seconds().or(Some(0)),
+            "max-stale" => self.max_stale = Some(seconds()),
+            "min-fresh" => self.min_fresh = seconds(),
+            "no-cache" => self.no_cache = Some(fields()),
+            "no-store" => self.no_store = true,
+            "no-transform" => self.no_transform = true,
+            "only-if-cached" => self.only_if_cached = true,
+            "must-revalidate" => self.must_revalidate = true,
+            "proxy-revalidate" => self.proxy_revalidate = true,
+            "must-understand" => self.must_understand = true,
+            "public" => self.public = true,
+            "private" => self.private = Some(fields()),
+            "immutable" => self.immutable = true,
+            "stale-while-revalidate" => self.stale_while_revalidate = seconds(),
+            "stale-if-error" => self.stale_if_error = seconds(),
+            name => self.extensions.push((name.to_string(), argument)),
+        };
+    }
+
+    pub fn extension(&self, name: &str) -> Option<Option<&str>> {
+        let name = name.to_lowercase();
+        self.extensions.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_deref())
+    }
+}
+
+impl Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut directives: Vec<String> = Vec::new();
+        let fields = |name: &str, fields: &Vec<String>| match fields.is_empty() {
+            true => name.to_string(),
+            false => format!("{}=\"{}\"", name, fields.join(", ")),
+        };
+        let flags = [
+            (self.no_store, "no-store"), (self.no_transform, "no-transform"),
+            (self.only_if_cached, "only-if-cached"), (self.must_revalidate, "must-revalidate"),
+            (self.proxy_revalidate, "proxy-revalidate"), (self.must_understand, "must-understand"),
+            (self.public, "public"), (self.immutable, "immutable"),
+        ];
+
+        if let Some(list) = &self.no_cache {
+            directives.push(fields("no-cache", list));
+        }
+        if let Some(list) = &self.private {
+            directives.push(fields("private", list));
+        }
+        directives.extend(flags.iter().filter(|(set, _)| *set).map(|(_, name)| name.to_string()));
+        if let Some(seconds) = self.max_age {
+            directives.push(format!("max-age={}", seconds));
+        }
+        if let Some(seconds) = self.s_maxage {
+            directives.push(format!("s-maxage={}", seconds));
+        }
+        match self.max_stale {
+            Some(Some(seconds)) => directives.push(format!("max-stale={}", seconds)),
+            Some(None) => directives.push(String::from("max-stale")),
+            None => (),
+        };
+        if let Some(seconds) = self.min_fresh {
+            directives.push(format!("min-fresh={}", seconds));
+        }
+        if let Some(seconds) = self.stale_while_revalidate {
+            directives.push(format!("stale-while-revalidate={}", seconds));
+        }
+        if let Some(seconds) = self.stale_if_error {
+            directives.push(format!("stale-if-error={}", seconds));
+        }
+        for (name, argument) in self.extensions.iter() {
+            directives.push(match argument {
+                Some(a) if !a.is_empty() && a.bytes().all(is_token_char) => format!("{}={}", name, a),
+                Some(a) => format!("{}=\"{}\"", name, a.replace('\\', "\\\\").replace('"', "\\\"")),
+                None => name.to_string(),
+            });
+        }
+
+        write!(f, "{}", directives.join(", "))
+    }
+}
+
+impl FromStr for CacheControl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut control = Self::new();
+        control.merge(s)?;
+        Ok(control)
+    }
+}
+
+/// Response headers which determine whether and for how long a response can be reused.
+///
+/// Collect the headers with `set_header` while reading the response head.
+///
+/// See [rfc7234 section 4.2](https://tools.ietf.org/html/rfc7234#section-4.2) for more details.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Freshness {
+    cache_control: CacheControl,
+    date: Option<HttpDate>,
+    expires: Option<HttpDate>,
+    last_modified: Option<HttpDate>,
+    age: Option<u64>,
+}
+
+impl Freshness {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores a `Cache-Control`, `Date`, `Expires`, `Last-Modified` or `Age` header. Other
+    /// headers are ignored.
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        match name.to_lowercase().as_str() {
+            "cache-control" => self.cache_control.merge(value)?,
+            "date" => self.date = HttpDate::from_str(value).ok(),
+            "expires" => self.expires = Some(HttpDate::from_str(value).unwrap_or_else(|_| HttpDate::from(SystemTime::UNIX_EPOCH))), // invalid means expired
+            "last-modified" => self.last_modified = HttpDate::from_str(value).ok(),
+            "age" => self.age = parse_delta_seconds(value.trim()),
+            _ => (),
+        };
+        Ok(())
+    }
+
+    pub fn cache_control(&self) -> &CacheControl {
+        &self.cache_control
+    }
+
+    pub fn date(&self) -> Option<HttpDate> {
+        self.date
+    }
+
+    pub fn last_modified(&self) -> Option<HttpDate> {
+        self.last_modified
+    }
+
+    /// Returns `true` if a response to a request with the `method` can be stored. A `shared`
+    /// cache must not store private responses, nor responses to requests with the
+    /// `Authorization` header unless explicitly allowed.
+    ///
+    /// Partial and not modified responses are never stored.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7234#section-3) for more details.
+    pub fn is_storable(&self, method: Method, status: Status, shared: bool, authorization: bool) -> bool {
+        let cc = &self.cache_control;
+        let uncacheable = !method.is_cacheable() || status.is_informational() || status == Status::PartialContent || status == Status::NotModified;
+        let forbidden = cc.no_store || (shared && cc.private.is_some());
+        let unauthorized = shared && authorization && !(cc.public || cc.must_revalidate || cc.s_maxage.is_some());
+        if uncacheable || forbidden || unauthorized {
+            return false;
+        }
+
+        cc.public
+            || (!shared && cc.private.is_some())
+            || self.expires.is_some()
+            || cc.max_age.is_some()
+            || (shared && cc.s_maxage.is_some())
+            || status.is_heuristically_cacheable()
+    }
+
+    /// Returns the time a response stays fresh after its generation.
+    ///
+    /// Explicit expiration (`s-maxage` for shared caches, `max-age`, `Expires`) takes priority
+    /// over the heuristic of 10% of the time elapsed since `Last-Modified`, which only applies
+    /// to heuristically cacheable status codes.
+    pub fn lifetime(&self, status: Status, shared: bool) -> Duration {
+        let cc = &self.cache_control;
+        let seconds = if let (true, Some(seconds)) = (shared, cc.s_maxage) {
+            seconds
+        } else if let Some(seconds) = cc.max_age {
+            seconds
+        } else if let Some(expires) = self.expires {
+            match self.date {
+                Some(date) => expires.timestamp().saturating_sub(date.timestamp()),
+                None => 0,
+            }
+        } else if let (true, Some(modified), Some(date)) = (status.is_heuristically_cacheable() || cc.public, self.last_modified, self.date) {
+            date.timestamp().saturating_sub(modified.timestamp()) / 10
+        } else {
+            0
+        };
+        Duration::from_secs(seconds)
+    }
+
+    /// Returns the current age of a response which was requested at `request_time` and received
+    /// at `response_time`.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7234#section-4.2.3) for more details.
+    pub fn current_age(&self, request_time: SystemTime, response_time: SystemTime, now: SystemTime) -> Duration {
+        let elapsed = |from: SystemTime, to: SystemTime| to.duration_since(from).unwrap_or_default();
+
+        let apparent_age = match self.date {
+            Some(date) => elapsed(date.into(), response_time),
+            None => Duration::from_secs(0),
+        };
+        let corrected_age = Duration::from_secs(self.age.unwrap_or(0)).saturating_add(elapsed(request_time, response_time));
+        apparent_age.max(corrected_age).saturating_add(elapsed(response_time, now))
+    }
+
+    /// Returns `true` if the response of the given `age` can be served without validation.
+    ///
+    /// The `request` directives may widen (`max-stale`) or narrow (`max-age`, `min-fresh`,
+    /// `no-cache`) the acceptable staleness. Responses with `no-cache` or `must-revalidate`
+    /// (and `proxy-revalidate` for shared caches) are never served stale.
+    pub fn is_fresh(&self, request: &CacheControl, status: Status, shared: bool, age: Duration) -> bool {
+        let cc = &self.cache_control;
+        if request.no_cache.is_some() || cc.no_cache.is_some() {
+            return false;
+        } else if let Some(max_age) = request.max_age {
+            if age > Duration::from_secs(max_age) {
+                return false;
+            }
+        }
+
+        let lifetime = self.lifetime(status, shared);
+        let min_fresh = Duration::from_secs(request.min_fresh.unwrap_or(0));
+        if lifetime > age.saturating_add(min_fresh) {
+            return true;
+        }
+
+        let revalidate = cc.must_revalidate || (shared && (cc.proxy_revalidate || cc.s_maxage.is_some()));
+        match (revalidate, request.max_stale) {
+            (true, _) | (false, None) => false,
+            (false, Some(None)) => true,
+            (false, Some(Some(max_stale))) => lifetime.saturating_add(Duration::from_secs(max_stale)) > age.saturating_add(min_fresh),
+        }
+    }
+}
+
+/// Parses delta-seconds. Values above 2^31 are clamped to it.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9111#section-1.2.2) for more details.
+fn parse_delta_seconds(value: &str) -> Option<u64> {
+    match !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        true => Some(value.parse::<u64>().unwrap_or(u64::MAX).min(1 << 31)),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn freshness(headers: &[(&str, &str)]) -> Freshness {
+        let mut freshness = Freshness::new();
+        for (name, value) in headers.iter() {
+            freshness.set_header(name, value).unwrap();
+        }
+        freshness
+    }
+
+    #[test]
+    fn implements_from_str() {
+        let cc = CacheControl::from_str("Max-Age=60, no-cache=\"Set-Cookie, X-Foo\", private, max-stale, foo=\"b,r\", bar").unwrap();
+        assert_eq!(cc.max_age, Some(60));
+        assert_eq!(cc.no_cache, Some(vec![String::from("set-cookie"), String::from("x-foo")]));
+        assert_eq!(cc.private, Some(vec![]));
+        assert_eq!(cc.max_stale, Some(None));
+        assert_eq!(cc.extension("foo"), Some(Some("b,r")));
+        assert_eq!(cc.extension("bar"), Some(None));
+        assert_eq!(CacheControl::from_str("max-age=abc").unwrap().max_age, Some(0));
+        assert!(CacheControl::from_str("max-age=\"60").is_err());
+        assert!(CacheControl::from_str("max-age 60").is_err());
+    }
+
+    #[test]
+    fn implements_to_string() {
+        let cc = CacheControl::from_str("public, max-age=60, foo=\"a b\", no-cache=\"x\"").unwrap();
+        assert_eq!(cc.to_string(), "no-cache=\"x\", public, max-age=60, foo=\"a b\"");
+    }
+
+    #[test]
+    fn checks_storable() {
+        let response = freshness(&[("Cache-Control", "private")]);
+        assert!(response.is_storable(Method::Get, Status::Ok, false, false));
+        assert!(!response.is_storable(Method::Get, Status::Ok, true, false));
+        assert!(!response.is_storable(Method::Post, Status::Ok, false, false));
+        let response = freshness(&[]);
+        assert!(response.is_storable(Method::Get, Status::NotFound, true, false));
+        assert!(!response.is_storable(Method::Get, Status::Found, true, false));
+        assert!(!response.is_storable(Method::Get, Status::Ok, true, true));
+        let response = freshness(&[("Cache-Control", "s-maxage=10")]);
+        assert!(response.is_storable(Method::Get, Status::Ok, true, true));
+        let response = freshness(&[("Cache-Control", "no-store, max-age=10")]);
+        assert!(!response.is_storable(Method::Get, Status::Ok, false, false));
+    }
+
+    #[test]
+    fn calculates_lifetime() {
+        let date = ("Date", "Sun, 06 Nov 1994 08:49:37 GMT");
+        let response = freshness(&[date, ("Cache-Control", "max-age=60, s-maxage=120"), ("Expires", "Sun, 06 Nov 1994 08:59:37 GMT")]);
+        assert_eq!(response.lifetime(Status::Ok, false), Duration::from_secs(60));
+        assert_eq!(response.lifetime(Status::Ok, true), Duration::from_secs(120));
+        let response = freshness(&[date, ("Expires", "Sun, 06 Nov 1994 08:59:37 GMT")]);
+        assert_eq!(response.lifetime(Status::Ok, false), Duration::from_secs(600));
+        let response = freshness(&[date, ("Expires", "0")]);
+        assert_eq!(response.lifetime(Status::Ok, false), Duration::from_secs(0));
+        let response = freshness(&[date, ("Last-Modified", "Sun, 06 Nov 1994 07:49:37 GMT")]);
+        assert_eq!(response.lifetime(Status::Ok, false), Duration::from_secs(360));
+        assert_eq!(response.lifetime(Status::Found, false), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn calculates_current_age() {
+        let response = freshness(&[("Date", "Thu, 01 Jan 1970 00:01:40 GMT"), ("Age", "30")]);
+        let request_time = UNIX_EPOCH + Duration::from_secs(100);
+        let response_time = UNIX_EPOCH + Duration::from_secs(110);
+        let now = UNIX_EPOCH + Duration::from_secs(200);
+        assert_eq!(response.current_age(request_time, response_time, now), Duration::from_secs(130));
+        let response = freshness(&[("Age", "18446744073709551615")]);
+        assert_eq!(response.current_age(request_time, response_time, now), Duration::from_secs((1 << 31) + 100));
+    }
+
+    #[test]
+    fn checks_freshness() {
+        let response = freshness(&[("Cache-Control", "max-age=60")]);
+        let request = CacheControl::new();
+        assert!(response.is_fresh(&request, Status::Ok, false, Duration::from_secs(59)));
+        assert!(!response.is_fresh(&request, Status::Ok, false, Duration::from_secs(60)));
+        let request = CacheControl::from_str("max-stale=10").unwrap();
+        assert!(response.is_fresh(&request, Status::Ok, false, Duration::from_secs(65)));
+        let request = CacheControl::from_str("min-fresh=20").unwrap();
+        assert!(!response.is_fresh(&request, Status::Ok, false, Duration::from_secs(45)));
+        let request = CacheControl::from_str("max-age=10").unwrap();
+        assert!(!response.is_fresh(&request, Status::Ok, false, Duration::from_secs(30)));
+        let response = freshness(&[("Cache-Control", "max-age=60, must-revalidate")]);
+        let request = CacheControl::from_str("max-stale").unwrap();
+        assert!(!response.is_fresh(&request, Status::Ok, false, Duration::from_secs(65)));
+        let response = freshness(&[("Cache-Control", "max-age=60")]);
+        let request = CacheControl::from_str("min-fresh=18446744073709551615, max-stale=99999999999").unwrap();
+        assert_eq!(request.min_fresh, Some(1 << 31));
+        assert!(!response.is_fresh(&request, Status::Ok, false, Duration::MAX));
+    }
+}
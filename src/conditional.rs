@@ -0,0 +1,301 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use std::time::SystemTime;
+use crate::{HttpDate, Method, Status};
+use crate::parse::split_list;
+
+/// Entity tag of the `ETag` header.
+///
+/// As defined by [rfc7232 section 2.3](https://tools.ietf.org/html/rfc7232#section-2.3).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntityTag {
+    weak: bool,
+    tag: String,
+}
+
+impl EntityTag {
+
+    pub fn strong(tag: &str) -> Self {
+        Self { weak: false, tag: tag.to_string() }
+    }
+
+    pub fn weak(tag: &str) -> Self {
+        Self { weak: true, tag: tag.to_string() }
+    }
+
+    /// The opaque tag without quotes.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Both tags must be strong and identical.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7232#section-2.3.2) for more details.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// The tags must be identical regardless of either or both being weak.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl Display for EntityTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.weak {
+            true => write!(f, "W/\"{}\"", self.tag),
+            false => write!(f, "\"{}\"", self.tag),
+        }
+    }
+}
+
+impl FromStr for EntityTag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (weak, quoted) = match s.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, s),
+        };
+        let valid = quoted.len() >= 2 && quoted.starts_with('"') && quoted.ends_with('"')
+            && quoted[1..quoted.len() - 1].bytes().all(|b| b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80);
+        match valid {
+            true => Ok(Self { weak, tag: quoted[1..quoted.len() - 1].to_string() }),
+            false => Err(Error::new(ErrorKind::InvalidInput, format!("The entity tag `{}` is invalid.", s))),
+        }
+    }
+}
+
+impl std::convert::TryFrom<&[u8]> for EntityTag {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(txt) => Self::from_str(&txt),
+            Err(e) => Err(Error::new(ErrorKind::InvalidInput, e.to_string())),
+        }
+    }
+}
+
+/// Value of the `If-Match` and `If-None-Match` headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntityTagList {
+
+    /// `*` matches any current representation.
+    Any,
+
+    Tags(Vec<EntityTag>),
+}
+
+impl FromStr for EntityTagList {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "*" => Ok(Self::Any),
+            s => Ok(Self::Tags(split_list(s).into_iter().map(EntityTag::from_str).collect::<Result<Vec<EntityTag>, Error>>()?)),
+        }
+    }
+}
+
+/// Value of the `If-Range` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfRange {
+    Tag(EntityTag),
+    Date(HttpDate),
+}
+
+impl FromStr for IfRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().starts_with("W/") || s.trim().starts_with('"') {
+            true => Ok(Self::Tag(EntityTag::from_str(s)?)),
+            false => Ok(Self::Date(HttpDate::from_str(s)?)),
+        }
+    }
+}
+
+/// Conditional request headers.
+///
+/// Collect the headers with `set_header` while reading the request head, then `evaluate` them
+/// against the current state of the target resource.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Conditions {
+    if_match: Option<EntityTagList>,
+    if_none_match: Option<EntityTagList>,
+    if_modified_since: Option<HttpDate>,
+    if_unmodified_since: Option<HttpDate>,
+    if_range: Option<Option<IfRange>>, // `Some(None)` for a value which cannot be parsed
+}
+
+impl Conditions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores a conditional header. Other headers are ignored.
+    ///
+    /// Invalid dates are ignored as required by the spec while invalid entity tags are
+    /// reported. An invalid `If-Range` is kept so that the `Range` header gets ignored.
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        match name.to_lowercase().as_str() {
+            "if-match" => self.if_match = Some(EntityTagList::from_str(value)?),
+            "if-none-match" => self.if_none_match = Some(EntityTagList::from_str(value)?),
+            "if-modified-since" => self.if_modified_since = HttpDate::from_str(value).ok(),
+            "if-unmodified-since" => self.if_unmodified_since = HttpDate::from_str(value).ok(),
+            "if-range" => self.if_range = Some(IfRange::from_str(value).ok()),
+            _ => (),
+        };
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Evaluates the preconditions in the order of precedence.
+    ///
+    /// The `exists` flag tells whether the target resource has a current representation with
+    /// the optional `etag` and `last_modified` validators. Returns `Status::NotModified` or
+    /// `Status::PreconditionFailed` when the method must not be performed.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7232#section-6) for more details.
+    pub fn evaluate(&self, method: Method, exists: bool, etag: Option<&EntityTag>, last_modified: Option<SystemTime>) -> Result<(), Status> {
+        let last_modified = last_modified.map(HttpDate::from);
+
+        if let Some(list) = &self.if_match {
+            let matched = match list {
+                EntityTagList::Any => exists,
+                EntityTagList::Tags(tags) => etag.map(|etag| tags.iter().any(|tag| tag.strong_eq(etag))).unwrap_or(false),
+            };
+            if !matched {
+                return Err(Status::PreconditionFailed);
+            }
+        } else if let (Some(date), Some(modified)) = (self.if_unmodified_since, last_modified) {
+            if modified > date {
+                return Err(Status::PreconditionFailed);
+            }
+        }
+
+        let is_read = method == Method::Get || method == Method::Head;
+        if let Some(list) = &self.if_none_match {
+            let matched = match list {
+                EntityTagList::Any => exists,
+                EntityTagList::Tags(tags) => etag.map(|etag| tags.iter().any(|tag| tag.weak_eq(etag))).unwrap_or(false),
+            };
+            if matched && is_read {
+                return Err(Status::NotModified);
+            } else if matched {
+                return Err(Status::PreconditionFailed);
+            }
+        } else if let (true, Some(date), Some(modified)) = (is_read, self.if_modified_since, last_modified) {
+            if modified <= date {
+                return Err(Status::NotModified);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if the `Range` header of a `GET` request should be honoured.
+    ///
+    /// An entity tag must strongly match the current `etag` and a date must exactly match the
+    /// `last_modified` time. Without the `If-Range` header ranges always apply, with an invalid
+    /// one they never do.
+    pub fn is_range_applicable(&self, etag: Option<&EntityTag>, last_modified: Option<SystemTime>) -> bool {
+        match &self.if_range {
+            None => true,
+            Some(None) => false,
+            Some(Some(IfRange::Tag(tag))) => etag.map(|etag| tag.strong_eq(etag)).unwrap_or(false),
+            Some(Some(IfRange::Date(date))) => last_modified.map(|modified| HttpDate::from(modified) == *date).unwrap_or(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn conditions(headers: &[(&str, &str)]) -> Conditions {
+        let mut conditions = Conditions::new();
+        for (name, value) in headers.iter() {
+            conditions.set_header(name, value).unwrap();
+        }
+        conditions
+    }
+
+    #[test]
+    fn implements_entity_tag() {
+        let tag = EntityTag::from_str("W/\"xyzzy\"").unwrap();
+        assert!(tag.is_weak());
+        assert_eq!(tag.tag(), "xyzzy");
+        assert_eq!(tag.to_string(), "W/\"xyzzy\"");
+        assert!(tag.weak_eq(&EntityTag::strong("xyzzy")));
+        assert!(!tag.strong_eq(&EntityTag::strong("xyzzy")));
+        assert!(EntityTag::strong("a").strong_eq(&EntityTag::strong("a")));
+        assert!(EntityTag::from_str("xyzzy").is_err());
+        assert!(EntityTag::from_str("\"a\"b\"").is_err());
+    }
+
+    #[test]
+    fn evaluates_etags() {
+        let etag = EntityTag::strong("v2");
+        let result = conditions(&[("If-None-Match", "W/\"v1\", W/\"v2\"")]).evaluate(Method::Get, true, Some(&etag), None);
+        assert_eq!(result, Err(Status::NotModified));
+        let result = conditions(&[("If-None-Match", "*")]).evaluate(Method::Put, true, Some(&etag), None);
+        assert_eq!(result, Err(Status::PreconditionFailed));
+        let result = conditions(&[("If-None-Match", "*")]).evaluate(Method::Put, false, None, None);
+        assert_eq!(result, Ok(()));
+        let result = conditions(&[("If-Match", "W/\"v2\"")]).evaluate(Method::Put, true, Some(&etag), None);
+        assert_eq!(result, Err(Status::PreconditionFailed));
+        let result = conditions(&[("If-Match", "\"v1\", \"v2\"")]).evaluate(Method::Put, true, Some(&etag), None);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn evaluates_dates() {
+        let modified = UNIX_EPOCH + Duration::from_secs(784111777);
+        let result = conditions(&[("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT")]).evaluate(Method::Get, true, None, Some(modified));
+        assert_eq!(result, Err(Status::NotModified));
+        let result = conditions(&[("If-Modified-Since", "Sun, 06 Nov 1994 08:49:36 GMT")]).evaluate(Method::Get, true, None, Some(modified));
+        assert_eq!(result, Ok(()));
+        let result = conditions(&[("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT")]).evaluate(Method::Post, true, None, Some(modified));
+        assert_eq!(result, Ok(()));
+        let result = conditions(&[("If-Unmodified-Since", "Sun, 06 Nov 1994 08:49:36 GMT")]).evaluate(Method::Delete, true, None, Some(modified));
+        assert_eq!(result, Err(Status::PreconditionFailed));
+        let result = conditions(&[("If-Unmodified-Since", "invalid")]).evaluate(Method::Delete, true, None, Some(modified));
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn evaluates_precedence() {
+        let etag = EntityTag::strong("v2");
+        let modified = UNIX_EPOCH + Duration::from_secs(784111777);
+        let headers = [("If-None-Match", "\"v1\""), ("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT")];
+        assert_eq!(conditions(&headers).evaluate(Method::Get, true, Some(&etag), Some(modified)), Ok(()));
+        let headers = [("If-Match", "\"v2\""), ("If-Unmodified-Since", "Sun, 06 Nov 1994 08:49:36 GMT")];
+        assert_eq!(conditions(&headers).evaluate(Method::Put, true, Some(&etag), Some(modified)), Ok(()));
+    }
+
+    #[test]
+    fn evaluates_if_range() {
+        let etag = EntityTag::strong("v2");
+        let modified = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert!(conditions(&[]).is_range_applicable(Some(&etag), None));
+        assert!(conditions(&[("If-Range", "\"v2\"")]).is_range_applicable(Some(&etag), None));
+        assert!(!conditions(&[("If-Range", "W/\"v2\"")]).is_range_applicable(Some(&EntityTag::weak("v2")), None));
+        assert!(conditions(&[("If-Range", "Sun, 06 Nov 1994 08:49:37 GMT")]).is_range_applicable(None, Some(modified)));
+        assert!(!conditions(&[("If-Range", "Sun, 06 Nov 1994 08:49:36 GMT")]).is_range_applicable(None, Some(modified)));
+        assert!(!conditions(&[("If-Range", "yesterday")]).is_range_applicable(Some(&etag), Some(modified)));
+    }
+}
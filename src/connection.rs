@@ -0,0 +1,113 @@
+use crate::Version;
+use crate::parse::split_list;
+
+/// How the end of a response body is determined.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7230#section-3.3.3) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Framing {
+
+    /// The response has no body (e.g. `204`, `304` or a response to `HEAD`).
+    Empty,
+
+    /// The body length is given by the `Content-Length` header.
+    Length(usize),
+
+    /// The body uses the chunked transfer coding.
+    Chunked,
+
+    /// The body ends when the server closes the connection.
+    CloseDelimited,
+}
+
+/// Decision whether a connection can be reused after an exchange.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Persistence {
+    reuse: bool,
+    request_options: Vec<String>,
+    response_options: Vec<String>,
+}
+
+impl Persistence {
+
+    /// Returns `true` if the connection can be used for another request.
+    pub fn reuse(&self) -> bool {
+        self.reuse
+    }
+
+    /// The lowercase header names listed by the request `Connection` header which a proxy
+    /// must not forward.
+    pub fn request_options(&self) -> &Vec<String> {
+        &self.request_options
+    }
+
+    /// The lowercase header names listed by the response `Connection` header which a proxy
+    /// must not forward.
+    pub fn response_options(&self) -> &Vec<String> {
+        &self.response_options
+    }
+}
+
+/// Returns the lowercase options of a `Connection` header value.
+pub fn parse_connection(value: &str) -> Vec<String> {
+    split_list(value).into_iter().map(|token| token.to_lowercase()).collect()
+}
+
+/// Decides whether to reuse or close the connection after the response is sent or received.
+///
+/// The connection is closed if either side sends `Connection: close` or the response body is
+/// close-delimited. Otherwise an exchange where both messages are HTTP/1.1 or later persists
+/// while HTTP/1.0 requires `keep-alive` on both the request and the response.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7230#section-6.3) for more details.
+pub fn decide_persistence(request: (Version, Option<&str>), response: (Version, Option<&str>), framing: Framing) -> Persistence {
+    let request_tokens = request.1.map(parse_connection).unwrap_or_default();
+    let response_tokens = response.1.map(parse_connection).unwrap_or_default();
+    let has = |tokens: &Vec<String>, token: &str| tokens.iter().any(|t| t == token);
+
+    let closed = has(&request_tokens, "close") || has(&response_tokens, "close") || framing == Framing::CloseDelimited;
+    let reuse = !closed && match request.0.min(response.0) {
+        v if v.is_persistent_by_default() => true,
+        Version::Http1_0 => has(&request_tokens, "keep-alive") && has(&response_tokens, "keep-alive"),
+        _ => false,
+    };
+
+    let options = |tokens: Vec<String>| tokens.into_iter().filter(|t| t != "close").collect();
+    Persistence {
+        reuse,
+        request_options: options(request_tokens),
+        response_options: options(response_tokens),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_connection() {
+        assert_eq!(parse_connection("Keep-Alive, X-Foo,, close"), vec!["keep-alive", "x-foo", "close"]);
+    }
+
+    #[test]
+    fn decides_persistence() {
+        let http11 = (Version::Http1_1, None);
+        let http10 = (Version::Http1_0, None);
+        assert!(decide_persistence(http11, http11, Framing::Chunked).reuse());
+        assert!(!decide_persistence(http11, http11, Framing::CloseDelimited).reuse());
+        assert!(!decide_persistence((Version::Http1_1, Some("close")), http11, Framing::Empty).reuse());
+        assert!(!decide_persistence(http11, (Version::Http1_1, Some("Close")), Framing::Length(5)).reuse());
+        assert!(!decide_persistence(http10, http11, Framing::Length(5)).reuse());
+        let keep_alive = (Version::Http1_0, Some("keep-alive"));
+        assert!(!decide_persistence(keep_alive, http11, Framing::Length(5)).reuse());
+        assert!(decide_persistence(keep_alive, (Version::Http1_1, Some("keep-alive")), Framing::Length(5)).reuse());
+        assert!(!decide_persistence((Version::Http0_9, None), http11, Framing::Empty).reuse());
+    }
+
+    #[test]
+    fn lists_options() {
+        let persistence = decide_persistence((Version::Http1_1, Some("X-Trace, close")), (Version::Http1_1, Some("keep-alive")), Framing::Empty);
+        assert_eq!(persistence.request_options(), &vec![String::from("x-trace")]);
+        assert_eq!(persistence.response_options(), &vec![String::from("keep-alive")]);
+    }
+}
@@ -0,0 +1,566 @@
+use std::fmt::{self, Display};
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use async_std::fs;
+use async_std::path::Path;
+use crate::date::days_from_civil;
+
+/// Multi-label public suffixes known to the jar by default. Single-label
+/// domains (e.g. `com`) are always treated as public suffixes.
+const PUBLIC_SUFFIXES: &[&str] = &[
+    "co.uk", "org.uk", "ac.uk", "gov.uk", "me.uk", "ltd.uk", "plc.uk",
+    "com.au", "net.au", "org.au", "edu.au", "gov.au",
+    "co.jp", "ne.jp", "or.jp", "ac.jp", "go.jp",
+    "co.nz", "org.nz", "net.nz",
+    "com.br", "net.br", "org.br",
+    "com.cn", "net.cn", "org.cn",
+    "co.in", "net.in", "org.in",
+    "co.za", "org.za",
+    "com.mx", "com.tr", "com.tw", "com.sg", "com.hk",
+    "github.io", "gitlab.io", "herokuapp.com", "appspot.com",
+    "blogspot.com", "netlify.app", "vercel.app", "pages.dev",
+];
+
+/// The maximum number of bytes accepted for a cookie name and value.
+const COOKIE_LIMIT: usize = 4096;
+
+/// Seconds since the epoch of the expiry given to cookies whose `Max-Age` reaches past what
+/// the system clock can hold (9999-12-31 23:59:59 UTC).
+const FAR_FUTURE: u64 = 253402300799;
+
+/// A cookie stored in a `CookieJar`.
+///
+/// As defined by [rfc6265 section 5.3](https://tools.ietf.org/html/rfc6265#section-5.3).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    path: String,
+    expires: Option<SystemTime>,
+    host_only: bool,
+    secure: bool,
+    http_only: bool,
+}
+
+impl Cookie {
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns `None` for session cookies.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    pub fn is_host_only(&self) -> bool {
+        self.host_only
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        match self.expires {
+            Some(expires) => expires <= now,
+            None => false,
+        }
+    }
+
+    fn matches(&self, host: &str, path: &str, secure: bool, now: SystemTime) -> bool {
+        let domain = match self.host_only {
+            true => self.domain == host,
+            false => domain_match(host, &self.domain),
+        };
+        domain && path_match(path, &self.path) && (secure || !self.secure) && !self.is_expired(now)
+    }
+}
+
+/// Client-side cookie store.
+///
+/// Cookies are ingested from `Set-Cookie` response headers and returned as a `Cookie` request
+/// header value following the storage and retrieval models of
+/// [rfc6265](https://tools.ietf.org/html/rfc6265). The jar serializes to the Netscape
+/// `cookies.txt` format.
+#[derive(Debug, Clone)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    public_suffixes: HashSet<String>,
+}
+
+impl CookieJar {
+
+    pub fn new() -> Self {
+        Self {
+            cookies: Vec::new(),
+            public_suffixes: PUBLIC_SUFFIXES.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Registers an additional public suffix (e.g. `example.co`) on which domain cookies will
+    /// be rejected.
+    pub fn add_public_suffix(&mut self, suffix: &str) {
+        self.public_suffixes.insert(suffix.trim_matches('.').to_lowercase());
+    }
+
+    pub fn is_public_suffix(&self, domain: &str) -> bool {
+        !domain.contains('.') || self.public_suffixes.contains(domain)
+    }
+
+    pub fn cookies(&self) -> &Vec<Cookie> {
+        &self.cookies
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+    }
+
+    /// Stores the cookie from a `Set-Cookie` header value received in a response to the
+    /// request `uri`.
+    pub fn set_cookie(&mut self, uri: &str, value: &str) -> Result<(), Error> {
+        self.set_cookie_at(uri, value, SystemTime::now())
+    }
+
+    pub fn set_cookie_at(&mut self, uri: &str, value: &str, now: SystemTime) -> Result<(), Error> {
+        let (scheme, host, path) = split_uri(uri)?;
+        let mut parts = value.split(';');
+
+        let pair = parts.next().unwrap_or("");
+        let (name, value) = match pair.find('=') {
+            Some(index) => (pair[..index].trim(), pair[index + 1..].trim()),
+            None => return Err(Error::new(ErrorKind::InvalidData, "The cookie has no name-value pair.")),
+        };
+        if name.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "The cookie name is empty."));
+        } else if name.len() + value.len() > COOKIE_LIMIT {
+            return Err(Error::new(ErrorKind::InvalidData, format!("The cookie exceeds the limit of {} bytes.", COOKIE_LIMIT)));
+        }
+
+        let (mut max_age, mut expires, mut domain, mut cookie_path) = (None, None, None, None);
+        let (mut secure, mut http_only) = (false, false);
+        for attr in parts {
+            let (key, val) = match attr.find('=') {
+                Some(index) => (attr[..index].trim(), attr[index + 1..].trim()),
+                None => (attr.trim(), ""),
+            };
+            match key.to_lowercase().as_str() {
+                "expires" => if let Some(time) = parse_cookie_date(val) { expires = Some(time) },
+                "max-age" => if let Ok(seconds) = val.parse::<i64>() {
+                    max_age = match seconds > 0 {
+                        true => now.checked_add(Duration::from_secs(seconds as u64))
+                            .or_else(|| UNIX_EPOCH.checked_add(Duration::from_secs(FAR_FUTURE))),
+                        false => Some(UNIX_EPOCH),
+                    };
+                },
+                "domain" if !val.is_empty() => domain = Some(val.trim_start_matches('.').to_lowercase()),
+                "path" => cookie_path = match val.starts_with('/') {
+                    true => Some(val.to_string()),
+                    false => None,
+                },
+                "secure" => secure = true,
+                "httponly" => http_only = true,
+                _ => (),
+            };
+        }
+
+        let (host_only, domain) = match domain {
+            Some(domain) if self.is_public_suffix(&domain) && domain != host => {
+                return Err(Error::new(ErrorKind::InvalidData, format!("The cookie domain `{}` is a public suffix.", domain)));
+            },
+            Some(domain) if self.is_public_suffix(&domain) => (true, host),
+            Some(domain) if !domain_match(&host, &domain) => {
+                return Err(Error::new(ErrorKind::InvalidData, format!("The cookie domain `{}` does not match the host `{}`.", domain, host)));
+            },
+            Some(domain) => (false, domain),
+            None => (true, host),
+        };
+        if secure && !is_secure_scheme(&scheme) {
+            return Err(Error::new(ErrorKind::InvalidData, "The secure cookie was received over an insecure channel."));
+        }
+
+        let cookie = Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain,
+            path: cookie_path.unwrap_or_else(|| default_path(&path)),
+            expires: max_age.or(expires),
+            host_only,
+            secure,
+            http_only,
+        };
+
+        let position = self.cookies.iter().position(|c| {
+            c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path
+        });
+        match (position, cookie.is_expired(now)) {
+            (Some(index), true) => { self.cookies.remove(index); },
+            (Some(index), false) => self.cookies[index] = cookie,
+            (None, true) => (),
+            (None, false) => self.cookies.push(cookie),
+        };
+
+        Ok(())
+    }
+
+    /// Returns the `Cookie` header value for a request to `uri` or `None` if no stored cookie
+    /// applies.
+    pub fn cookie_header(&self, uri: &str) -> Result<Option<String>, Error> {
+        self.cookie_header_at(uri, SystemTime::now())
+    }
+
+    pub fn cookie_header_at(&self, uri: &str, now: SystemTime) -> Result<Option<String>, Error> {
+        let (scheme, host, path) = split_uri(uri)?;
+        let secure = is_secure_scheme(&scheme);
+
+        let mut cookies: Vec<&Cookie> = self.cookies.iter()
+            .filter(|c| c.matches(&host, &path, secure, now))
+            .collect();
+        cookies.sort_by_key(|c| std::cmp::Reverse(c.path.len())); // stable, keeps creation order
+
+        match cookies.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(cookies.iter()
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect::<Vec<String>>()
+                .join("; "))),
+        }
+    }
+
+    /// Removes cookies which expired before `now`.
+    pub fn remove_expired(&mut self, now: SystemTime) {
+        self.cookies.retain(|c| !c.is_expired(now));
+    }
+
+    /// Writes the jar to a file in the Netscape `cookies.txt` format.
+    pub async fn save<P>(&self, path: P) -> Result<(), Error>
+        where
+        P: AsRef<Path>,
+    {
+        fs::write(path, self.to_string()).await
+    }
+
+    /// Reads a jar from a file in the Netscape `cookies.txt` format.
+    pub async fn load<P>(path: P) -> Result<Self, Error>
+        where
+        P: AsRef<Path>,
+    {
+        Self::from_str(&fs::read_to_string(path).await?)
+    }
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# Netscape HTTP Cookie File")?;
+        for cookie in self.cookies.iter() {
+            let expires = match cookie.expires {
+                Some(time) => time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0).max(1),
+                None => 0,
+            };
+            writeln!(f, "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if cookie.http_only { "#HttpOnly_" } else { "" },
+                if cookie.host_only { "" } else { "." },
+                cookie.domain,
+                if cookie.host_only { "FALSE" } else { "TRUE" },
+                cookie.path,
+                if cookie.secure { "TRUE" } else { "FALSE" },
+                expires,
+                cookie.name,
+                cookie.value,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CookieJar {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut jar = Self::new();
+
+        for line in s.lines() {
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 7 {
+                return Err(Error::new(ErrorKind::InvalidData, format!("The line `{}` is not a valid cookie file line.", line)));
+            }
+            let expires = match fields[4].parse::<u64>() {
+                Ok(0) => None,
+                Ok(seconds) => match UNIX_EPOCH.checked_add(Duration::from_secs(seconds)) {
+                    Some(time) => Some(time),
+                    None => return Err(Error::new(ErrorKind::InvalidData, format!("The expiry `{}` is out of range.", seconds))),
+                },
+                Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+            };
+            jar.cookies.push(Cookie {
+                name: fields[5].to_string(),
+                value: fields[6].to_string(),
+                domain: fields[0].trim_start_matches('.').to_lowercase(),
+                path: fields[2].to_string(),
+                expires,
+                host_only: fields[1] != "TRUE",
+                secure: fields[3] == "TRUE",
+                http_only,
+            });
+        }
+
+        Ok(jar)
+    }
+}
+
+fn is_secure_scheme(scheme: &str) -> bool {
+    scheme == "https" || scheme == "wss"
+}
+
+fn is_ip_address(host: &str) -> bool {
+    host.starts_with('[') || host.parse::<std::net::IpAddr>().is_ok()
+}
+
+/// See [the spec](https://tools.ietf.org/html/rfc6265#section-5.1.3) for more details.
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        true
+    } else if is_ip_address(host) || !host.ends_with(domain) {
+        false
+    } else {
+        host[..host.len() - domain.len()].ends_with('.')
+    }
+}
+
+/// See [the spec](https://tools.ietf.org/html/rfc6265#section-5.1.4) for more details.
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    if path == cookie_path {
+        true
+    } else if !path.starts_with(cookie_path) {
+        false
+    } else {
+        cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')
+    }
+}
+
+/// See [the spec](https://tools.ietf.org/html/rfc6265#section-5.1.4) for more details.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => String::from("/"),
+        Some(index) => path[..index].to_string(),
+    }
+}
+
+/// Splits an absolute URI into a lowercase scheme, a lowercase host without port and a path
+/// without query.
+fn split_uri(uri: &str) -> Result<(String, String, String), Error> {
+    let (scheme, rest) = match uri.find("://") {
+        Some(index) => (uri[..index].to_lowercase(), &uri[index + 3..]),
+        None => return Err(Error::new(ErrorKind::InvalidInput, format!("The URI `{}` is not absolute.", uri))),
+    };
+    let (authority, path) = match rest.find(['/', '?', '#']) {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, ""),
+    };
+    let authority = match authority.rfind('@') {
+        Some(index) => &authority[index + 1..],
+        None => authority,
+    };
+    let host = match authority.starts_with('[') {
+        true => match authority.find(']') {
+            Some(index) => &authority[..index + 1],
+            None => authority,
+        },
+        false => match authority.rfind(':') {
+            Some(index) => &authority[..index],
+            None => authority,
+        },
+    };
+    if host.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("The URI `{}` has no host.", uri)));
+    }
+    let path = match path.find(['?', '#']) {
+        Some(index) => &path[..index],
+        None => path,
+    };
+    let path = match path.starts_with('/') {
+        true => path.to_string(),
+        false => String::from("/"),
+    };
+
+    Ok((scheme, host.trim_end_matches('.').to_lowercase(), path))
+}
+
+/// Parses the date of the `Expires` attribute.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc6265#section-5.1.1) for more details.
+fn parse_cookie_date(value: &str) -> Option<SystemTime> {
+    let (mut time, mut day, mut month, mut year) = (None, None, None, None);
+
+    let is_delimiter = |c: char| {
+        c == '\t' || (' '..='/').contains(&c) || (';'..='@').contains(&c)
+            || ('['..='`').contains(&c) || ('{'..='~').contains(&c)
+    };
+    for token in value.split(is_delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none() {
+            if let Some(t) = parse_cookie_time(token) {
+                time = Some(t);
+                continue;
+            }
+        }
+        let digits = token.chars().take_while(|c| c.is_ascii_digit()).count();
+        if day.is_none() && (1..=2).contains(&digits) {
+            day = token[..digits].parse::<u32>().ok();
+            continue;
+        }
+        if let Some(name) = token.get(..3).filter(|_| month.is_none()) {
+            let months = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+            if let Some(index) = months.iter().position(|m| m.eq_ignore_ascii_case(name)) {
+                month = Some(index as u32 + 1);
+                continue;
+            }
+        }
+        if year.is_none() && (2..=4).contains(&digits) {
+            year = token[..digits].parse::<i64>().ok();
+            continue;
+        }
+    }
+
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    if (70..=99).contains(&year) {
+        year += 1900;
+    } else if (0..=69).contains(&year) {
+        year += 2000;
+    }
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let seconds = days_from_civil(year, month, day) * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    match seconds >= 0 {
+        true => UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64)),
+        false => Some(UNIX_EPOCH),
+    }
+}
+
+fn parse_cookie_time(token: &str) -> Option<(u32, u32, u32)> {
+    let parts: Vec<&str> = token.splitn(3, ':').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let mut values = [0u32; 3];
+    for (i, part) in parts.iter().enumerate() {
+        let digits = part.chars().take_while(|c| c.is_ascii_digit()).count();
+        if !(1..=2).contains(&digits) || (i < 2 && digits != part.len()) {
+            return None;
+        }
+        values[i] = part[..digits].parse().ok()?;
+    }
+    Some((values[0], values[1], values[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cookie_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(1445412480);
+        assert_eq!(parse_cookie_date("Wed, 21 Oct 2015 07:28:00 GMT"), Some(time));
+        assert_eq!(parse_cookie_date("Wednesday, 21-Oct-15 07:28:00 GMT"), Some(time));
+        assert_eq!(parse_cookie_date("Wed Oct 21 07:28:00 2015"), Some(time));
+        assert_eq!(parse_cookie_date("Wed, 32 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_cookie_date("xx\u{e9} 12:00:00 1 jan 2020"), Some(UNIX_EPOCH + Duration::from_secs(1577880000)));
+        assert_eq!(parse_cookie_date("12:00:00 1 \u{e9}xx 2020"), None);
+    }
+
+    #[test]
+    fn matches_domain_and_path() {
+        assert!(domain_match("www.example.com", "example.com"));
+        assert!(!domain_match("wwwexample.com", "example.com"));
+        assert!(!domain_match("127.0.0.1", "0.0.1"));
+        assert!(path_match("/docs/web", "/docs"));
+        assert!(path_match("/docs/", "/docs/"));
+        assert!(!path_match("/docsweb", "/docs"));
+        assert_eq!(default_path("/docs/web/index.html"), "/docs/web");
+        assert_eq!(default_path("/index.html"), "/");
+    }
+
+    #[test]
+    fn stores_and_retrieves_cookies() {
+        let now = UNIX_EPOCH + Duration::from_secs(1445412480);
+        let mut jar = CookieJar::new();
+        jar.set_cookie_at("https://www.example.com/docs/index.html", "a=1", now).unwrap();
+        jar.set_cookie_at("https://www.example.com/", "b=2; Path=/docs/web; Secure", now).unwrap();
+        jar.set_cookie_at("https://www.example.com/", "c=3; Domain=.example.com; Max-Age=60", now).unwrap();
+        assert!(jar.set_cookie_at("https://www.example.com/", "d=4; Domain=com", now).is_err());
+        assert!(jar.set_cookie_at("https://www.example.com/", "d=4; Domain=example.co.uk", now).is_err());
+        assert!(jar.set_cookie_at("http://www.example.com/", "d=4; Secure", now).is_err());
+        assert_eq!(jar.len(), 3);
+
+        let header = jar.cookie_header_at("https://www.example.com/docs/web/x?y=z", now).unwrap();
+        assert_eq!(header, Some(String::from("b=2; a=1; c=3")));
+        let header = jar.cookie_header_at("http://api.example.com/docs/web", now).unwrap();
+        assert_eq!(header, Some(String::from("c=3")));
+        let header = jar.cookie_header_at("http://api.example.com/", now + Duration::from_secs(60)).unwrap();
+        assert_eq!(header, None);
+
+        jar.set_cookie_at("https://www.example.com/", "c=0; Domain=example.com; Max-Age=0", now).unwrap();
+        assert_eq!(jar.len(), 2);
+
+        jar.set_cookie_at("https://www.example.com/", "e=5; Max-Age=9223372036854775807", now).unwrap();
+        let header = jar.cookie_header_at("https://www.example.com/", now + Duration::from_secs(1 << 32)).unwrap();
+        assert_eq!(header, Some(String::from("e=5")));
+    }
+
+    #[test]
+    fn implements_from_str_and_to_string() {
+        let now = UNIX_EPOCH + Duration::from_secs(1445412480);
+        let mut jar = CookieJar::new();
+        jar.set_cookie_at("https://example.com/", "a=1; HttpOnly", now).unwrap();
+        jar.set_cookie_at("https://example.com/", "b=2; Domain=example.com; Expires=Wed, 21 Oct 2015 08:28:00 GMT", now).unwrap();
+        let txt = jar.to_string();
+        assert_eq!(txt, "# Netscape HTTP Cookie File\n#HttpOnly_example.com\tFALSE\t/\tFALSE\t0\ta\t1\n.example.com\tTRUE\t/\tFALSE\t1445416080\tb\t2\n");
+        let loaded = CookieJar::from_str(&txt).unwrap();
+        assert_eq!(loaded.cookies(), jar.cookies());
+        let overflow = CookieJar::from_str("example.com\tFALSE\t/\tFALSE\t18446744073709551615\ta\t1\n");
+        assert_eq!(overflow.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
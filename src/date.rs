@@ -0,0 +1,314 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const LONG_WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// HTTP-date value with a precision of one second.
+///
+/// As defined by [rfc7231 section 7.1.1.1](https://tools.ietf.org/html/rfc7231#section-7.1.1.1).
+/// Parses IMF-fixdate and the obsolete RFC 850 and asctime formats, and always formats to
+/// IMF-fixdate (e.g. `Sun, 06 Nov 1994 08:49:37 GMT`).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct HttpDate {
+    secs: u64, // since UNIX epoch
+}
+
+impl HttpDate {
+
+    pub fn now() -> Self {
+        Self::from(SystemTime::now())
+    }
+
+    /// Seconds since UNIX epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.secs
+    }
+
+    fn from_parts(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32, weekday: Option<usize>) -> Result<Self, Error> {
+        if !(1970..=9999).contains(&year) || !(1..=days_in_month(year, month)).contains(&day)
+            || hour > 23 || minute > 59 || second > 60 {
+            return Err(Error::new(ErrorKind::InvalidInput, "The date is out of range."));
+        }
+
+        let days = days_from_civil(year, month, day);
+        if weekday.is_some() && weekday != Some(weekday_from_days(days)) {
+            return Err(Error::new(ErrorKind::InvalidInput, "The date has a mismatching day name."));
+        }
+        let secs = days as u64 * 86400 + (hour * 3600 + minute * 60 + second.min(59)) as u64;
+
+        Ok(Self { secs })
+    }
+
+    /// Parses `Sun, 06 Nov 1994 08:49:37 GMT`.
+    fn parse_imf_fixdate(s: &str) -> Option<Result<Self, Error>> {
+        let b = s.as_bytes();
+        if b.len() != 29 || &s[3..5] != ", " || &s[25..] != " GMT" || b[7] != b' ' || b[11] != b' ' || b[16] != b' ' {
+            return None;
+        }
+        let weekday = WEEKDAYS.iter().position(|d| *d == &s[0..3])?;
+        let day = parse_digits(&s[5..7])?;
+        let month = parse_month(&s[8..11])?;
+        let year = parse_digits(&s[12..16])? as i64;
+        let (hour, minute, second) = parse_time(&s[17..25])?;
+        Some(Self::from_parts(year, month, day, hour, minute, second, Some(weekday)))
+    }
+
+    /// Parses `Sunday, 06-Nov-94 08:49:37 GMT`.
+    fn parse_rfc850(s: &str) -> Option<Result<Self, Error>> {
+        let index = s.find(", ")?;
+        let weekday = LONG_WEEKDAYS.iter().position(|d| *d == &s[..index])?;
+        let s = &s[index + 2..];
+        let b = s.as_bytes();
+        if b.len() != 22 || b[2] != b'-' || b[6] != b'-' || b[9] != b' ' || &s[18..] != " GMT" {
+            return None;
+        }
+        let day = parse_digits(&s[0..2])?;
+        let month = parse_month(&s[3..6])?;
+        let year = parse_digits(&s[7..9])? as i64;
+        let current = civil_from_days((HttpDate::now().secs / 86400) as i64).0;
+        let year = match current - current % 100 + year {
+            year if year > current + 50 => year - 100, // no more than 50 years in the future
+            year => year,
+        };
+        let (hour, minute, second) = parse_time(&s[10..18])?;
+        Some(Self::from_parts(year, month, day, hour, minute, second, Some(weekday)))
+    }
+
+    /// Parses `Sun Nov  6 08:49:37 1994`.
+    fn parse_asctime(s: &str) -> Option<Result<Self, Error>> {
+        let b = s.as_bytes();
+        if b.len() != 24 || b[3] != b' ' || b[7] != b' ' || b[10] != b' ' || b[19] != b' ' {
+            return None;
+        }
+        let weekday = WEEKDAYS.iter().position(|d| *d == &s[0..3])?;
+        let month = parse_month(&s[4..7])?;
+        let day = parse_digits(s[8..10].trim_start())?;
+        let (hour, minute, second) = parse_time(&s[11..19])?;
+        let year = parse_digits(&s[20..24])? as i64;
+        Some(Self::from_parts(year, month, day, hour, minute, second, Some(weekday)))
+    }
+}
+
+impl Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = (self.secs / 86400) as i64;
+        let secs = self.secs % 86400;
+        let (year, month, day) = civil_from_days(days);
+        write!(f, "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[weekday_from_days(days)],
+            day,
+            MONTHS[month as usize - 1],
+            year,
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+        )
+    }
+}
+
+impl FromStr for HttpDate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if !s.is_ascii() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("The date `{}` is invalid.", s)));
+        }
+        Self::parse_imf_fixdate(s)
+            .or_else(|| Self::parse_rfc850(s))
+            .or_else(|| Self::parse_asctime(s))
+            .unwrap_or_else(|| Err(Error::new(ErrorKind::InvalidInput, format!("The date `{}` is invalid.", s))))
+    }
+}
+
+impl std::convert::TryFrom<&[u8]> for HttpDate {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(txt) => Self::from_str(&txt),
+            Err(e) => Err(Error::new(ErrorKind::InvalidInput, e.to_string())),
+        }
+    }
+}
+
+impl From<SystemTime> for HttpDate {
+    fn from(time: SystemTime) -> Self {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs(),
+            Err(_) => 0,
+        };
+        Self { secs }
+    }
+}
+
+impl From<HttpDate> for SystemTime {
+    fn from(date: HttpDate) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(date.secs)
+    }
+}
+
+/// Generator of the `Date` header value which formats the date at most once per second.
+///
+/// Servers can share a single instance between connections.
+#[derive(Debug)]
+pub struct DateCache {
+    cached: Mutex<(u64, String)>,
+}
+
+impl DateCache {
+
+    pub const fn new() -> Self {
+        Self {
+            cached: Mutex::new((u64::MAX, String::new())),
+        }
+    }
+
+    /// Returns the current date formatted as IMF-fixdate.
+    pub fn value(&self) -> String {
+        self.value_at(SystemTime::now())
+    }
+
+    pub fn value_at(&self, time: SystemTime) -> String {
+        let date = HttpDate::from(time);
+        let mut cached = match self.cached.lock() {
+            Ok(cached) => cached,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if cached.0 != date.secs {
+            *cached = (date.secs, date.to_string());
+        }
+        cached.1.clone()
+    }
+}
+
+impl Default for DateCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the current `Date` header value from a process-wide `DateCache`.
+pub fn date_header() -> String {
+    static CACHE: DateCache = DateCache::new();
+    CACHE.value()
+}
+
+fn parse_digits(s: &str) -> Option<u32> {
+    match !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().ok(),
+        false => None,
+    }
+}
+
+fn parse_month(s: &str) -> Option<u32> {
+    MONTHS.iter().position(|m| *m == s).map(|m| m as u32 + 1)
+}
+
+fn parse_time(s: &str) -> Option<(u32, u32, u32)> {
+    let b = s.as_bytes();
+    if b.len() != 8 || b[2] != b':' || b[5] != b':' {
+        return None;
+    }
+    Some((parse_digits(&s[0..2])?, parse_digits(&s[3..5])?, parse_digits(&s[6..8])?))
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Returns the index into `WEEKDAYS` for a number of days since 1970-01-01 (a Thursday).
+fn weekday_from_days(days: i64) -> usize {
+    (days + 3).rem_euclid(7) as usize
+}
+
+/// Returns the number of days since 1970-01-01 for a proleptic Gregorian date.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Returns the proleptic Gregorian date for a number of days since 1970-01-01.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn implements_from_str() {
+        let date = HttpDate::from_str("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(date.timestamp(), 784111777);
+        assert_eq!(HttpDate::from_str("Sunday, 06-Nov-94 08:49:37 GMT").unwrap(), date);
+        assert_eq!(HttpDate::from_str("Sun Nov  6 08:49:37 1994").unwrap(), date);
+        assert!(HttpDate::from_str("Mon, 06 Nov 1994 08:49:37 GMT").is_err());
+        assert!(HttpDate::from_str("Sun, 31 Nov 1994 08:49:37 GMT").is_err());
+        assert!(HttpDate::from_str("Sun, 06 Nov 1994 08:49:37").is_err());
+        assert!(HttpDate::from_str("Sun, 06 Nov 1994 08:49:3ñGMT").is_err());
+        assert!(HttpDate::from_str("Sunday, 06-Nov-94 08:49:3ñGMT").is_err());
+        assert!(HttpDate::from_str("Sun Nov  6 08:49:37 199ñ").is_err());
+        assert_eq!(HttpDate::from_str("Wednesday, 06-Nov-75 08:49:37 GMT").unwrap().timestamp(), 3340255777);
+    }
+
+    #[test]
+    fn implements_try_from() {
+        let date = HttpDate::try_from("Thu, 01 Jan 1970 00:00:00 GMT".as_bytes()).unwrap();
+        assert_eq!(date.timestamp(), 0);
+    }
+
+    #[test]
+    fn implements_to_string() {
+        let date = HttpDate::from(UNIX_EPOCH + Duration::from_secs(784111777));
+        assert_eq!(date.to_string(), "Sun, 06 Nov 1994 08:49:37 GMT");
+        let date = HttpDate::from(UNIX_EPOCH + Duration::from_secs(951782400));
+        assert_eq!(date.to_string(), "Tue, 29 Feb 2000 00:00:00 GMT");
+    }
+
+    #[test]
+    fn implements_system_time_conversion() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(SystemTime::from(HttpDate::from(time)), time);
+    }
+
+    #[test]
+    fn caches_date_header() {
+        let cache = DateCache::new();
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(cache.value_at(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(cache.value_at(time + Duration::from_millis(500)), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(cache.value_at(time + Duration::from_secs(1)), "Sun, 06 Nov 1994 08:49:38 GMT");
+        assert_eq!(date_header().len(), 29);
+    }
+}
@@ -0,0 +1,244 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+use crate::Status;
+
+/// Field of `Limits` which a reading, writing or relaying operation hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    FirstLine,
+    HeaderLine,
+    Headers,
+    Head,
+    Body,
+    Chunk,
+    Trailers,
+    Extension,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FirstLine => write!(f, "the HTTP first line"),
+            Self::HeaderLine => write!(f, "the HTTP header line"),
+            Self::Headers => write!(f, "the HTTP header fields"),
+            Self::Head => write!(f, "the HTTP header section"),
+            Self::Body => write!(f, "the HTTP body"),
+            Self::Chunk => write!(f, "the HTTP body chunk"),
+            Self::Trailers => write!(f, "the HTTP trailer section"),
+            Self::Extension => write!(f, "the HTTP chunk extensions"),
+        }
+    }
+}
+
+/// Field of `Timeouts` which expired while reading or writing through a `TimedStream`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Timeout {
+    Head,
+    Idle,
+    Body,
+    Rate,
+}
+
+impl Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Head => write!(f, "the HTTP head read timeout"),
+            Self::Idle => write!(f, "the idle timeout"),
+            Self::Body => write!(f, "the HTTP body time budget"),
+            Self::Rate => write!(f, "the minimum transfer rate"),
+        }
+    }
+}
+
+/// Failure of reading, writing or relaying an HTTP/1.x message.
+///
+/// The `offset` counts the bytes the failed operation consumed before the error was detected.
+/// Timeouts instead count the bytes read through the `TimedStream` in its current phase.
+/// Returned inside an `std::io::Error` from where it can be recovered with
+/// `HttpError::from_io`.
+#[derive(Debug)]
+pub enum HttpError {
+    Io {
+        error: Error,
+        offset: usize,
+    },
+    LimitExceeded {
+        limit: Limit,
+        max: usize,
+        offset: usize,
+    },
+    MalformedFirstLine {
+        offset: usize,
+    },
+    MalformedHeader {
+        offset: usize,
+    },
+    MalformedChunk {
+        offset: usize,
+    },
+    UnsupportedVersion {
+        version: String,
+        offset: usize,
+    },
+    UnexpectedEof {
+        offset: usize,
+    },
+    /// A timeout of a server's `TimedStream` expired while waiting for the client.
+    TimedOut {
+        timeout: Timeout,
+        offset: usize,
+    },
+    /// A timeout of a client's `TimedStream` expired while waiting for the server.
+    ClientTimedOut {
+        timeout: Timeout,
+        offset: usize,
+    },
+}
+
+impl HttpError {
+
+    /// Returns the number of bytes consumed before the error was detected.
+    pub fn offset(&self) -> usize {
+        match self {
+            Self::Io { offset, .. }
+            | Self::LimitExceeded { offset, .. }
+            | Self::MalformedFirstLine { offset }
+            | Self::MalformedHeader { offset }
+            | Self::MalformedChunk { offset }
+            | Self::UnsupportedVersion { offset, .. }
+            | Self::UnexpectedEof { offset }
+            | Self::TimedOut { offset, .. }
+            | Self::ClientTimedOut { offset, .. } => *offset,
+        }
+    }
+
+    /// Returns the status a server should reply with before closing the connection. A gateway
+    /// whose request to the upstream server failed with `ClientTimedOut` replies with
+    /// `Status::GatewayTimeout`.
+    pub fn status(&self) -> Status {
+        match self {
+            Self::TimedOut { .. } => Status::RequestTimeout,
+            Self::ClientTimedOut { .. } => Status::GatewayTimeout,
+            Self::Io { error, .. } if error.kind() == ErrorKind::TimedOut => Status::RequestTimeout,
+            Self::LimitExceeded { limit: Limit::FirstLine, .. } => Status::UriTooLong,
+            Self::LimitExceeded { limit: Limit::HeaderLine, .. }
+            | Self::LimitExceeded { limit: Limit::Headers, .. }
+            | Self::LimitExceeded { limit: Limit::Head, .. } => Status::RequestHeaderFieldsTooLarge,
+            Self::LimitExceeded { .. } => Status::PayloadTooLarge,
+            Self::UnsupportedVersion { .. } => Status::HttpVersionNotSupported,
+            _ => Status::BadRequest,
+        }
+    }
+
+    /// Returns the HTTP error wrapped by an I/O error.
+    pub fn from_io(error: &Error) -> Option<&HttpError> {
+        error.get_ref().and_then(|e| e.downcast_ref::<HttpError>())
+    }
+
+    fn offset_mut(&mut self) -> &mut usize {
+        match self {
+            Self::Io { offset, .. }
+            | Self::LimitExceeded { offset, .. }
+            | Self::MalformedFirstLine { offset }
+            | Self::MalformedHeader { offset }
+            | Self::MalformedChunk { offset }
+            | Self::UnsupportedVersion { offset, .. }
+            | Self::UnexpectedEof { offset }
+            | Self::TimedOut { offset, .. }
+            | Self::ClientTimedOut { offset, .. } => offset,
+        }
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { error, .. } => write!(f, "{}", error),
+            Self::LimitExceeded { limit: Limit::Headers, max, .. } => write!(f, "The operation hit the limit of {} fields for {}.", max, Limit::Headers),
+            Self::LimitExceeded { limit, max, .. } => write!(f, "The operation hit the limit of {} bytes for {}.", max, limit),
+            Self::MalformedFirstLine { .. } => write!(f, "The data is not a valid HTTP first line."),
+            Self::MalformedHeader { .. } => write!(f, "The data is not a valid HTTP header line."),
+            Self::MalformedChunk { .. } => write!(f, "The data is not a valid HTTP body chunk."),
+            Self::UnsupportedVersion { version, .. } => write!(f, "The version `{}` is not supported.", version),
+            Self::UnexpectedEof { offset } => write!(f, "The input ended unexpectedly after {} bytes.", offset),
+            Self::TimedOut { timeout, .. } => write!(f, "The operation hit {}.", timeout),
+            Self::ClientTimedOut { timeout, .. } => write!(f, "The operation hit {} while waiting for the server.", timeout),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps the kind of wrapped I/O errors and uses `UnexpectedEof`, `TimedOut` or `InvalidData`
+/// otherwise.
+impl From<HttpError> for Error {
+    fn from(error: HttpError) -> Self {
+        let kind = match &error {
+            HttpError::Io { error, .. } => error.kind(),
+            HttpError::UnexpectedEof { .. } => ErrorKind::UnexpectedEof,
+            HttpError::TimedOut { .. } | HttpError::ClientTimedOut { .. } => ErrorKind::TimedOut,
+            _ => ErrorKind::InvalidData,
+        };
+        Error::new(kind, error)
+    }
+}
+
+/// Moves the offset of an error from a nested operation which started `offset` bytes into
+/// the current one. Plain I/O errors are wrapped and timeouts keep their phase offset.
+pub(crate) fn offset_error(error: Error, offset: usize) -> Error {
+    match HttpError::from_io(&error) {
+        None => return HttpError::Io { error, offset }.into(),
+        Some(HttpError::TimedOut { .. }) | Some(HttpError::ClientTimedOut { .. }) => return error,
+        Some(_) => (),
+    };
+
+    let kind = error.kind();
+    match error.into_inner().map(|inner| inner.downcast::<HttpError>()) {
+        Some(Ok(mut inner)) => {
+            *inner.offset_mut() += offset;
+            Error::new(kind, *inner)
+        },
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_status() {
+        let limit = |limit| HttpError::LimitExceeded { limit, max: 1, offset: 2 };
+        assert_eq!(limit(Limit::FirstLine).status(), Status::UriTooLong);
+        assert_eq!(limit(Limit::HeaderLine).status(), Status::RequestHeaderFieldsTooLarge);
+        assert_eq!(limit(Limit::Headers).status(), Status::RequestHeaderFieldsTooLarge);
+        assert_eq!(limit(Limit::Body).status(), Status::PayloadTooLarge);
+        assert_eq!(HttpError::UnsupportedVersion { version: "HTTP/4".to_string(), offset: 0 }.status(), Status::HttpVersionNotSupported);
+        assert_eq!(HttpError::MalformedHeader { offset: 3 }.status(), Status::BadRequest);
+        assert_eq!(HttpError::Io { error: ErrorKind::TimedOut.into(), offset: 0 }.status(), Status::RequestTimeout);
+        assert_eq!(HttpError::TimedOut { timeout: Timeout::Idle, offset: 0 }.status(), Status::RequestTimeout);
+        assert_eq!(HttpError::ClientTimedOut { timeout: Timeout::Head, offset: 0 }.status(), Status::GatewayTimeout);
+    }
+
+    #[test]
+    fn converts_into_io_error() {
+        let error: Error = HttpError::LimitExceeded { limit: Limit::HeaderLine, max: 8, offset: 9 }.into();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "The operation hit the limit of 8 bytes for the HTTP header line.");
+        let error = offset_error(error, 10);
+        assert_eq!(HttpError::from_io(&error).unwrap().offset(), 19);
+
+        let error = offset_error(Error::new(ErrorKind::ConnectionReset, "reset"), 4);
+        assert_eq!(error.kind(), ErrorKind::ConnectionReset);
+        assert!(matches!(HttpError::from_io(&error), Some(HttpError::Io { offset: 4, .. })));
+        let error: Error = HttpError::UnexpectedEof { offset: 5 }.into();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,236 @@
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+use async_std::future;
+use async_std::prelude::*;
+use async_std::io::{Read, Write};
+use crate::{read_first_line, read_headers, write_exact, write_slice, flush_write,
+    parse_status, parse_version, Limits, Status, Version};
+
+/// Answer awaited by a client after sending a request head with `Expect: 100-continue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Continuation {
+
+    /// The server sent `100 Continue`.
+    Continue,
+
+    /// The server did not answer in time. The client should send the body anyway.
+    Timeout,
+
+    /// The server sent a final response without reading the body. Only the first line has been
+    /// read, the headers follow.
+    Final(Version, Status),
+}
+
+/// Checks the `Expect` header of a request before its body is read.
+///
+/// Returns `Ok(true)` when `100 Continue` must be written before reading the body. Requests
+/// announcing a body larger than the `body` limit are rejected with `Status::PayloadTooLarge` and
+/// unknown expectations with `Status::ExpectationFailed`. Expectations of HTTP/1.0 requests
+/// are ignored.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7231#section-5.1.1) for more details.
+pub fn check_expectation(version: Version, expect: Option<&str>, length: Option<usize>, limits: &Limits) -> Result<bool, Status> {
+    if let (Some(length), Some(limit)) = (length, limits.body) {
+        if length > limit {
+            return Err(Status::PayloadTooLarge);
+        }
+    }
+
+    match expect.map(|e| e.trim()) {
+        _ if version < Version::Http1_1 => Ok(false),
+        None => Ok(false),
+        Some(e) if e.eq_ignore_ascii_case("100-continue") => Ok(true),
+        Some(_) => Err(Status::ExpectationFailed),
+    }
+}
+
+/// Writes the `100 Continue` interim response. Nothing is written for versions other than
+/// HTTP/1.1; HTTP/1.0 clients do not expect it and HTTP/2 sends it in a HEADERS frame.
+pub async fn write_continue<O>(output: &mut O, version: Version) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    if version != Version::Http1_1 {
+        return Ok(0);
+    }
+
+    let size = write_slice(output, format!("{} {} {}\r\n\r\n", version, Status::Continue, Status::Continue.reason()).as_bytes()).await?;
+    flush_write(output).await?;
+
+    Ok(size)
+}
+
+/// Waits up to `timeout` for the server to answer a request head sent with
+/// `Expect: 100-continue`.
+///
+/// Other informational responses (e.g. `103 Early Hints`) are skipped. The head `limits` apply
+/// to each response read. The `timeout` covers whole response heads; one which started to
+/// arrive but is incomplete when it expires fails with `ErrorKind::TimedOut`.
+pub async fn read_continue<I>(input: &mut I, timeout: Duration, limits: &Limits) -> Result<Continuation, Error>
+    where
+    I: Read + Unpin,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut started = false;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match future::timeout(remaining, read_interim(input, limits, &mut started)).await {
+            Ok(result) => if let Some(continuation) = result? {
+                return Ok(continuation);
+            },
+            Err(_) if !started => return Ok(Continuation::Timeout),
+            Err(_) => return Err(Error::new(ErrorKind::TimedOut, "The server did not finish the interim response in time.")),
+        };
+    }
+}
+
+/// Reads a response head for `read_continue` and returns `None` if it must be skipped. The
+/// `started` flag is set once its first byte arrived.
+async fn read_interim<I>(input: &mut I, limits: &Limits, started: &mut bool) -> Result<Option<Continuation>, Error>
+    where
+    I: Read + Unpin,
+{
+    let mut first = [0u8];
+    if input.read(&mut first).await? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "The connection was closed while waiting for 100 Continue."));
+    }
+    *started = true;
+
+    let (mut version, mut status, mut reason) = (vec![], vec![], vec![]);
+    let mut line = (&first[..]).chain(&mut *input);
+    read_first_line(&mut line, (&mut version, &mut status, &mut reason), limits).await?;
+    let version = parse_version(version)?;
+    let status = parse_status(status)?;
+
+    if !status.is_informational() {
+        return Ok(Some(Continuation::Final(version, status)));
+    }
+    read_headers(input, &mut Vec::new(), limits).await?;
+    match status == Status::Continue {
+        true => Ok(Some(Continuation::Continue)),
+        false => Ok(None),
+    }
+}
+
+/// Sends a request `head` which includes `Expect: 100-continue`, waits for the server and
+/// streams `length` bytes of the body unless the server answered with a final status.
+///
+/// Returns the final response when the body was not sent. After a timeout the body is sent
+/// and a late `100 Continue` must be skipped while reading the response. The head `limits`
+/// apply to the responses read while waiting.
+pub async fn write_with_continue<S, I>(stream: &mut S, head: &[u8], body: &mut I, length: usize, timeout: Duration, limits: &Limits) -> Result<Option<(Version, Status)>, Error>
+    where
+    S: Read + Write + Unpin,
+    I: Read + Unpin,
+{
+    write_slice(stream, head).await?;
+    flush_write(stream).await?;
+
+    match read_continue(stream, timeout, limits).await? {
+        Continuation::Final(version, status) => Ok(Some((version, status))),
+        Continuation::Continue | Continuation::Timeout => {
+            write_exact(stream, body, length).await?;
+            flush_write(stream).await?;
+            Ok(None)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Stream answering reads from a fixed response and collecting writes.
+    struct Duplex {
+        input: Vec<u8>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize, Error>> {
+            if self.input.is_empty() {
+                return Poll::Pending; // the server is silent
+            }
+            let size = buf.len().min(self.input.len());
+            buf[..size].copy_from_slice(&self.input[..size]);
+            self.input.drain(..size);
+            Poll::Ready(Ok(size))
+        }
+    }
+
+    impl Write for Duplex {
+        fn poll_write(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+            self.output.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn checks_expectation() {
+        let limits = Limits::new().with_body(Some(100));
+        assert_eq!(check_expectation(Version::Http1_1, Some("100-Continue"), Some(10), &limits), Ok(true));
+        assert_eq!(check_expectation(Version::Http1_1, None, Some(10), &limits), Ok(false));
+        assert_eq!(check_expectation(Version::Http1_0, Some("100-continue"), Some(10), &Limits::new()), Ok(false));
+        assert_eq!(check_expectation(Version::Http1_1, Some("100-continue"), Some(101), &limits), Err(Status::PayloadTooLarge));
+        assert_eq!(check_expectation(Version::Http1_1, Some("200-ok"), None, &Limits::new()), Err(Status::ExpectationFailed));
+    }
+
+    #[async_std::test]
+    async fn writes_continue() {
+        let mut output = Vec::new();
+        write_continue(&mut output, Version::Http1_1).await.unwrap();
+        assert_eq!(output, b"HTTP/1.1 100 Continue\r\n\r\n");
+        let mut output = Vec::new();
+        assert_eq!(write_continue(&mut output, Version::Http1_0).await.unwrap(), 0);
+        assert_eq!(write_continue(&mut output, Version::Http2_0).await.unwrap(), 0);
+        assert!(output.is_empty());
+    }
+
+    #[async_std::test]
+    async fn reads_continue() {
+        let mut input = "HTTP/1.1 103 Early Hints\r\nLink: </a.css>\r\n\r\nHTTP/1.1 100 Continue\r\n\r\n".as_bytes();
+        let continuation = read_continue(&mut input, Duration::from_secs(1), &Limits::default()).await.unwrap();
+        assert_eq!(continuation, Continuation::Continue);
+        let mut input = "HTTP/1.1 413 Payload Too Large\r\n\r\n".as_bytes();
+        let continuation = read_continue(&mut input, Duration::from_secs(1), &Limits::default()).await.unwrap();
+        assert_eq!(continuation, Continuation::Final(Version::Http1_1, Status::PayloadTooLarge));
+
+        let mut stream = Duplex { input: b"HTTP/1.1 100 Cont".to_vec(), output: vec![] }; // trickling server
+        let error = read_continue(&mut stream, Duration::from_millis(10), &Limits::default()).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        let mut stream = Duplex { input: b"HTTP/1.1 103 Early Hints\r\n\r\n".to_vec(), output: vec![] };
+        let continuation = read_continue(&mut stream, Duration::from_millis(10), &Limits::default()).await.unwrap();
+        assert_eq!(continuation, Continuation::Timeout);
+    }
+
+    #[async_std::test]
+    async fn writes_with_continue() {
+        let head = b"PUT / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n";
+        let mut stream = Duplex { input: b"HTTP/1.1 100 Continue\r\n\r\n".to_vec(), output: vec![] };
+        let rejected = write_with_continue(&mut stream, head, &mut "hello".as_bytes(), 5, Duration::from_secs(1), &Limits::default()).await.unwrap();
+        assert_eq!(rejected, None);
+        assert!(stream.output.ends_with(b"\r\n\r\nhello"));
+
+        let mut stream = Duplex { input: vec![], output: vec![] };
+        let rejected = write_with_continue(&mut stream, head, &mut "hello".as_bytes(), 5, Duration::from_millis(10), &Limits::default()).await.unwrap();
+        assert_eq!(rejected, None);
+        assert!(stream.output.ends_with(b"hello"));
+
+        let mut stream = Duplex { input: b"HTTP/1.1 417 Expectation Failed\r\n\r\n".to_vec(), output: vec![] };
+        let rejected = write_with_continue(&mut stream, head, &mut "hello".as_bytes(), 5, Duration::from_secs(1), &Limits::default()).await.unwrap();
+        assert_eq!(rejected, Some((Version::Http1_1, Status::ExpectationFailed)));
+        assert_eq!(stream.output, head.to_vec());
+
+        let mut stream = Duplex { input: b"HTTP/1.1 100 Continue\r\n\r\n".to_vec(), output: vec![] };
+        let limits = Limits::new().with_first_line(Some(8));
+        assert!(write_with_continue(&mut stream, head, &mut "hello".as_bytes(), 5, Duration::from_secs(1), &limits).await.is_err());
+    }
+}
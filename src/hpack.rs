@@ -0,0 +1,555 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use crate::huffman;
+
+/// Default size of the dynamic table in bytes until the peer announces another one.
+pub const HPACK_TABLE_SIZE: usize = 4096;
+
+/// Static table entries, indexed from 1.
+///
+/// As defined by [rfc7541 appendix A](https://tools.ietf.org/html/rfc7541#appendix-A).
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Header field of a compressed header block.
+///
+/// Sensitive fields (e.g. `authorization` or short cookies) are sent as never-indexed literals
+/// which intermediaries must forward the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HeaderField {
+    name: String,
+    value: String,
+    sensitive: bool,
+}
+
+impl HeaderField {
+
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            sensitive: false,
+        }
+    }
+
+    pub fn with_sensitive(mut self, sensitive: bool) -> Self {
+        self.sensitive = sensitive;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive
+    }
+
+    /// Returns the size of the field as counted by the dynamic table and header list limits.
+    pub fn size(&self) -> usize {
+        self.name.len() + self.value.len() + 32
+    }
+}
+
+/// Dynamic table holding the most recent entry first.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7541#section-4) for more details.
+#[derive(Debug, Clone)]
+struct DynamicTable {
+    entries: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl DynamicTable {
+
+    fn new(max_size: usize) -> Self {
+        Self { entries: VecDeque::new(), size: 0, max_size }
+    }
+
+    fn get(&self, index: usize) -> Option<&(String, String)> {
+        self.entries.get(index)
+    }
+
+    fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict(0);
+    }
+
+    /// Inserts an entry after evicting old ones. An entry larger than the table empties it.
+    fn insert(&mut self, name: &str, value: &str) {
+        let size = name.len() + value.len() + 32;
+        self.evict(size);
+        if size <= self.max_size {
+            self.entries.push_front((name.to_string(), value.to_string()));
+            self.size += size;
+        }
+    }
+
+    fn evict(&mut self, space: usize) {
+        while self.size + space > self.max_size {
+            match self.entries.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + 32,
+                None => break,
+            };
+        }
+    }
+}
+
+/// Compresses header lists into header blocks, keeping the dynamic table of one direction of
+/// a connection.
+#[derive(Debug, Clone)]
+pub struct HpackEncoder {
+    table: DynamicTable,
+    huffman: bool,
+    size_update: Option<usize>,
+}
+
+impl HpackEncoder {
+
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            table: DynamicTable::new(max_size),
+            huffman: true,
+            size_update: None,
+        }
+    }
+
+    /// Enables Huffman coding of string literals which do not grow by it (the default).
+    pub fn with_huffman(mut self, huffman: bool) -> Self {
+        self.huffman = huffman;
+        self
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.table.max_size
+    }
+
+    /// Changes the dynamic table size, e.g. after the peer announced a new
+    /// `SETTINGS_HEADER_TABLE_SIZE`. The change is signaled at the start of the next block.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.size_update = Some(self.size_update.unwrap_or(max_size).min(max_size));
+        self.table.set_max_size(max_size);
+    }
+
+    /// Appends the header block of `fields` to `output`.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7541#section-6) for more details.
+    pub fn encode(&mut self, fields: &[HeaderField], output: &mut Vec<u8>) {
+        if let Some(smallest) = self.size_update.take() {
+            if smallest < self.table.max_size {
+                encode_integer(smallest, 5, 0x20, output);
+            }
+            encode_integer(self.table.max_size, 5, 0x20, output);
+        }
+
+        for field in fields.iter() {
+            let (name, value) = (field.name.as_str(), field.value.as_str());
+            let (index, exact) = match self.find(name, value) {
+                Some((index, exact)) => (index, exact),
+                None => (0, false),
+            };
+
+            if exact && !field.sensitive {
+                encode_integer(index, 7, 0x80, output);
+                continue;
+            } else if field.sensitive {
+                encode_integer(index, 4, 0x10, output);
+            } else {
+                encode_integer(index, 6, 0x40, output);
+                self.table.insert(name, value);
+            }
+            if index == 0 {
+                encode_string(name.as_bytes(), 7, self.huffman, output);
+            }
+            encode_string(value.as_bytes(), 7, self.huffman, output);
+        }
+    }
+
+    /// Returns the lowest index of a matching entry and whether the value matches too.
+    fn find(&self, name: &str, value: &str) -> Option<(usize, bool)> {
+        let entries = STATIC_TABLE.iter().map(|(n, v)| (*n, *v))
+            .chain(self.table.entries.iter().map(|(n, v)| (n.as_str(), v.as_str())));
+
+        let mut found = None;
+        for (index, (n, v)) in entries.enumerate() {
+            if n == name && v == value {
+                return Some((index + 1, true));
+            } else if n == name && found.is_none() {
+                found = Some((index + 1, false));
+            }
+        }
+        found
+    }
+}
+
+impl Default for HpackEncoder {
+    fn default() -> Self {
+        Self::new(HPACK_TABLE_SIZE)
+    }
+}
+
+/// Decompresses header blocks into header lists, keeping the dynamic table of one direction
+/// of a connection.
+#[derive(Debug, Clone)]
+pub struct HpackDecoder {
+    table: DynamicTable,
+    max_size: usize,
+}
+
+impl HpackDecoder {
+
+    /// Creates a decoder which allows the encoder to use up to `max_size` bytes, as announced
+    /// in the local `SETTINGS_HEADER_TABLE_SIZE`.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            table: DynamicTable::new(max_size),
+            max_size,
+        }
+    }
+
+    /// Changes the allowed table size. The table shrinks when the encoder signals it.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+
+    /// Decodes a complete header block. Header lists larger than `limit` bytes, counted as
+    /// defined by `HeaderField::size`, are rejected.
+    pub fn decode(&mut self, block: &[u8], limit: Option<usize>) -> Result<Vec<HeaderField>, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        let mut fields: Vec<HeaderField> = Vec::new();
+        let (mut index, mut size) = (0, 0);
+
+        while index < block.len() {
+            let byte = block[index];
+            let field = if byte & 0x80 != 0 { // indexed
+                let (name, value) = self.get(decode_integer(block, &mut index, 7)?)?;
+                HeaderField::new(&name, &value)
+            } else if byte & 0x40 != 0 { // literal with incremental indexing
+                let (name, value) = self.read_literal(block, &mut index, 6)?;
+                self.table.insert(&name, &value);
+                HeaderField::new(&name, &value)
+            } else if byte & 0x20 != 0 { // dynamic table size update
+                if !fields.is_empty() {
+                    return Err(invalid("The HPACK table size update must start the header block."));
+                }
+                let max_size = decode_integer(block, &mut index, 5)?;
+                if max_size > self.max_size {
+                    return Err(invalid("The HPACK table size update exceeds the allowed size."));
+                }
+                self.table.set_max_size(max_size);
+                continue;
+            } else { // literal without indexing or never indexed
+                let (name, value) = self.read_literal(block, &mut index, 4)?;
+                HeaderField::new(&name, &value).with_sensitive(byte & 0x10 != 0)
+            };
+
+            size += field.size();
+            if let Some(limit) = limit.filter(|limit| size > *limit) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while decoding the header block.", limit)));
+            }
+            fields.push(field);
+        }
+
+        Ok(fields)
+    }
+
+    fn get(&self, index: usize) -> Result<(String, String), Error> {
+        let entry = match index {
+            0 => None,
+            1..=61 => STATIC_TABLE.get(index - 1).map(|(n, v)| (n.to_string(), v.to_string())),
+            _ => self.table.get(index - 62).cloned(),
+        };
+        entry.ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("The HPACK index {} is invalid.", index)))
+    }
+
+    fn read_literal(&self, block: &[u8], index: &mut usize, prefix: u8) -> Result<(String, String), Error> {
+        let name = match decode_integer(block, index, prefix)? {
+            0 => decode_text(block, index, 7)?,
+            name => self.get(name)?.0,
+        };
+        Ok((name, decode_text(block, index, 7)?))
+    }
+}
+
+impl Default for HpackDecoder {
+    fn default() -> Self {
+        Self::new(HPACK_TABLE_SIZE)
+    }
+}
+
+/// Appends an integer with an N-bit prefix. The `flags` occupy the bits above the prefix.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7541#section-5.1) for more details.
+pub(crate) fn encode_integer(value: usize, prefix: u8, flags: u8, output: &mut Vec<u8>) {
+    let max = (1usize << prefix) - 1;
+    if value < max {
+        output.push(flags | value as u8);
+        return;
+    }
+    output.push(flags | max as u8);
+    let mut rest = value - max;
+    while rest >= 128 {
+        output.push((rest % 128) as u8 | 0x80);
+        rest /= 128;
+    }
+    output.push(rest as u8);
+}
+
+/// Reads an integer with an N-bit prefix starting at `index` and moves past it.
+pub(crate) fn decode_integer(data: &[u8], index: &mut usize, prefix: u8) -> Result<usize, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "The HPACK integer is invalid.");
+    let max = (1usize << prefix) - 1;
+    let mut value = (*data.get(*index).ok_or_else(invalid)? as usize) & max;
+    *index += 1;
+    if value < max {
+        return Ok(value);
+    }
+
+    for shift in (0..).step_by(7) {
+        let byte = *data.get(*index).ok_or_else(invalid)?;
+        *index += 1;
+        if shift > 28 {
+            return Err(invalid()); // more than fits a 32-bit length
+        }
+        value += ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(value)
+}
+
+/// Appends a string literal whose length has an N-bit prefix with the Huffman flag right
+/// above it. Huffman coding is skipped when it would make the string longer.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7541#section-5.2) for more details.
+pub(crate) fn encode_string(data: &[u8], prefix: u8, huffman: bool, output: &mut Vec<u8>) {
+    encode_string_with_flags(data, prefix, 0, huffman, output)
+}
+
+/// Appends a string literal like `encode_string` after setting `flags` above the Huffman flag
+/// in the first byte.
+pub(crate) fn encode_string_with_flags(data: &[u8], prefix: u8, flags: u8, huffman: bool, output: &mut Vec<u8>) {
+    let length = huffman::encoded_len(data);
+    if huffman && length <= data.len() {
+        encode_integer(length, prefix, flags | 1 << prefix, output);
+        output.extend_from_slice(&huffman::encode(data));
+    } else {
+        encode_integer(data.len(), prefix, flags, output);
+        output.extend_from_slice(data);
+    }
+}
+
+/// Reads a string literal with an N-bit length prefix starting at `index` and moves past it.
+pub(crate) fn decode_string(data: &[u8], index: &mut usize, prefix: u8) -> Result<Vec<u8>, Error> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+    let encoded = data.get(*index).map(|b| b >> prefix & 1 == 1).unwrap_or(false);
+    let length = decode_integer(data, index, prefix)?;
+    let bytes = match data.get(*index..).filter(|rest| rest.len() >= length) {
+        Some(rest) => &rest[..length],
+        None => return Err(invalid("The HPACK string literal is truncated.")),
+    };
+    *index += length;
+
+    match encoded {
+        true => huffman::decode(bytes).ok_or_else(|| invalid("The Huffman-encoded string is invalid.")),
+        false => Ok(bytes.to_vec()),
+    }
+}
+
+/// Reads a string literal like `decode_string` which must be valid UTF-8.
+pub(crate) fn decode_text(data: &[u8], index: &mut usize, prefix: u8) -> Result<String, Error> {
+    String::from_utf8(decode_string(data, index, prefix)?)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "The HPACK string literal is not valid UTF-8."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn fields(list: &[(&str, &str)]) -> Vec<HeaderField> {
+        list.iter().map(|(n, v)| HeaderField::new(n, v)).collect()
+    }
+
+    /// Encodes and decodes the header lists of an appendix C example block by block.
+    fn check(mut encoder: HpackEncoder, lists: &[Vec<HeaderField>], blocks: &[&str], table_size: usize) {
+        let mut decoder = HpackDecoder::new(encoder.max_size());
+        for (list, block) in lists.iter().zip(blocks.iter()) {
+            let mut output = Vec::new();
+            encoder.encode(list, &mut output);
+            assert_eq!(output, hex(block));
+            assert_eq!(&decoder.decode(&output, None).unwrap(), list);
+        }
+        assert_eq!(encoder.table.size, table_size);
+        assert_eq!(decoder.table.size, table_size);
+    }
+
+    #[test]
+    fn codes_integers() {
+        let mut output = Vec::new();
+        encode_integer(10, 5, 0, &mut output);
+        encode_integer(1337, 5, 0, &mut output);
+        encode_integer(42, 8, 0, &mut output);
+        assert_eq!(output, vec![0x0a, 0x1f, 0x9a, 0x0a, 0x2a]);
+        let mut index = 0;
+        assert_eq!(decode_integer(&output, &mut index, 5).unwrap(), 10);
+        assert_eq!(decode_integer(&output, &mut index, 5).unwrap(), 1337);
+        assert_eq!(decode_integer(&output, &mut index, 8).unwrap(), 42);
+        assert!(decode_integer(&[0x1f, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01], &mut 0, 5).is_err());
+        assert!(decode_integer(&[0x1f, 0x9a], &mut 0, 5).is_err());
+    }
+
+    #[test]
+    fn decodes_literal_examples() {
+        let mut decoder = HpackDecoder::default();
+        let block = hex("400a637573746f6d2d6b65790d637573746f6d2d686561646572");
+        assert_eq!(decoder.decode(&block, None).unwrap(), fields(&[("custom-key", "custom-header")]));
+        assert_eq!(decoder.table.size, 55);
+        let block = hex("040c2f73616d706c652f70617468");
+        assert_eq!(decoder.decode(&block, None).unwrap(), fields(&[(":path", "/sample/path")]));
+        let block = hex("100870617373776f726406736563726574");
+        assert_eq!(decoder.decode(&block, None).unwrap(), vec![HeaderField::new("password", "secret").with_sensitive(true)]);
+        assert_eq!(decoder.decode(&hex("82"), None).unwrap(), fields(&[(":method", "GET")]));
+        assert_eq!(decoder.table.size, 55);
+    }
+
+    #[test]
+    fn codes_requests() {
+        let lists = [
+            fields(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com")]),
+            fields(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"), ("cache-control", "no-cache")]),
+            fields(&[(":method", "GET"), (":scheme", "https"), (":path", "/index.html"), (":authority", "www.example.com"), ("custom-key", "custom-value")]),
+        ];
+        check(HpackEncoder::default().with_huffman(false), &lists, &[
+            "828684410f7777772e6578616d706c652e636f6d",
+            "828684be58086e6f2d6361636865",
+            "828785bf400a637573746f6d2d6b65790c637573746f6d2d76616c7565",
+        ], 164);
+        check(HpackEncoder::default(), &lists, &[
+            "828684418cf1e3c2e5f23a6ba0ab90f4ff",
+            "828684be5886a8eb10649cbf",
+            "828785bf408825a849e95ba97d7f8925a849e95bb8e8b4bf",
+        ], 164);
+    }
+
+    #[test]
+    fn codes_responses_with_eviction() {
+        let lists = [
+            fields(&[(":status", "302"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"), ("location", "https://www.example.com")]),
+            fields(&[(":status", "307"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"), ("location", "https://www.example.com")]),
+            fields(&[(":status", "200"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:22 GMT"), ("location", "https://www.example.com"), ("content-encoding", "gzip"), ("set-cookie", "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1")]),
+        ];
+        check(HpackEncoder::new(256).with_huffman(false), &lists, &[
+            "4803333032580770726976617465611d4d6f6e2c203231204f637420323031332032303a31333a323120474d546e1768747470733a2f2f7777772e6578616d706c652e636f6d",
+            "4803333037c1c0bf",
+            "88c1611d4d6f6e2c203231204f637420323031332032303a31333a323220474d54c05a04677a69707738666f6f3d4153444a4b48514b425a584f5157454f50495541585157454f49553b206d61782d6167653d333630303b2076657273696f6e3d31",
+        ], 215);
+        check(HpackEncoder::new(256), &lists, &[
+            "488264025885aec3771a4b6196d07abe941054d444a8200595040b8166e082a62d1bff6e919d29ad171863c78f0b97c8e9ae82ae43d3",
+            "4883640effc1c0bf",
+            "88c16196d07abe941054d444a8200595040b8166e084a62d1bffc05a839bd9ab77ad94e7821dd7f2e6c7b335dfdfcd5b3960d5af27087f3672c1ab270fb5291f9587316065c003ed4ee5b1063d5007",
+        ], 215);
+    }
+
+    #[test]
+    fn updates_table_size() {
+        let mut encoder = HpackEncoder::default();
+        let mut decoder = HpackDecoder::default();
+        let mut output = Vec::new();
+        encoder.encode(&fields(&[("x-a", "1")]), &mut output);
+        encoder.set_max_size(0);
+        encoder.set_max_size(100);
+        let mut block = Vec::new();
+        encoder.encode(&fields(&[("x-a", "1")]), &mut block);
+        assert_eq!(&block[..2], &[0x20, 0x3f]);
+        decoder.decode(&output, None).unwrap();
+        assert_eq!(decoder.decode(&block, None).unwrap(), fields(&[("x-a", "1")]));
+        assert_eq!(decoder.table.size, 36);
+
+        assert!(decoder.decode(&[0x82, 0x20], None).is_err()); // update after a field
+        assert!(HpackDecoder::new(100).decode(&hex("3fe11f"), None).is_err()); // update above 100
+        assert!(decoder.decode(&hex("bf"), None).is_err()); // index beyond the table
+        assert!(decoder.decode(&hex("82"), Some(40)).is_err());
+    }
+
+    #[test]
+    fn never_indexes_sensitive_fields() {
+        let mut encoder = HpackEncoder::default();
+        let mut output = Vec::new();
+        encoder.encode(&[HeaderField::new("authorization", "secret").with_sensitive(true)], &mut output);
+        assert_eq!(output[0], 0x1f);
+        assert_eq!(encoder.table.size, 0);
+        let decoded = HpackDecoder::default().decode(&output, None).unwrap();
+        assert!(decoded[0].is_sensitive());
+    }
+}
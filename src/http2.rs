@@ -0,0 +1,1285 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use async_std::prelude::*;
+use async_std::io::{Read, Write};
+use crate::{read_exact, flush_write, read_http2_frame, write_http2_frame,
+    is_upgrade_request, select_upgrade, write_upgrade_request, parse_connection,
+    HeaderField, HpackDecoder, HpackEncoder, Http2Error, Http2ErrorCode, Http2Frame, Http2Settings,
+    Method, Status, Version, HPACK_TABLE_SIZE, HTTP2_MAX_FRAME_SIZE};
+use crate::base64;
+
+/// The client connection preface which starts every HTTP/2 connection.
+pub const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The flow control window of new connections and streams.
+pub const HTTP2_WINDOW_SIZE: u32 = 65_535;
+
+const MAX_WINDOW_SIZE: i64 = i32::MAX as i64;
+
+/// Headers which are specific to HTTP/1.1 connections and must not appear in HTTP/2.
+const CONNECTION_HEADERS: [&str; 5] = ["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+/// Side of an HTTP/2 connection which decides the parity of stream identifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Http2Role {
+    Client,
+    Server,
+}
+
+/// Stream states.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9113#section-5.1) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamState {
+    Idle,
+    ReservedLocal,
+    ReservedRemote,
+    Open,
+    HalfClosedLocal,
+    HalfClosedRemote,
+    Closed,
+}
+
+/// Request head carried by the pseudo-headers and headers of a `HEADERS` frame.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9113#section-8.3.1) for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2Request {
+    pub method: Method,
+    pub scheme: Option<String>,
+    pub authority: Option<String>,
+    pub path: Option<String>,
+    pub headers: Vec<HeaderField>,
+}
+
+impl Http2Request {
+
+    /// Creates a request for `target` which is the path and query of the URI.
+    pub fn new(method: Method, scheme: &str, authority: &str, target: &str) -> Self {
+        Self {
+            method,
+            scheme: Some(scheme.to_string()),
+            authority: Some(authority.to_string()),
+            path: Some(target.to_string()),
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push(HeaderField::new(name, value));
+        self
+    }
+
+    /// Parses a decoded header list. Malformed requests are stream errors.
+    pub fn from_fields(stream_id: u32, fields: Vec<HeaderField>) -> Result<Self, Http2Error> {
+        let malformed = || Http2Error::stream(stream_id, Http2ErrorCode::ProtocolError, "The HTTP/2 request is malformed.");
+        let (pseudo, headers) = split_pseudo(fields).ok_or_else(malformed)?;
+
+        let (mut method, mut scheme, mut authority, mut path) = (None, None, None, None);
+        for field in pseudo.into_iter() {
+            let slot = match field.name() {
+                ":method" => &mut method,
+                ":scheme" => &mut scheme,
+                ":authority" => &mut authority,
+                ":path" => &mut path,
+                _ => return Err(malformed()),
+            };
+            if slot.replace(field.value().to_string()).is_some() {
+                return Err(malformed());
+            }
+        }
+
+        let method = Method::from_str(&method.ok_or_else(malformed)?).map_err(|_| malformed())?;
+        let valid = match method {
+            Method::Connect => scheme.is_none() && path.is_none() && authority.is_some(),
+            _ => scheme.is_some() && path.as_ref().map(|p| !p.is_empty()).unwrap_or(false),
+        };
+        match valid {
+            true => Ok(Self { method, scheme, authority, path, headers }),
+            false => Err(malformed()),
+        }
+    }
+
+    /// Returns the pseudo-headers followed by the headers.
+    pub fn to_fields(&self) -> Vec<HeaderField> {
+        let mut fields = vec![HeaderField::new(":method", &self.method.to_string())];
+        let pseudo = [(":scheme", &self.scheme), (":authority", &self.authority), (":path", &self.path)];
+        for (name, value) in pseudo.iter() {
+            if let Some(value) = value {
+                fields.push(HeaderField::new(name, value));
+            }
+        }
+        fields.extend(lowercase(&self.headers));
+        fields
+    }
+}
+
+/// Response head carried by the `:status` pseudo-header and headers of a `HEADERS` frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2Response {
+    pub status: Status,
+    pub headers: Vec<HeaderField>,
+}
+
+impl Http2Response {
+
+    pub fn new(status: Status) -> Self {
+        Self { status, headers: Vec::new() }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push(HeaderField::new(name, value));
+        self
+    }
+
+    /// Parses a decoded header list. Malformed responses are stream errors.
+    pub fn from_fields(stream_id: u32, fields: Vec<HeaderField>) -> Result<Self, Http2Error> {
+        let malformed = || Http2Error::stream(stream_id, Http2ErrorCode::ProtocolError, "The HTTP/2 response is malformed.");
+        let (pseudo, headers) = split_pseudo(fields).ok_or_else(malformed)?;
+
+        let status = match pseudo.as_slice() {
+            [field] if field.name() == ":status" => field.value().parse::<u16>().ok().and_then(|s| Status::try_from(s).ok()),
+            _ => None,
+        };
+        match status {
+            Some(status) => Ok(Self { status, headers }),
+            None => Err(malformed()),
+        }
+    }
+
+    pub fn to_fields(&self) -> Vec<HeaderField> {
+        let mut fields = vec![HeaderField::new(":status", &self.status.to_string())];
+        fields.extend(lowercase(&self.headers));
+        fields
+    }
+}
+
+/// Something that happened on a connection while reading from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Http2Event {
+
+    /// The peer opened a stream with a request (servers only).
+    Request {
+        stream_id: u32,
+        request: Http2Request,
+        end_stream: bool,
+    },
+
+    /// The server answered a request. Informational responses may precede the final one.
+    Response {
+        stream_id: u32,
+        response: Http2Response,
+        end_stream: bool,
+    },
+
+    /// A header section which follows the data and ends the stream.
+    Trailers {
+        stream_id: u32,
+        headers: Vec<HeaderField>,
+    },
+
+    Data {
+        stream_id: u32,
+        data: Vec<u8>,
+        end_stream: bool,
+    },
+
+    /// The server reserved a stream for a response to the promised request (clients only).
+    PushPromise {
+        stream_id: u32,
+        promised_id: u32,
+        request: Http2Request,
+    },
+
+    /// The stream was reset by the peer or, after a stream error, by this side.
+    Reset {
+        stream_id: u32,
+        error: Http2ErrorCode,
+    },
+
+    /// The peer changed its settings which have been acknowledged.
+    Settings(Http2Settings),
+
+    /// The peer acknowledged the local settings which now apply.
+    SettingsAcknowledged,
+
+    /// The peer sent a ping which has been answered.
+    Ping([u8; 8]),
+
+    /// The peer answered a ping.
+    Pong([u8; 8]),
+
+    /// More data can be sent on the stream or, for stream `0`, on the connection.
+    WindowUpdate {
+        stream_id: u32,
+    },
+
+    /// The peer is shutting down. Streams above `last_stream_id` were not processed.
+    GoAway {
+        last_stream_id: u32,
+        error: Http2ErrorCode,
+        debug: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Stream {
+    state: StreamState,
+    send_window: i64,
+    recv_window: i64,
+    head_received: bool,
+}
+
+/// HTTP/2 connection over a bidirectional stream, e.g. a `TcpStream`.
+///
+/// Frames are written as soon as a method is called while `read_event` processes incoming
+/// frames one at a time, answering `PING` and `SETTINGS` frames and replenishing the receive
+/// windows as data arrives.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9113) for more details.
+#[derive(Debug)]
+pub struct Http2Connection<S> {
+    stream: S,
+    role: Http2Role,
+    local: Http2Settings,
+    pending: VecDeque<Http2Settings>,
+    remote: Http2Settings,
+    remote_received: bool,
+    encoder: HpackEncoder,
+    decoder: HpackDecoder,
+    streams: HashMap<u32, Stream>,
+    next_stream_id: u32,
+    last_remote_id: u32,
+    send_window: i64,
+    recv_window: i64,
+    continuation: Option<(Http2Frame, Vec<u8>)>,
+    goaway: Option<u32>,
+}
+
+impl<S> Http2Connection<S>
+    where
+    S: Read + Write + Unpin,
+{
+
+    /// Starts a connection. Clients send the connection preface, servers read and verify it.
+    /// Both sides then send their `settings`.
+    ///
+    /// The first event read afterwards must be the peer's settings.
+    pub async fn handshake(mut stream: S, role: Http2Role, settings: Http2Settings) -> Result<Self, Error> {
+        match role {
+            Http2Role::Client => {
+                stream.write_all(HTTP2_PREFACE).await?;
+            },
+            Http2Role::Server => {
+                let mut preface = Vec::with_capacity(HTTP2_PREFACE.len());
+                read_exact(&mut stream, &mut preface, HTTP2_PREFACE.len()).await?;
+                if preface != HTTP2_PREFACE {
+                    return Err(Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 connection preface is invalid.").into());
+                }
+            },
+        };
+
+        let mut connection = Self {
+            stream,
+            role,
+            local: Http2Settings::new(),
+            pending: VecDeque::new(),
+            remote: Http2Settings::new(),
+            remote_received: false,
+            encoder: HpackEncoder::default(),
+            decoder: HpackDecoder::default(),
+            streams: HashMap::new(),
+            next_stream_id: if role == Http2Role::Client { 1 } else { 2 },
+            last_remote_id: 0,
+            send_window: HTTP2_WINDOW_SIZE as i64,
+            recv_window: HTTP2_WINDOW_SIZE as i64,
+            continuation: None,
+            goaway: None,
+        };
+        connection.update_settings(settings).await?;
+
+        Ok(connection)
+    }
+
+    /// Continues an `h2c` upgrade on the server after the `101 Switching Protocols` response.
+    ///
+    /// The `client_settings` of the `HTTP2-Settings` header apply right away and the upgraded
+    /// request becomes stream `1` which awaits the response.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7540#section-3.2) for more details.
+    pub async fn upgrade_server(stream: S, settings: Http2Settings, client_settings: Http2Settings) -> Result<Self, Error> {
+        let mut connection = Self::handshake(stream, Http2Role::Server, settings).await?;
+        connection.apply_remote(client_settings)?;
+        connection.remote_received = false; // the preface still carries a SETTINGS frame
+
+        let mut stream = connection.new_stream(StreamState::HalfClosedRemote);
+        stream.head_received = true;
+        connection.streams.insert(1, stream);
+        connection.last_remote_id = 1;
+
+        Ok(connection)
+    }
+
+    /// Continues an `h2c` upgrade on the client after the `101 Switching Protocols` response.
+    ///
+    /// The `settings` must be those sent in the `HTTP2-Settings` header. The upgrade request
+    /// becomes stream `1` which awaits the response.
+    pub async fn upgrade_client(stream: S, settings: Http2Settings) -> Result<Self, Error> {
+        let mut connection = Self::handshake(stream, Http2Role::Client, settings).await?;
+        connection.apply_local(settings)?;
+
+        let stream = connection.new_stream(StreamState::HalfClosedLocal);
+        connection.streams.insert(1, stream);
+        connection.next_stream_id = 3;
+
+        Ok(connection)
+    }
+
+    pub fn role(&self) -> Http2Role {
+        self.role
+    }
+
+    /// Returns the state of a stream. Unknown streams are idle or closed.
+    pub fn stream_state(&self, stream_id: u32) -> StreamState {
+        match self.streams.get(&stream_id) {
+            Some(stream) => stream.state,
+            None if self.is_used(stream_id) => StreamState::Closed,
+            None => StreamState::Idle,
+        }
+    }
+
+    /// Returns how many bytes of data can be sent on the stream right now.
+    pub fn send_capacity(&self, stream_id: u32) -> usize {
+        match self.streams.get(&stream_id) {
+            Some(stream) => stream.send_window.min(self.send_window).max(0) as usize,
+            None => 0,
+        }
+    }
+
+    /// The settings the peer announced last.
+    pub fn remote_settings(&self) -> &Http2Settings {
+        &self.remote
+    }
+
+    /// The local settings acknowledged by the peer.
+    pub fn local_settings(&self) -> &Http2Settings {
+        &self.local
+    }
+
+    /// Sends new local settings. They apply once the peer acknowledges them.
+    pub async fn update_settings(&mut self, settings: Http2Settings) -> Result<(), Error> {
+        self.pending.push_back(settings);
+        self.write(&Http2Frame::Settings { ack: false, settings }).await
+    }
+
+    /// Opens a new stream with a request and returns its identifier (clients only).
+    pub async fn send_request(&mut self, request: &Http2Request, end_stream: bool) -> Result<u32, Error> {
+        let refused = |message: &str| Error::new(ErrorKind::InvalidInput, message.to_string());
+        if self.role != Http2Role::Client {
+            return Err(refused("Only HTTP/2 clients can send requests."));
+        } else if self.goaway.is_some() {
+            return Err(refused("The HTTP/2 connection is shutting down."));
+        } else if self.local_streams() >= self.remote.max_concurrent_streams.unwrap_or(u32::MAX) as usize {
+            return Err(refused("The HTTP/2 connection reached the maximum of concurrent streams."));
+        } else if self.next_stream_id > 0x7fff_ffff {
+            return Err(refused("The HTTP/2 connection ran out of stream identifiers."));
+        }
+
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 2;
+        self.streams.insert(stream_id, self.new_stream(StreamState::Idle));
+        self.send_headers(stream_id, request.to_fields(), end_stream).await?;
+
+        Ok(stream_id)
+    }
+
+    /// Sends the response head of a stream opened by a request (servers only).
+    pub async fn send_response(&mut self, stream_id: u32, response: &Http2Response, end_stream: bool) -> Result<(), Error> {
+        self.send_headers(stream_id, response.to_fields(), end_stream && !response.status.is_informational()).await
+    }
+
+    /// Sends trailers which end the stream.
+    pub async fn send_trailers(&mut self, stream_id: u32, headers: &[HeaderField]) -> Result<(), Error> {
+        self.send_headers(stream_id, lowercase(headers), true).await
+    }
+
+    /// Sends as much data as the flow control windows allow and returns its length. The
+    /// stream ends only when all the data was sent with `end_stream`.
+    pub async fn send_data(&mut self, stream_id: u32, data: &[u8], end_stream: bool) -> Result<usize, Error> {
+        self.check_sendable(stream_id)?;
+        let size = self.send_capacity(stream_id).min(data.len());
+        let max_frame_size = self.remote.max_frame_size.unwrap_or(HTTP2_MAX_FRAME_SIZE) as usize;
+        let end_stream = end_stream && size == data.len();
+
+        let mut chunks = data[..size].chunks(max_frame_size).peekable();
+        if chunks.peek().is_none() && end_stream {
+            self.write(&Http2Frame::Data { stream_id, data: vec![], end_stream, padding: None }).await?;
+        }
+        while let Some(chunk) = chunks.next() {
+            let frame = Http2Frame::Data { stream_id, data: chunk.to_vec(), end_stream: end_stream && chunks.peek().is_none(), padding: None };
+            self.write(&frame).await?;
+        }
+
+        self.send_window -= size as i64;
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.send_window -= size as i64;
+        }
+        if end_stream {
+            self.close_local(stream_id);
+        }
+        flush_write(&mut self.stream).await?;
+
+        Ok(size)
+    }
+
+    /// Resets a stream.
+    pub async fn send_reset(&mut self, stream_id: u32, error: Http2ErrorCode) -> Result<(), Error> {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.state = StreamState::Closed;
+        }
+        self.streams.remove(&stream_id);
+        self.write(&Http2Frame::RstStream { stream_id, error }).await
+    }
+
+    pub async fn send_ping(&mut self, data: [u8; 8]) -> Result<(), Error> {
+        self.write(&Http2Frame::Ping { ack: false, data }).await
+    }
+
+    /// Starts a graceful shutdown. Streams opened by the peer afterwards are ignored.
+    pub async fn send_goaway(&mut self, error: Http2ErrorCode, debug: &[u8]) -> Result<(), Error> {
+        self.goaway = Some(self.goaway.unwrap_or(self.last_remote_id).min(self.last_remote_id));
+        self.write(&Http2Frame::GoAway { last_stream_id: self.last_remote_id, error, debug: debug.to_vec() }).await
+    }
+
+    /// Reads frames until something happens which concerns the application.
+    ///
+    /// Stream errors reset the stream and are returned as `Http2Event::Reset`. Connection
+    /// errors send a `GOAWAY` and are returned as an `Http2Error` inside the I/O error.
+    pub async fn read_event(&mut self) -> Result<Http2Event, Error> {
+        loop {
+            let max_frame_size = self.local.max_frame_size.unwrap_or(HTTP2_MAX_FRAME_SIZE);
+            let result = match read_http2_frame(&mut self.stream, max_frame_size).await {
+                Ok(frame) => self.process(frame).await,
+                Err(error) => match Http2Error::from_io(&error).cloned() {
+                    Some(error) => Err(error),
+                    None => return Err(error),
+                },
+            };
+
+            match result {
+                Ok(Some(event)) => return Ok(event),
+                Ok(None) => continue,
+                Err(error) if error.is_connection_error() => {
+                    let frame = Http2Frame::GoAway { last_stream_id: self.last_remote_id, error: error.code(), debug: vec![] };
+                    self.write(&frame).await?;
+                    return Err(error.into());
+                },
+                Err(error) => {
+                    self.send_reset(error.stream_id(), error.code()).await?;
+                    return Ok(Http2Event::Reset { stream_id: error.stream_id(), error: error.code() });
+                },
+            };
+        }
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    async fn process(&mut self, frame: Http2Frame) -> Result<Option<Http2Event>, Http2Error> {
+        let protocol = |message: &str| Http2Error::connection(Http2ErrorCode::ProtocolError, message);
+
+        if !self.remote_received && !matches!(frame, Http2Frame::Settings { ack: false, .. }) {
+            return Err(protocol("The HTTP/2 peer did not start with SETTINGS."));
+        } else if self.continuation.is_some() && !matches!(frame, Http2Frame::Continuation { .. }) {
+            return Err(protocol("The HTTP/2 header block was interrupted."));
+        }
+
+        match frame {
+            Http2Frame::Settings { ack: false, settings } => {
+                self.apply_remote(settings)?;
+                self.write(&Http2Frame::Settings { ack: true, settings: Http2Settings::new() }).await.map_err(internal)?;
+                Ok(Some(Http2Event::Settings(settings)))
+            },
+            Http2Frame::Settings { ack: true, .. } => {
+                let settings = self.pending.pop_front().ok_or_else(|| protocol("The HTTP/2 SETTINGS acknowledgement was not expected."))?;
+                self.apply_local(settings)?;
+                Ok(Some(Http2Event::SettingsAcknowledged))
+            },
+            Http2Frame::Ping { ack: false, data } => {
+                self.write(&Http2Frame::Ping { ack: true, data }).await.map_err(internal)?;
+                Ok(Some(Http2Event::Ping(data)))
+            },
+            Http2Frame::Ping { ack: true, data } => Ok(Some(Http2Event::Pong(data))),
+            Http2Frame::GoAway { last_stream_id, error, debug } => {
+                self.goaway = Some(last_stream_id);
+                let parity = if self.role == Http2Role::Client { 1 } else { 0 };
+                self.streams.retain(|id, _| id % 2 != parity || *id <= last_stream_id);
+                Ok(Some(Http2Event::GoAway { last_stream_id, error, debug }))
+            },
+            Http2Frame::WindowUpdate { stream_id: 0, increment } => {
+                self.send_window += increment as i64;
+                if self.send_window > MAX_WINDOW_SIZE {
+                    return Err(Http2Error::connection(Http2ErrorCode::FlowControlError, "The HTTP/2 connection window overflowed."));
+                }
+                Ok(Some(Http2Event::WindowUpdate { stream_id: 0 }))
+            },
+            Http2Frame::WindowUpdate { stream_id, increment } => {
+                self.check_known(stream_id)?;
+                match self.streams.get_mut(&stream_id) {
+                    Some(stream) => {
+                        stream.send_window += increment as i64;
+                        if stream.send_window > MAX_WINDOW_SIZE {
+                            return Err(Http2Error::stream(stream_id, Http2ErrorCode::FlowControlError, "The HTTP/2 stream window overflowed."));
+                        }
+                        Ok(Some(Http2Event::WindowUpdate { stream_id }))
+                    },
+                    None => Ok(None), // recently closed
+                }
+            },
+            Http2Frame::RstStream { stream_id, error } => {
+                self.check_known(stream_id)?;
+                self.streams.remove(&stream_id);
+                Ok(Some(Http2Event::Reset { stream_id, error }))
+            },
+            Http2Frame::Data { stream_id, data, end_stream, padding } => {
+                let length = data.len() + padding.map(|p| p as usize + 1).unwrap_or(0);
+                self.receive_data(stream_id, length)?;
+                self.replenish(stream_id, length).await.map_err(internal)?;
+                match self.streams.get(&stream_id).map(|s| s.state) {
+                    Some(StreamState::Open) | Some(StreamState::HalfClosedLocal) => (),
+                    _ => return Err(self.closed_error(stream_id)),
+                };
+                if end_stream {
+                    self.close_remote(stream_id);
+                }
+                Ok(Some(Http2Event::Data { stream_id, data, end_stream }))
+            },
+            Http2Frame::Continuation { stream_id, block, end_headers } => {
+                let (head, mut buffer) = self.continuation.take().ok_or_else(|| protocol("The HTTP/2 CONTINUATION frame was not expected."))?;
+                if head.stream_id() != stream_id {
+                    return Err(protocol("The HTTP/2 CONTINUATION frame belongs to another stream."));
+                } else if buffer.len() + block.len() > self.block_limit() {
+                    return Err(Http2Error::connection(Http2ErrorCode::EnhanceYourCalm, "The HTTP/2 header block is too large."));
+                }
+                buffer.extend_from_slice(&block);
+                match end_headers {
+                    true => self.receive_headers(head, buffer),
+                    false => {
+                        self.continuation = Some((head, buffer));
+                        Ok(None)
+                    },
+                }
+            },
+            Http2Frame::Headers { .. } | Http2Frame::PushPromise { .. } => {
+                let end_headers = matches!(frame, Http2Frame::Headers { end_headers: true, .. } | Http2Frame::PushPromise { end_headers: true, .. });
+                let (head, block) = split_block(frame);
+                match end_headers {
+                    true => self.receive_headers(head, block),
+                    false => {
+                        self.continuation = Some((head, block));
+                        Ok(None)
+                    },
+                }
+            },
+            Http2Frame::Priority { .. } | Http2Frame::Unknown { .. } => Ok(None),
+        }
+    }
+
+    /// Returns the size up to which a header block may grow over CONTINUATION frames. An
+    /// encoded block is never larger than the header list it decodes to.
+    fn block_limit(&self) -> usize {
+        match self.local.max_header_list_size {
+            Some(size) => size as usize,
+            None => self.local.max_frame_size.unwrap_or(HTTP2_MAX_FRAME_SIZE) as usize * 16,
+        }
+    }
+
+    fn receive_headers(&mut self, head: Http2Frame, block: Vec<u8>) -> Result<Option<Http2Event>, Http2Error> {
+        let limit = self.local.max_header_list_size.map(|size| size as usize);
+        let fields = self.decoder.decode(&block, limit)
+            .map_err(|_| Http2Error::connection(Http2ErrorCode::CompressionError, "The HTTP/2 header block cannot be decoded."))?;
+
+        match head {
+            Http2Frame::PushPromise { stream_id, promised_id, .. } => {
+                if self.role != Http2Role::Client || self.local.enable_push == Some(false) {
+                    return Err(Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 push was not enabled."));
+                } else if promised_id % 2 != 0 || promised_id <= self.last_remote_id {
+                    return Err(Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 promised stream is invalid."));
+                }
+                match self.streams.get(&stream_id).map(|s| s.state) {
+                    Some(StreamState::Open) | Some(StreamState::HalfClosedLocal) => (),
+                    _ => return Err(Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 push was promised on an unusable stream.")),
+                };
+                self.last_remote_id = promised_id;
+                self.streams.insert(promised_id, self.new_stream(StreamState::ReservedRemote));
+                let request = Http2Request::from_fields(promised_id, fields)?;
+                Ok(Some(Http2Event::PushPromise { stream_id, promised_id, request }))
+            },
+            Http2Frame::Headers { stream_id, end_stream, .. } => {
+                let remote = (stream_id % 2 == 1) == (self.role == Http2Role::Server);
+                if remote && !self.is_used(stream_id) {
+                    self.open_remote(stream_id)?;
+                }
+                let stream = match self.streams.get_mut(&stream_id) {
+                    Some(stream) => stream,
+                    None => return Err(self.closed_error(stream_id)),
+                };
+                stream.state = match stream.state {
+                    StreamState::Idle | StreamState::Open => StreamState::Open,
+                    StreamState::ReservedRemote => StreamState::HalfClosedLocal,
+                    StreamState::HalfClosedLocal => StreamState::HalfClosedLocal,
+                    _ => return Err(Http2Error::stream(stream_id, Http2ErrorCode::StreamClosed, "The HTTP/2 stream is closed.")),
+                };
+                let first = !stream.head_received;
+                stream.head_received = true;
+                if end_stream {
+                    self.close_remote(stream_id);
+                } else if !first && !fields.iter().any(|f| f.name().starts_with(':')) {
+                    return Err(Http2Error::stream(stream_id, Http2ErrorCode::ProtocolError, "The HTTP/2 trailers do not end the stream."));
+                }
+
+                if self.role == Http2Role::Server && first {
+                    let request = Http2Request::from_fields(stream_id, fields)?;
+                    Ok(Some(Http2Event::Request { stream_id, request, end_stream }))
+                } else if self.role == Http2Role::Client && fields.first().map(|f| f.name().starts_with(':')).unwrap_or(false) {
+                    let response = Http2Response::from_fields(stream_id, fields)?;
+                    if response.status.is_informational() {
+                        if let Some(stream) = self.streams.get_mut(&stream_id) {
+                            stream.head_received = false;
+                        }
+                    }
+                    Ok(Some(Http2Event::Response { stream_id, response, end_stream }))
+                } else {
+                    match split_pseudo(fields) {
+                        Some((pseudo, headers)) if pseudo.is_empty() && end_stream => Ok(Some(Http2Event::Trailers { stream_id, headers })),
+                        _ => Err(Http2Error::stream(stream_id, Http2ErrorCode::ProtocolError, "The HTTP/2 trailers are malformed.")),
+                    }
+                }
+            },
+            _ => Ok(None),
+        }
+    }
+
+    async fn send_headers(&mut self, stream_id: u32, fields: Vec<HeaderField>, end_stream: bool) -> Result<(), Error> {
+        let stream = self.streams.get_mut(&stream_id)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "The HTTP/2 stream is not open."))?;
+        stream.state = match stream.state {
+            StreamState::Idle | StreamState::Open => StreamState::Open,
+            StreamState::ReservedLocal => StreamState::HalfClosedRemote,
+            StreamState::HalfClosedRemote => StreamState::HalfClosedRemote,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "The HTTP/2 stream is not open.")),
+        };
+
+        let mut block = Vec::new();
+        self.encoder.encode(&fields, &mut block);
+        let max_frame_size = self.remote.max_frame_size.unwrap_or(HTTP2_MAX_FRAME_SIZE) as usize;
+        let count = ((block.len() + max_frame_size - 1) / max_frame_size).max(1);
+        for index in 0..count {
+            let fragment = block.get(index * max_frame_size..block.len().min((index + 1) * max_frame_size)).unwrap_or(&[]).to_vec();
+            let end_headers = index + 1 == count;
+            let frame = match index {
+                0 => Http2Frame::Headers { stream_id, block: fragment, end_stream, end_headers, priority: None, padding: None },
+                _ => Http2Frame::Continuation { stream_id, block: fragment, end_headers },
+            };
+            write_http2_frame(&mut self.stream, &frame).await?;
+        }
+        if end_stream {
+            self.close_local(stream_id);
+        }
+        flush_write(&mut self.stream).await
+    }
+
+    fn apply_remote(&mut self, settings: Http2Settings) -> Result<(), Http2Error> {
+        if let Some(size) = settings.initial_window_size {
+            let delta = size as i64 - self.remote.initial_window_size.unwrap_or(HTTP2_WINDOW_SIZE) as i64;
+            for stream in self.streams.values_mut() {
+                stream.send_window += delta;
+                if stream.send_window > MAX_WINDOW_SIZE {
+                    return Err(Http2Error::connection(Http2ErrorCode::FlowControlError, "The HTTP/2 stream window overflowed."));
+                }
+            }
+        }
+        if let Some(size) = settings.header_table_size {
+            self.encoder.set_max_size((size as usize).min(HPACK_TABLE_SIZE));
+        }
+
+        self.remote = merge(self.remote, settings);
+        self.remote_received = true;
+        Ok(())
+    }
+
+    fn apply_local(&mut self, settings: Http2Settings) -> Result<(), Http2Error> {
+        if let Some(size) = settings.initial_window_size {
+            let delta = size as i64 - self.local.initial_window_size.unwrap_or(HTTP2_WINDOW_SIZE) as i64;
+            for stream in self.streams.values_mut() {
+                stream.recv_window += delta;
+            }
+        }
+        if let Some(size) = settings.header_table_size {
+            self.decoder.set_max_size(size as usize);
+        }
+
+        self.local = merge(self.local, settings);
+        Ok(())
+    }
+
+    fn open_remote(&mut self, stream_id: u32) -> Result<(), Http2Error> {
+        if stream_id <= self.last_remote_id {
+            return Err(Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 stream identifier was already used."));
+        }
+        self.last_remote_id = stream_id;
+        if self.goaway.map(|last| stream_id > last).unwrap_or(false) {
+            return Err(Http2Error::stream(stream_id, Http2ErrorCode::RefusedStream, "The HTTP/2 connection is shutting down."));
+        }
+
+        let remote_streams = self.streams.iter()
+            .filter(|(id, s)| **id % 2 == stream_id % 2 && matches!(s.state, StreamState::Open | StreamState::HalfClosedLocal | StreamState::HalfClosedRemote))
+            .count();
+        if remote_streams >= self.local.max_concurrent_streams.unwrap_or(u32::MAX) as usize {
+            return Err(Http2Error::stream(stream_id, Http2ErrorCode::RefusedStream, "The HTTP/2 connection reached the maximum of concurrent streams."));
+        }
+        self.streams.insert(stream_id, self.new_stream(StreamState::Idle));
+        Ok(())
+    }
+
+    fn receive_data(&mut self, stream_id: u32, length: usize) -> Result<(), Http2Error> {
+        self.recv_window -= length as i64;
+        if self.recv_window < 0 {
+            return Err(Http2Error::connection(Http2ErrorCode::FlowControlError, "The HTTP/2 peer exceeded the connection window."));
+        }
+        self.check_known(stream_id)?;
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.recv_window -= length as i64;
+            if stream.recv_window < 0 {
+                return Err(Http2Error::stream(stream_id, Http2ErrorCode::FlowControlError, "The HTTP/2 peer exceeded the stream window."));
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the receive windows once half of them has been used.
+    async fn replenish(&mut self, stream_id: u32, length: usize) -> Result<(), Error> {
+        let initial = self.local.initial_window_size.unwrap_or(HTTP2_WINDOW_SIZE) as i64;
+        if length > 0 && self.recv_window < HTTP2_WINDOW_SIZE as i64 / 2 {
+            let increment = (HTTP2_WINDOW_SIZE as i64 - self.recv_window) as u32;
+            self.recv_window += increment as i64;
+            self.write(&Http2Frame::WindowUpdate { stream_id: 0, increment }).await?;
+        }
+        let update = match self.streams.get_mut(&stream_id) {
+            Some(stream) if length > 0 && stream.recv_window < initial / 2 && stream.state != StreamState::HalfClosedRemote => {
+                let increment = (initial - stream.recv_window) as u32;
+                stream.recv_window += increment as i64;
+                Some(increment)
+            },
+            _ => None,
+        };
+        if let Some(increment) = update {
+            self.write(&Http2Frame::WindowUpdate { stream_id, increment }).await?;
+        }
+        Ok(())
+    }
+
+    fn check_known(&self, stream_id: u32) -> Result<(), Http2Error> {
+        match self.is_used(stream_id) || self.streams.contains_key(&stream_id) {
+            true => Ok(()),
+            false => Err(Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 frame belongs to an idle stream.")),
+        }
+    }
+
+    fn check_sendable(&self, stream_id: u32) -> Result<(), Error> {
+        match self.streams.get(&stream_id).map(|s| s.state) {
+            Some(StreamState::Open) | Some(StreamState::HalfClosedRemote) => Ok(()),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "The HTTP/2 stream is not open for sending.")),
+        }
+    }
+
+    fn closed_error(&self, stream_id: u32) -> Http2Error {
+        match self.is_used(stream_id) {
+            true => Http2Error::stream(stream_id, Http2ErrorCode::StreamClosed, "The HTTP/2 stream is closed."),
+            false => Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 frame belongs to an idle stream."),
+        }
+    }
+
+    /// Returns `true` if the identifier has been used by either side.
+    fn is_used(&self, stream_id: u32) -> bool {
+        let local = (stream_id % 2 == 1) == (self.role == Http2Role::Client);
+        match local {
+            true => stream_id < self.next_stream_id,
+            false => stream_id <= self.last_remote_id,
+        }
+    }
+
+    fn local_streams(&self) -> usize {
+        let parity = if self.role == Http2Role::Client { 1 } else { 0 };
+        self.streams.iter().filter(|(id, s)| *id % 2 == parity && s.state != StreamState::ReservedLocal).count()
+    }
+
+    fn new_stream(&self, state: StreamState) -> Stream {
+        Stream {
+            state,
+            send_window: self.remote.initial_window_size.unwrap_or(HTTP2_WINDOW_SIZE) as i64,
+            recv_window: self.local.initial_window_size.unwrap_or(HTTP2_WINDOW_SIZE) as i64,
+            head_received: false,
+        }
+    }
+
+    fn close_local(&mut self, stream_id: u32) {
+        let state = self.streams.get(&stream_id).map(|s| s.state);
+        match state {
+            Some(StreamState::Open) => self.set_state(stream_id, StreamState::HalfClosedLocal),
+            Some(StreamState::HalfClosedRemote) => {
+                self.streams.remove(&stream_id);
+            },
+            _ => (),
+        };
+    }
+
+    fn close_remote(&mut self, stream_id: u32) {
+        let state = self.streams.get(&stream_id).map(|s| s.state);
+        match state {
+            Some(StreamState::Open) => self.set_state(stream_id, StreamState::HalfClosedRemote),
+            Some(StreamState::HalfClosedLocal) => {
+                self.streams.remove(&stream_id);
+            },
+            _ => (),
+        };
+    }
+
+    fn set_state(&mut self, stream_id: u32, state: StreamState) {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.state = state;
+        }
+    }
+
+    async fn write(&mut self, frame: &Http2Frame) -> Result<(), Error> {
+        write_http2_frame(&mut self.stream, frame).await?;
+        flush_write(&mut self.stream).await
+    }
+}
+
+/// Reads the first bytes of a connection to tell cleartext HTTP/2 with prior knowledge from
+/// HTTP/1.x before the request line is parsed.
+///
+/// Returns `Version::Http2_0` if the connection starts with the HTTP/2 preface and
+/// `Version::Http1_1` otherwise, together with the bytes which have been read. Reading stops as
+/// soon as the bytes differ from the preface. The bytes must be replayed, e.g. with
+/// `Upgraded::new(stream, bytes)`, to `read_first_line` or `Http2Connection::handshake`.
+pub async fn peek_http2_preface<I>(input: &mut I) -> Result<(Version, Vec<u8>), Error>
+    where
+    I: Read + Unpin,
+{
+    let mut bytes = Vec::with_capacity(HTTP2_PREFACE.len());
+
+    while bytes.len() < HTTP2_PREFACE.len() {
+        let mut buffer = vec![0u8; HTTP2_PREFACE.len() - bytes.len()];
+        let size = input.read(&mut buffer).await?;
+        bytes.extend_from_slice(&buffer[..size]);
+
+        if size == 0 || !HTTP2_PREFACE.starts_with(&bytes) {
+            return Ok((Version::Http1_1, bytes));
+        }
+    }
+
+    Ok((Version::Http2_0, bytes))
+}
+
+/// Returns the `HTTP2-Settings` header value of an `h2c` upgrade request.
+pub fn http2_settings_header(settings: &Http2Settings) -> String {
+    base64::encode_url(&settings.encode())
+}
+
+/// Parses an `HTTP2-Settings` header value.
+pub fn parse_http2_settings_header(value: &str) -> Result<Http2Settings, Error> {
+    let payload = base64::decode_url(value.trim())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "The HTTP2-Settings header is invalid."))?;
+    Ok(Http2Settings::decode(&payload)?)
+}
+
+/// Checks whether an HTTP/1.1 request asks to upgrade to cleartext HTTP/2 and returns the
+/// client's settings of its `HTTP2-Settings` header.
+///
+/// Returns `None` if the upgrade is not requested or malformed in which case the request is
+/// served over HTTP/1.1. Otherwise the server should read the request body, reply with
+/// `write_switching_protocols(output, "h2c", &[])` and continue with
+/// `Http2Connection::upgrade_server`.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7540#section-3.2) for more details.
+pub fn validate_h2c_request(version: Version, headers: &[(String, String)]) -> Option<Http2Settings> {
+    let values = |name: &str| headers.iter()
+        .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+        .collect::<Vec<&str>>();
+
+    let connection = values("connection").join(", ");
+    let upgrade = values("upgrade").join(", ");
+    let listed = parse_connection(&connection).iter().any(|t| t == "http2-settings");
+    if !listed || !is_upgrade_request(version, Some(&connection), Some(&upgrade)) || select_upgrade(&upgrade, &["h2c"]).is_none() {
+        return None;
+    }
+
+    match values("http2-settings").as_slice() {
+        [value] => parse_http2_settings_header(value).ok(),
+        _ => None,
+    }
+}
+
+/// Writes a `GET` request head which asks the server to switch to cleartext HTTP/2 with the
+/// client's `settings`.
+pub async fn write_h2c_request<O>(output: &mut O, target: &str, host: &str, settings: &Http2Settings) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let value = http2_settings_header(settings);
+    write_upgrade_request(output, target, host, &["h2c"], &[("Connection", "HTTP2-Settings"), ("HTTP2-Settings", &value)]).await
+}
+
+/// Splits a header list into pseudo-headers and headers. Returns `None` if a pseudo-header
+/// follows a header, a name is not lowercase or a connection-specific header is present.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9113#section-8.2.2) for more details.
+fn split_pseudo(fields: Vec<HeaderField>) -> Option<(Vec<HeaderField>, Vec<HeaderField>)> {
+    let (mut pseudo, mut headers) = (Vec::new(), Vec::new());
+    for field in fields.into_iter() {
+        let name = field.name();
+        if name.is_empty() || name.bytes().any(|b| b.is_ascii_uppercase()) {
+            return None;
+        } else if let Some(stripped) = name.strip_prefix(':') {
+            if !headers.is_empty() || stripped.is_empty() {
+                return None;
+            }
+            pseudo.push(field);
+        } else if CONNECTION_HEADERS.contains(&name) || (name == "te" && field.value() != "trailers") {
+            return None;
+        } else {
+            headers.push(field);
+        }
+    }
+    Some((pseudo, headers))
+}
+
+fn lowercase(headers: &[HeaderField]) -> Vec<HeaderField> {
+    headers.iter()
+        .map(|f| HeaderField::new(&f.name().to_lowercase(), f.value()).with_sensitive(f.is_sensitive()))
+        .collect()
+}
+
+fn merge(base: Http2Settings, update: Http2Settings) -> Http2Settings {
+    Http2Settings {
+        header_table_size: update.header_table_size.or(base.header_table_size),
+        enable_push: update.enable_push.or(base.enable_push),
+        max_concurrent_streams: update.max_concurrent_streams.or(base.max_concurrent_streams),
+        initial_window_size: update.initial_window_size.or(base.initial_window_size),
+        max_frame_size: update.max_frame_size.or(base.max_frame_size),
+        max_header_list_size: update.max_header_list_size.or(base.max_header_list_size),
+    }
+}
+
+/// Takes the header block out of the frame which is kept while CONTINUATION frames arrive.
+fn split_block(frame: Http2Frame) -> (Http2Frame, Vec<u8>) {
+    match frame {
+        Http2Frame::Headers { stream_id, block, end_stream, priority, padding, .. } => {
+            (Http2Frame::Headers { stream_id, block: vec![], end_stream, end_headers: true, priority, padding }, block)
+        },
+        Http2Frame::PushPromise { stream_id, promised_id, block, padding, .. } => {
+            (Http2Frame::PushPromise { stream_id, promised_id, block: vec![], end_headers: true, padding }, block)
+        },
+        frame => (frame, vec![]),
+    }
+}
+
+fn internal(error: Error) -> Http2Error {
+    Http2Error::connection(Http2ErrorCode::InternalError, &error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+    use crate::{read_first_line, read_headers, read_upgrade_response, write_switching_protocols, Limits};
+    use super::*;
+
+    type Queue = Arc<Mutex<(VecDeque<u8>, Option<Waker>)>>;
+
+    /// One end of an in-memory duplex stream.
+    #[derive(Debug, Clone)]
+    struct Pipe {
+        input: Queue,
+        output: Queue,
+    }
+
+    fn duplex() -> (Pipe, Pipe) {
+        let (first, second) = (Queue::default(), Queue::default());
+        (Pipe { input: first.clone(), output: second.clone() }, Pipe { input: second, output: first })
+    }
+
+    impl Read for Pipe {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize, Error>> {
+            let mut queue = self.input.lock().unwrap();
+            if queue.0.is_empty() {
+                queue.1 = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let size = buf.len().min(queue.0.len());
+            for (byte, value) in buf.iter_mut().zip(queue.0.drain(..size)) {
+                *byte = value;
+            }
+            Poll::Ready(Ok(size))
+        }
+    }
+
+    impl Write for Pipe {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+            let mut queue = self.output.lock().unwrap();
+            queue.0.extend(buf);
+            if let Some(waker) = queue.1.take() {
+                waker.wake();
+            }
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn connect(client: Http2Settings, server: Http2Settings) -> (Http2Connection<Pipe>, Http2Connection<Pipe>) {
+        let (first, second) = duplex();
+        let mut client = Http2Connection::handshake(first, Http2Role::Client, client).await.unwrap();
+        let mut server = Http2Connection::handshake(second, Http2Role::Server, server).await.unwrap();
+        assert!(matches!(client.read_event().await.unwrap(), Http2Event::Settings(_)));
+        assert!(matches!(server.read_event().await.unwrap(), Http2Event::Settings(_)));
+        assert_eq!(server.read_event().await.unwrap(), Http2Event::SettingsAcknowledged);
+        assert_eq!(client.read_event().await.unwrap(), Http2Event::SettingsAcknowledged);
+        (client, server)
+    }
+
+    #[test]
+    fn maps_pseudo_headers() {
+        let request = Http2Request::new(Method::Get, "https", "example.com", "/?q=1").with_header("Accept", "*/*");
+        let fields = request.to_fields();
+        assert_eq!(fields[0], HeaderField::new(":method", "GET"));
+        assert_eq!(fields[4], HeaderField::new("accept", "*/*"));
+        assert_eq!(Http2Request::from_fields(1, fields).unwrap().path, Some("/?q=1".to_string()));
+
+        let connect = vec![HeaderField::new(":method", "CONNECT"), HeaderField::new(":authority", "example.com:443")];
+        assert_eq!(Http2Request::from_fields(1, connect).unwrap().method, Method::Connect);
+        let invalid = [
+            vec![HeaderField::new(":method", "GET"), HeaderField::new(":scheme", "https")],
+            vec![HeaderField::new(":method", "GET"), HeaderField::new(":scheme", "https"), HeaderField::new(":path", "/"), HeaderField::new(":path", "/")],
+            vec![HeaderField::new(":method", "GET"), HeaderField::new("accept", "*/*"), HeaderField::new(":scheme", "https"), HeaderField::new(":path", "/")],
+            vec![HeaderField::new(":method", "GET"), HeaderField::new(":scheme", "https"), HeaderField::new(":path", "/"), HeaderField::new("Accept", "*/*")],
+            vec![HeaderField::new(":method", "GET"), HeaderField::new(":scheme", "https"), HeaderField::new(":path", "/"), HeaderField::new("connection", "close")],
+            vec![HeaderField::new(":method", "GET"), HeaderField::new(":scheme", "https"), HeaderField::new(":path", "/"), HeaderField::new(":protocol", "x")],
+        ];
+        for fields in invalid.iter() {
+            let error = Http2Request::from_fields(3, fields.clone()).unwrap_err();
+            assert_eq!((error.stream_id(), error.code()), (3, Http2ErrorCode::ProtocolError));
+        }
+
+        let response = Http2Response::new(Status::NotFound).with_header("Content-Length", "0");
+        assert_eq!(response.to_fields(), vec![HeaderField::new(":status", "404"), HeaderField::new("content-length", "0")]);
+        assert_eq!(Http2Response::from_fields(1, response.to_fields()).unwrap(), Http2Response::new(Status::NotFound).with_header("content-length", "0"));
+        assert!(Http2Response::from_fields(1, vec![HeaderField::new(":status", "abc")]).is_err());
+    }
+
+    #[async_std::test]
+    async fn exchanges_request_and_response() {
+        let settings = Http2Settings { max_concurrent_streams: Some(1), ..Http2Settings::new() };
+        let (mut client, mut server) = connect(Http2Settings::new(), settings).await;
+
+        let request = Http2Request::new(Method::Get, "https", "example.com", "/index.html");
+        assert_eq!(client.send_request(&request, true).await.unwrap(), 1);
+        assert_eq!(client.stream_state(1), StreamState::HalfClosedLocal);
+        assert!(client.send_request(&request, true).await.is_err());
+        assert_eq!(server.read_event().await.unwrap(), Http2Event::Request { stream_id: 1, request, end_stream: true });
+        assert_eq!(server.stream_state(1), StreamState::HalfClosedRemote);
+
+        server.send_response(1, &Http2Response::new(Status::Continue), true).await.unwrap();
+        server.send_response(1, &Http2Response::new(Status::Ok), false).await.unwrap();
+        assert_eq!(server.send_data(1, b"hello", false).await.unwrap(), 5);
+        server.send_trailers(1, &[HeaderField::new("Grpc-Status", "0")]).await.unwrap();
+        assert_eq!(server.stream_state(1), StreamState::Closed);
+
+        let expected = [
+            Http2Event::Response { stream_id: 1, response: Http2Response::new(Status::Continue), end_stream: false },
+            Http2Event::Response { stream_id: 1, response: Http2Response::new(Status::Ok), end_stream: false },
+            Http2Event::Data { stream_id: 1, data: b"hello".to_vec(), end_stream: false },
+            Http2Event::Trailers { stream_id: 1, headers: vec![HeaderField::new("grpc-status", "0")] },
+        ];
+        for event in expected.iter() {
+            assert_eq!(&client.read_event().await.unwrap(), event);
+        }
+        assert_eq!(client.stream_state(1), StreamState::Closed);
+        assert_eq!(client.send_request(&Http2Request::new(Method::Get, "https", "example.com", "/"), false).await.unwrap(), 3);
+    }
+
+    #[async_std::test]
+    async fn exhausts_stream_identifiers() {
+        let (mut client, _server) = connect(Http2Settings::new(), Http2Settings::new()).await;
+        client.next_stream_id = 0x7fff_ffff;
+        let request = Http2Request::new(Method::Get, "https", "example.com", "/");
+        assert_eq!(client.send_request(&request, false).await.unwrap(), 0x7fff_ffff);
+        assert_eq!(client.send_request(&request, false).await.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[async_std::test]
+    async fn respects_flow_control() {
+        let settings = Http2Settings { initial_window_size: Some(4), max_frame_size: Some(HTTP2_MAX_FRAME_SIZE), ..Http2Settings::new() };
+        let (mut client, mut server) = connect(settings, Http2Settings::new()).await;
+
+        client.send_request(&Http2Request::new(Method::Get, "https", "example.com", "/"), true).await.unwrap();
+        server.read_event().await.unwrap();
+        server.send_response(1, &Http2Response::new(Status::Ok), false).await.unwrap();
+        assert_eq!(server.send_capacity(1), 4);
+        assert_eq!(server.send_data(1, b"hello world", true).await.unwrap(), 4);
+        assert_eq!(server.send_data(1, b"o world", true).await.unwrap(), 0);
+
+        client.read_event().await.unwrap();
+        assert_eq!(client.read_event().await.unwrap(), Http2Event::Data { stream_id: 1, data: b"hell".to_vec(), end_stream: false });
+        assert_eq!(server.read_event().await.unwrap(), Http2Event::WindowUpdate { stream_id: 1 });
+        assert_eq!(server.send_data(1, b"o world", true).await.unwrap(), 4);
+        assert_eq!(server.stream_state(1), StreamState::HalfClosedRemote);
+    }
+
+    #[async_std::test]
+    async fn rejects_window_violations() {
+        let (first, second) = duplex();
+        let mut raw = first.clone();
+        let mut client = Http2Connection::handshake(first, Http2Role::Client, Http2Settings::new()).await.unwrap();
+        let mut server = Http2Connection::handshake(second, Http2Role::Server, Http2Settings { initial_window_size: Some(4), ..Http2Settings::new() }).await.unwrap();
+        assert!(matches!(client.read_event().await.unwrap(), Http2Event::Settings(_)));
+        assert!(matches!(server.read_event().await.unwrap(), Http2Event::Settings(_)));
+        assert_eq!(server.read_event().await.unwrap(), Http2Event::SettingsAcknowledged);
+
+        client.send_request(&Http2Request::new(Method::Post, "https", "example.com", "/"), false).await.unwrap();
+        assert!(matches!(server.read_event().await.unwrap(), Http2Event::Request { stream_id: 1, .. }));
+        write_http2_frame(&mut raw, &Http2Frame::Data { stream_id: 1, data: b"hello".to_vec(), end_stream: false, padding: None }).await.unwrap();
+        assert_eq!(server.read_event().await.unwrap(), Http2Event::Reset { stream_id: 1, error: Http2ErrorCode::FlowControlError });
+        assert_eq!(server.stream_state(1), StreamState::Closed);
+
+        write_http2_frame(&mut raw, &Http2Frame::Data { stream_id: 5, data: vec![], end_stream: true, padding: None }).await.unwrap();
+        let error = server.read_event().await.unwrap_err();
+        assert_eq!(Http2Error::from_io(&error).unwrap().code(), Http2ErrorCode::ProtocolError);
+        assert_eq!(client.read_event().await.unwrap(), Http2Event::SettingsAcknowledged);
+        assert_eq!(client.read_event().await.unwrap(), Http2Event::Reset { stream_id: 1, error: Http2ErrorCode::FlowControlError });
+        assert!(matches!(client.read_event().await.unwrap(), Http2Event::GoAway { last_stream_id: 1, error: Http2ErrorCode::ProtocolError, .. }));
+    }
+
+    #[async_std::test]
+    async fn rejects_continuation_flood() {
+        let (first, second) = duplex();
+        let mut raw = first.clone();
+        let mut client = Http2Connection::handshake(first, Http2Role::Client, Http2Settings::new()).await.unwrap();
+        let mut server = Http2Connection::handshake(second, Http2Role::Server, Http2Settings { max_header_list_size: Some(100), ..Http2Settings::new() }).await.unwrap();
+        assert!(matches!(client.read_event().await.unwrap(), Http2Event::Settings(_)));
+        assert!(matches!(server.read_event().await.unwrap(), Http2Event::Settings(_)));
+
+        let headers = Http2Frame::Headers { stream_id: 1, block: vec![0x82], end_stream: true, end_headers: false, priority: None, padding: None };
+        write_http2_frame(&mut raw, &headers).await.unwrap();
+        for _ in 0..3 {
+            write_http2_frame(&mut raw, &Http2Frame::Continuation { stream_id: 1, block: vec![0; 40], end_headers: false }).await.unwrap();
+        }
+        assert_eq!(server.read_event().await.unwrap(), Http2Event::SettingsAcknowledged);
+        let error = server.read_event().await.unwrap_err();
+        assert_eq!(Http2Error::from_io(&error).unwrap().code(), Http2ErrorCode::EnhanceYourCalm);
+    }
+
+    #[async_std::test]
+    async fn handles_ping_and_goaway() {
+        let (mut client, mut server) = connect(Http2Settings::new(), Http2Settings::new()).await;
+
+        client.send_ping(*b"12345678").await.unwrap();
+        assert_eq!(server.read_event().await.unwrap(), Http2Event::Ping(*b"12345678"));
+        assert_eq!(client.read_event().await.unwrap(), Http2Event::Pong(*b"12345678"));
+
+        client.send_request(&Http2Request::new(Method::Get, "https", "example.com", "/"), true).await.unwrap();
+        server.read_event().await.unwrap();
+        server.send_goaway(Http2ErrorCode::NoError, b"bye").await.unwrap();
+        let expected = Http2Event::GoAway { last_stream_id: 1, error: Http2ErrorCode::NoError, debug: b"bye".to_vec() };
+        assert_eq!(client.read_event().await.unwrap(), expected);
+        assert!(client.send_request(&Http2Request::new(Method::Get, "https", "example.com", "/"), true).await.is_err());
+        server.send_response(1, &Http2Response::new(Status::NoContent), true).await.unwrap();
+        assert!(matches!(client.read_event().await.unwrap(), Http2Event::Response { stream_id: 1, end_stream: true, .. }));
+    }
+
+    #[async_std::test]
+    async fn peeks_preface() {
+        let mut input = "GET / HTTP/1.1\r\n\r\n".as_bytes();
+        assert_eq!(peek_http2_preface(&mut input).await.unwrap(), (Version::Http1_1, b"GET / HTTP/1.1\r\n\r\n".to_vec()));
+        let mut input = "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\0\0".as_bytes();
+        assert_eq!(peek_http2_preface(&mut input).await.unwrap(), (Version::Http2_0, HTTP2_PREFACE.to_vec()));
+        assert_eq!(input, b"\0\0");
+        let mut input = "PRI * HTTP".as_bytes();
+        assert_eq!(peek_http2_preface(&mut input).await.unwrap(), (Version::Http1_1, b"PRI * HTTP".to_vec()));
+
+        let (mut first, mut second) = duplex();
+        first.write_all(b"POST /").await.unwrap();
+        assert_eq!(peek_http2_preface(&mut second).await.unwrap(), (Version::Http1_1, b"POST /".to_vec()));
+    }
+
+    #[test]
+    fn validates_h2c_request() {
+        let settings = Http2Settings { enable_push: Some(false), initial_window_size: Some(1 << 20), ..Http2Settings::new() };
+        let value = http2_settings_header(&settings);
+        assert!(!value.contains('='));
+        assert_eq!(parse_http2_settings_header(&value).unwrap(), settings);
+        assert!(parse_http2_settings_header("AAM").is_err());
+
+        let headers = |connection: &str, values: &[&str]| {
+            let mut headers = vec![("Host".to_string(), "a".to_string()), ("Connection".to_string(), connection.to_string()), ("Upgrade".to_string(), "h2c".to_string())];
+            headers.extend(values.iter().map(|v| ("HTTP2-Settings".to_string(), v.to_string())));
+            headers
+        };
+        assert_eq!(validate_h2c_request(Version::Http1_1, &headers("Upgrade, HTTP2-Settings", &[&value])), Some(settings));
+        assert_eq!(validate_h2c_request(Version::Http1_1, &headers("Upgrade, HTTP2-Settings", &[""])), Some(Http2Settings::new()));
+        assert_eq!(validate_h2c_request(Version::Http1_1, &headers("Upgrade", &[&value])), None);
+        assert_eq!(validate_h2c_request(Version::Http1_1, &headers("Upgrade, HTTP2-Settings", &[])), None);
+        assert_eq!(validate_h2c_request(Version::Http1_1, &headers("Upgrade, HTTP2-Settings", &[&value, &value])), None);
+        assert_eq!(validate_h2c_request(Version::Http1_0, &headers("Upgrade, HTTP2-Settings", &[&value])), None);
+    }
+
+    #[async_std::test]
+    async fn upgrades_to_h2c() {
+        let (first, second) = duplex();
+        let (mut client_io, mut server_io) = (first.clone(), second.clone());
+        let settings = Http2Settings { initial_window_size: Some(8), ..Http2Settings::new() };
+
+        write_h2c_request(&mut client_io, "/", "example.com", &settings).await.unwrap();
+        let (mut method, mut target, mut version, mut headers) = (vec![], vec![], vec![], vec![]);
+        read_first_line(&mut server_io, (&mut method, &mut target, &mut version), &Limits::default()).await.unwrap();
+        read_headers(&mut server_io, &mut headers, &Limits::default()).await.unwrap();
+        let client_settings = validate_h2c_request(Version::Http1_1, &headers).unwrap();
+        write_switching_protocols(&mut server_io, "h2c", &[]).await.unwrap();
+
+        assert_eq!(read_upgrade_response(&mut client_io, &["h2c"], &Limits::default()).await.unwrap().0, Status::SwitchingProtocols);
+        let mut client = Http2Connection::upgrade_client(first, settings).await.unwrap();
+        let mut server = Http2Connection::upgrade_server(second, Http2Settings::new(), client_settings).await.unwrap();
+        assert_eq!(client.stream_state(1), StreamState::HalfClosedLocal);
+        assert_eq!(server.stream_state(1), StreamState::HalfClosedRemote);
+
+        server.send_response(1, &Http2Response::new(Status::Ok), false).await.unwrap();
+        assert_eq!(server.send_data(1, b"hello world", true).await.unwrap(), 8);
+        assert!(matches!(client.read_event().await.unwrap(), Http2Event::Settings(_)));
+        assert!(matches!(client.read_event().await.unwrap(), Http2Event::Response { stream_id: 1, .. }));
+        assert_eq!(client.read_event().await.unwrap(), Http2Event::Data { stream_id: 1, data: b"hello wo".to_vec(), end_stream: false });
+        assert!(matches!(server.read_event().await.unwrap(), Http2Event::Settings(_)));
+        assert_eq!(client.send_request(&Http2Request::new(Method::Get, "http", "example.com", "/"), true).await.unwrap(), 3);
+    }
+
+    #[async_std::test]
+    async fn rejects_invalid_preface() {
+        let (mut first, second) = duplex();
+        first.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").await.unwrap();
+        let error = Http2Connection::handshake(second, Http2Role::Server, Http2Settings::new()).await.unwrap_err();
+        assert_eq!(Http2Error::from_io(&error).unwrap().code(), Http2ErrorCode::ProtocolError);
+    }
+}
@@ -0,0 +1,623 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+use async_std::prelude::*;
+use async_std::io::{Read, Write};
+use crate::{read_exact};
+
+/// The maximum frame payload size until the peer announces a larger one.
+pub const HTTP2_MAX_FRAME_SIZE: u32 = 16_384;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+/// Error codes of `RST_STREAM` and `GOAWAY` frames.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9113#section-7) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Http2ErrorCode {
+    NoError,
+    ProtocolError,
+    InternalError,
+    FlowControlError,
+    SettingsTimeout,
+    StreamClosed,
+    FrameSizeError,
+    RefusedStream,
+    Cancel,
+    CompressionError,
+    ConnectError,
+    EnhanceYourCalm,
+    InadequateSecurity,
+    Http11Required,
+    Unknown(u32),
+}
+
+impl From<u32> for Http2ErrorCode {
+    fn from(code: u32) -> Self {
+        match code {
+            0x0 => Http2ErrorCode::NoError,
+            0x1 => Http2ErrorCode::ProtocolError,
+            0x2 => Http2ErrorCode::InternalError,
+            0x3 => Http2ErrorCode::FlowControlError,
+            0x4 => Http2ErrorCode::SettingsTimeout,
+            0x5 => Http2ErrorCode::StreamClosed,
+            0x6 => Http2ErrorCode::FrameSizeError,
+            0x7 => Http2ErrorCode::RefusedStream,
+            0x8 => Http2ErrorCode::Cancel,
+            0x9 => Http2ErrorCode::CompressionError,
+            0xa => Http2ErrorCode::ConnectError,
+            0xb => Http2ErrorCode::EnhanceYourCalm,
+            0xc => Http2ErrorCode::InadequateSecurity,
+            0xd => Http2ErrorCode::Http11Required,
+            code => Http2ErrorCode::Unknown(code),
+        }
+    }
+}
+
+impl From<Http2ErrorCode> for u32 {
+    fn from(code: Http2ErrorCode) -> Self {
+        match code {
+            Http2ErrorCode::NoError => 0x0,
+            Http2ErrorCode::ProtocolError => 0x1,
+            Http2ErrorCode::InternalError => 0x2,
+            Http2ErrorCode::FlowControlError => 0x3,
+            Http2ErrorCode::SettingsTimeout => 0x4,
+            Http2ErrorCode::StreamClosed => 0x5,
+            Http2ErrorCode::FrameSizeError => 0x6,
+            Http2ErrorCode::RefusedStream => 0x7,
+            Http2ErrorCode::Cancel => 0x8,
+            Http2ErrorCode::CompressionError => 0x9,
+            Http2ErrorCode::ConnectError => 0xa,
+            Http2ErrorCode::EnhanceYourCalm => 0xb,
+            Http2ErrorCode::InadequateSecurity => 0xc,
+            Http2ErrorCode::Http11Required => 0xd,
+            Http2ErrorCode::Unknown(code) => code,
+        }
+    }
+}
+
+/// Violation of the HTTP/2 protocol.
+///
+/// Stream errors name the stream to reset while connection errors (stream `0`) require a
+/// `GOAWAY`. Returned inside an `std::io::Error` of kind `InvalidData` from where it can be
+/// recovered with `Http2Error::from_io`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2Error {
+    code: Http2ErrorCode,
+    stream_id: u32,
+    message: String,
+}
+
+impl Http2Error {
+
+    /// Creates an error which terminates the whole connection.
+    pub fn connection(code: Http2ErrorCode, message: &str) -> Self {
+        Self::stream(0, code, message)
+    }
+
+    /// Creates an error which only resets the stream.
+    pub fn stream(stream_id: u32, code: Http2ErrorCode, message: &str) -> Self {
+        Self { code, stream_id, message: message.to_string() }
+    }
+
+    pub fn code(&self) -> Http2ErrorCode {
+        self.code
+    }
+
+    /// The stream to reset or `0` for a connection error.
+    pub fn stream_id(&self) -> u32 {
+        self.stream_id
+    }
+
+    pub fn is_connection_error(&self) -> bool {
+        self.stream_id == 0
+    }
+
+    /// Returns the HTTP/2 error wrapped by an I/O error.
+    pub fn from_io(error: &Error) -> Option<&Http2Error> {
+        error.get_ref().and_then(|e| e.downcast_ref::<Http2Error>())
+    }
+}
+
+impl Display for Http2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Http2Error {}
+
+impl From<Http2Error> for Error {
+    fn from(error: Http2Error) -> Self {
+        Error::new(ErrorKind::InvalidData, error)
+    }
+}
+
+/// Parameters of a `SETTINGS` frame. Unknown settings are ignored.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9113#section-6.5.2) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Http2Settings {
+    pub header_table_size: Option<u32>,
+    pub enable_push: Option<bool>,
+    pub max_concurrent_streams: Option<u32>,
+    pub initial_window_size: Option<u32>,
+    pub max_frame_size: Option<u32>,
+    pub max_header_list_size: Option<u32>,
+}
+
+impl Http2Settings {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if no parameter is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Parses the payload of a `SETTINGS` frame.
+    pub fn decode(payload: &[u8]) -> Result<Self, Http2Error> {
+        if payload.len() % 6 != 0 {
+            return Err(Http2Error::connection(Http2ErrorCode::FrameSizeError, "The HTTP/2 SETTINGS frame length is invalid."));
+        }
+
+        let mut settings = Self::new();
+        for chunk in payload.chunks(6) {
+            let value = u32::from_be_bytes([chunk[2], chunk[3], chunk[4], chunk[5]]);
+            match u16::from_be_bytes([chunk[0], chunk[1]]) {
+                0x1 => settings.header_table_size = Some(value),
+                0x2 if value <= 1 => settings.enable_push = Some(value == 1),
+                0x2 => return Err(Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 SETTINGS_ENABLE_PUSH value is invalid.")),
+                0x3 => settings.max_concurrent_streams = Some(value),
+                0x4 if value <= i32::MAX as u32 => settings.initial_window_size = Some(value),
+                0x4 => return Err(Http2Error::connection(Http2ErrorCode::FlowControlError, "The HTTP/2 SETTINGS_INITIAL_WINDOW_SIZE value is invalid.")),
+                0x5 if (HTTP2_MAX_FRAME_SIZE..=0xff_ffff).contains(&value) => settings.max_frame_size = Some(value),
+                0x5 => return Err(Http2Error::connection(Http2ErrorCode::ProtocolError, "The HTTP/2 SETTINGS_MAX_FRAME_SIZE value is invalid.")),
+                0x6 => settings.max_header_list_size = Some(value),
+                _ => (),
+            };
+        }
+
+        Ok(settings)
+    }
+
+    /// Returns the payload of a `SETTINGS` frame holding the parameters which are set.
+    pub fn encode(&self) -> Vec<u8> {
+        let params = [
+            (0x1u16, self.header_table_size),
+            (0x2, self.enable_push.map(|push| push as u32)),
+            (0x3, self.max_concurrent_streams),
+            (0x4, self.initial_window_size),
+            (0x5, self.max_frame_size),
+            (0x6, self.max_header_list_size),
+        ];
+
+        let mut payload = Vec::new();
+        for (id, value) in params.iter() {
+            if let Some(value) = value {
+                payload.extend_from_slice(&id.to_be_bytes());
+                payload.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        payload
+    }
+}
+
+/// Stream dependency and weight of `HEADERS` and `PRIORITY` frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Http2Priority {
+    pub dependency: u32,
+    pub exclusive: bool,
+    pub weight: u8,
+}
+
+/// HTTP/2 frame.
+///
+/// The `padding` of `DATA`, `HEADERS` and `PUSH_PROMISE` frames is the number of padding
+/// bytes, if the frame is padded, and counts towards flow control.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9113#section-6) for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Http2Frame {
+    Data {
+        stream_id: u32,
+        data: Vec<u8>,
+        end_stream: bool,
+        padding: Option<u8>,
+    },
+    Headers {
+        stream_id: u32,
+        block: Vec<u8>,
+        end_stream: bool,
+        end_headers: bool,
+        priority: Option<Http2Priority>,
+        padding: Option<u8>,
+    },
+    Priority {
+        stream_id: u32,
+        priority: Http2Priority,
+    },
+    RstStream {
+        stream_id: u32,
+        error: Http2ErrorCode,
+    },
+    Settings {
+        ack: bool,
+        settings: Http2Settings,
+    },
+    PushPromise {
+        stream_id: u32,
+        promised_id: u32,
+        block: Vec<u8>,
+        end_headers: bool,
+        padding: Option<u8>,
+    },
+    Ping {
+        ack: bool,
+        data: [u8; 8],
+    },
+    GoAway {
+        last_stream_id: u32,
+        error: Http2ErrorCode,
+        debug: Vec<u8>,
+    },
+    WindowUpdate {
+        stream_id: u32,
+        increment: u32,
+    },
+    Continuation {
+        stream_id: u32,
+        block: Vec<u8>,
+        end_headers: bool,
+    },
+
+    /// Frame of an unknown type which must be ignored.
+    Unknown {
+        kind: u8,
+        flags: u8,
+        stream_id: u32,
+        payload: Vec<u8>,
+    },
+}
+
+impl Http2Frame {
+
+    /// Returns the stream the frame belongs to or `0` for connection frames.
+    pub fn stream_id(&self) -> u32 {
+        match self {
+            Http2Frame::Data { stream_id, .. }
+            | Http2Frame::Headers { stream_id, .. }
+            | Http2Frame::Priority { stream_id, .. }
+            | Http2Frame::RstStream { stream_id, .. }
+            | Http2Frame::PushPromise { stream_id, .. }
+            | Http2Frame::WindowUpdate { stream_id, .. }
+            | Http2Frame::Continuation { stream_id, .. }
+            | Http2Frame::Unknown { stream_id, .. } => *stream_id,
+            Http2Frame::Settings { .. } | Http2Frame::Ping { .. } | Http2Frame::GoAway { .. } => 0,
+        }
+    }
+
+    /// Returns the number of bytes the frame consumes from the flow control windows.
+    pub fn flow_len(&self) -> usize {
+        match self {
+            Http2Frame::Data { data, padding, .. } => data.len() + padding.map(|p| p as usize + 1).unwrap_or(0),
+            _ => 0,
+        }
+    }
+}
+
+/// Reads a frame and removes its padding.
+///
+/// Frames larger than `max_frame_size`, the value of the local `SETTINGS_MAX_FRAME_SIZE`,
+/// and malformed frames are rejected with an `Http2Error`.
+pub async fn read_http2_frame<I>(input: &mut I, max_frame_size: u32) -> Result<Http2Frame, Error>
+    where
+    I: Read + Unpin,
+{
+    let mut head = Vec::with_capacity(9);
+    read_exact(input, &mut head, 9).await?;
+    let length = u32::from_be_bytes([0, head[0], head[1], head[2]]);
+    let (kind, flags) = (head[3], head[4]);
+    let stream_id = u32::from_be_bytes([head[5], head[6], head[7], head[8]]) & 0x7fff_ffff;
+
+    if length > max_frame_size {
+        return Err(Http2Error::connection(Http2ErrorCode::FrameSizeError, "The HTTP/2 frame is larger than the maximum frame size.").into());
+    }
+    let mut payload = Vec::with_capacity(length as usize);
+    read_exact(input, &mut payload, length as usize).await?;
+
+    Ok(decode_frame(kind, flags, stream_id, payload)?)
+}
+
+/// Writes a frame with its padding. The payload must fit the peer's maximum frame size.
+pub async fn write_http2_frame<O>(output: &mut O, frame: &Http2Frame) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let (kind, mut flags, stream_id, payload) = encode_frame(frame);
+    let padding = match frame {
+        Http2Frame::Data { padding, .. } | Http2Frame::Headers { padding, .. } | Http2Frame::PushPromise { padding, .. } => *padding,
+        _ => None,
+    };
+
+    let mut body = Vec::with_capacity(payload.len() + 256);
+    if let Some(padding) = padding {
+        flags |= PADDED;
+        body.push(padding);
+    }
+    body.extend_from_slice(&payload);
+    body.resize(body.len() + padding.unwrap_or(0) as usize, 0);
+    if body.len() > 0xff_ffff {
+        return Err(Error::new(ErrorKind::InvalidInput, "The HTTP/2 frame payload is too large."));
+    }
+
+    let mut data = Vec::with_capacity(body.len() + 9);
+    data.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    data.push(kind);
+    data.push(flags);
+    data.extend_from_slice(&stream_id.to_be_bytes());
+    data.extend_from_slice(&body);
+
+    output.write_all(&data).await?;
+
+    Ok(data.len())
+}
+
+fn decode_frame(kind: u8, flags: u8, stream_id: u32, payload: Vec<u8>) -> Result<Http2Frame, Http2Error> {
+    let protocol = |message: &str| Http2Error::connection(Http2ErrorCode::ProtocolError, message);
+    let frame_size = |message: &str| Http2Error::connection(Http2ErrorCode::FrameSizeError, message);
+    let word = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+    let on_stream = matches!(kind, DATA | HEADERS | PRIORITY | RST_STREAM | PUSH_PROMISE | CONTINUATION);
+    let on_connection = matches!(kind, SETTINGS | PING | GOAWAY);
+    if on_stream && stream_id == 0 {
+        return Err(protocol("The HTTP/2 frame requires a stream."));
+    } else if on_connection && stream_id != 0 {
+        return Err(protocol("The HTTP/2 frame must not belong to a stream."));
+    }
+
+    let (padding, payload) = match kind {
+        DATA | HEADERS | PUSH_PROMISE if flags & PADDED != 0 => {
+            let padding = *payload.first().ok_or_else(|| frame_size("The HTTP/2 padded frame is empty."))?;
+            if padding as usize >= payload.len() {
+                return Err(protocol("The HTTP/2 frame padding exceeds the payload."));
+            }
+            (Some(padding), payload[1..payload.len() - padding as usize].to_vec())
+        },
+        _ => (None, payload),
+    };
+
+    let frame = match kind {
+        DATA => Http2Frame::Data {
+            stream_id,
+            data: payload,
+            end_stream: flags & END_STREAM != 0,
+            padding,
+        },
+        HEADERS => {
+            let (priority, block) = match flags & PRIORITY_FLAG != 0 {
+                true if payload.len() < 5 => return Err(frame_size("The HTTP/2 HEADERS frame is too short.")),
+                true => (Some(decode_priority(&payload)), payload[5..].to_vec()),
+                false => (None, payload),
+            };
+            Http2Frame::Headers {
+                stream_id,
+                block,
+                end_stream: flags & END_STREAM != 0,
+                end_headers: flags & END_HEADERS != 0,
+                priority,
+                padding,
+            }
+        },
+        PRIORITY => match payload.len() {
+            5 => Http2Frame::Priority { stream_id, priority: decode_priority(&payload) },
+            _ => return Err(Http2Error::stream(stream_id, Http2ErrorCode::FrameSizeError, "The HTTP/2 PRIORITY frame length is invalid.")),
+        },
+        RST_STREAM => match payload.len() {
+            4 => Http2Frame::RstStream { stream_id, error: word(&payload).into() },
+            _ => return Err(frame_size("The HTTP/2 RST_STREAM frame length is invalid.")),
+        },
+        SETTINGS => match flags & ACK != 0 {
+            true if !payload.is_empty() => return Err(frame_size("The HTTP/2 SETTINGS acknowledgement is not empty.")),
+            ack => Http2Frame::Settings { ack, settings: Http2Settings::decode(&payload)? },
+        },
+        PUSH_PROMISE => match payload.len() {
+            0..=3 => return Err(frame_size("The HTTP/2 PUSH_PROMISE frame is too short.")),
+            _ => Http2Frame::PushPromise {
+                stream_id,
+                promised_id: word(&payload) & 0x7fff_ffff,
+                block: payload[4..].to_vec(),
+                end_headers: flags & END_HEADERS != 0,
+                padding,
+            },
+        },
+        PING => match payload.len() {
+            8 => {
+                let mut data = [0u8; 8];
+                data.copy_from_slice(&payload);
+                Http2Frame::Ping { ack: flags & ACK != 0, data }
+            },
+            _ => return Err(frame_size("The HTTP/2 PING frame length is invalid.")),
+        },
+        GOAWAY => match payload.len() {
+            0..=7 => return Err(frame_size("The HTTP/2 GOAWAY frame is too short.")),
+            _ => Http2Frame::GoAway {
+                last_stream_id: word(&payload) & 0x7fff_ffff,
+                error: word(&payload[4..]).into(),
+                debug: payload[8..].to_vec(),
+            },
+        },
+        WINDOW_UPDATE => match payload.len() {
+            4 => match word(&payload) & 0x7fff_ffff {
+                0 => return Err(Http2Error::stream(stream_id, Http2ErrorCode::ProtocolError, "The HTTP/2 WINDOW_UPDATE increment is zero.")),
+                increment => Http2Frame::WindowUpdate { stream_id, increment },
+            },
+            _ => return Err(frame_size("The HTTP/2 WINDOW_UPDATE frame length is invalid.")),
+        },
+        CONTINUATION => Http2Frame::Continuation {
+            stream_id,
+            block: payload,
+            end_headers: flags & END_HEADERS != 0,
+        },
+        kind => Http2Frame::Unknown { kind, flags, stream_id, payload },
+    };
+
+    Ok(frame)
+}
+
+/// Returns the type, flags without `PADDED`, stream and unpadded payload of a frame.
+fn encode_frame(frame: &Http2Frame) -> (u8, u8, u32, Vec<u8>) {
+    let flag = |set: bool, flag: u8| if set { flag } else { 0 };
+
+    match frame {
+        Http2Frame::Data { stream_id, data, end_stream, .. } => (DATA, flag(*end_stream, END_STREAM), *stream_id, data.clone()),
+        Http2Frame::Headers { stream_id, block, end_stream, end_headers, priority, .. } => {
+            let mut payload = priority.map(|p| encode_priority(&p)).unwrap_or_default();
+            payload.extend_from_slice(block);
+            let flags = flag(*end_stream, END_STREAM) | flag(*end_headers, END_HEADERS) | flag(priority.is_some(), PRIORITY_FLAG);
+            (HEADERS, flags, *stream_id, payload)
+        },
+        Http2Frame::Priority { stream_id, priority } => (PRIORITY, 0, *stream_id, encode_priority(priority)),
+        Http2Frame::RstStream { stream_id, error } => (RST_STREAM, 0, *stream_id, u32::from(*error).to_be_bytes().to_vec()),
+        Http2Frame::Settings { ack, settings } => (SETTINGS, flag(*ack, ACK), 0, settings.encode()),
+        Http2Frame::PushPromise { stream_id, promised_id, block, end_headers, .. } => {
+            let mut payload = (promised_id & 0x7fff_ffff).to_be_bytes().to_vec();
+            payload.extend_from_slice(block);
+            (PUSH_PROMISE, flag(*end_headers, END_HEADERS), *stream_id, payload)
+        },
+        Http2Frame::Ping { ack, data } => (PING, flag(*ack, ACK), 0, data.to_vec()),
+        Http2Frame::GoAway { last_stream_id, error, debug } => {
+            let mut payload = (last_stream_id & 0x7fff_ffff).to_be_bytes().to_vec();
+            payload.extend_from_slice(&u32::from(*error).to_be_bytes());
+            payload.extend_from_slice(debug);
+            (GOAWAY, 0, 0, payload)
+        },
+        Http2Frame::WindowUpdate { stream_id, increment } => (WINDOW_UPDATE, 0, *stream_id, (increment & 0x7fff_ffff).to_be_bytes().to_vec()),
+        Http2Frame::Continuation { stream_id, block, end_headers } => (CONTINUATION, flag(*end_headers, END_HEADERS), *stream_id, block.clone()),
+        Http2Frame::Unknown { kind, flags, stream_id, payload } => (*kind, *flags & !PADDED, *stream_id, payload.clone()),
+    }
+}
+
+fn decode_priority(payload: &[u8]) -> Http2Priority {
+    let dependency = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+    Http2Priority {
+        dependency: dependency & 0x7fff_ffff,
+        exclusive: dependency & 0x8000_0000 != 0,
+        weight: payload[4],
+    }
+}
+
+fn encode_priority(priority: &Http2Priority) -> Vec<u8> {
+    let exclusive = if priority.exclusive { 0x8000_0000 } else { 0 };
+    let mut payload = (priority.dependency & 0x7fff_ffff | exclusive).to_be_bytes().to_vec();
+    payload.push(priority.weight);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Accepts a single byte per write.
+    struct ShortWriter(Vec<u8>);
+
+    impl Write for ShortWriter {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+            let size = buf.len().min(1);
+            self.get_mut().0.extend_from_slice(&buf[..size]);
+            Poll::Ready(Ok(size))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn round_trip(frame: Http2Frame) -> Http2Frame {
+        let mut output = ShortWriter(Vec::new());
+        write_http2_frame(&mut output, &frame).await.unwrap();
+        read_http2_frame(&mut output.0.as_slice(), HTTP2_MAX_FRAME_SIZE).await.unwrap()
+    }
+
+    async fn read_error(data: &[u8]) -> Http2Error {
+        let error = read_http2_frame(&mut &data[..], HTTP2_MAX_FRAME_SIZE).await.unwrap_err();
+        Http2Error::from_io(&error).unwrap().clone()
+    }
+
+    #[async_std::test]
+    async fn codes_all_frames() {
+        let priority = Http2Priority { dependency: 3, exclusive: true, weight: 15 };
+        let settings = Http2Settings { enable_push: Some(false), initial_window_size: Some(65_535), ..Http2Settings::new() };
+        let frames = vec![
+            Http2Frame::Data { stream_id: 1, data: b"hello".to_vec(), end_stream: true, padding: Some(3) },
+            Http2Frame::Headers { stream_id: 1, block: vec![0x82], end_stream: false, end_headers: true, priority: Some(priority), padding: None },
+            Http2Frame::Priority { stream_id: 5, priority },
+            Http2Frame::RstStream { stream_id: 1, error: Http2ErrorCode::Cancel },
+            Http2Frame::Settings { ack: false, settings },
+            Http2Frame::Settings { ack: true, settings: Http2Settings::new() },
+            Http2Frame::PushPromise { stream_id: 1, promised_id: 2, block: vec![0x82], end_headers: false, padding: Some(0) },
+            Http2Frame::Ping { ack: true, data: [1, 2, 3, 4, 5, 6, 7, 8] },
+            Http2Frame::GoAway { last_stream_id: 7, error: Http2ErrorCode::Unknown(0xff), debug: b"bye".to_vec() },
+            Http2Frame::WindowUpdate { stream_id: 0, increment: 1024 },
+            Http2Frame::Continuation { stream_id: 1, block: vec![0x84], end_headers: true },
+            Http2Frame::Unknown { kind: 0xfa, flags: 0x3, stream_id: 9, payload: vec![0] },
+        ];
+        for frame in frames.into_iter() {
+            assert_eq!(round_trip(frame.clone()).await, frame);
+        }
+    }
+
+    #[async_std::test]
+    async fn writes_wire_format() {
+        let mut output = Vec::new();
+        let frame = Http2Frame::Data { stream_id: 1, data: b"hi".to_vec(), end_stream: true, padding: Some(2) };
+        write_http2_frame(&mut output, &frame).await.unwrap();
+        assert_eq!(output, vec![0, 0, 5, 0, 0x9, 0, 0, 0, 1, 2, b'h', b'i', 0, 0]);
+        assert_eq!(frame.flow_len(), 5);
+        let mut output = Vec::new();
+        write_http2_frame(&mut output, &Http2Frame::Settings { ack: false, settings: Http2Settings { max_frame_size: Some(16_384), ..Http2Settings::new() } }).await.unwrap();
+        assert_eq!(output, vec![0, 0, 6, 4, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0x40, 0]);
+    }
+
+    #[async_std::test]
+    async fn rejects_invalid_frames() {
+        let error = read_error(&[0, 0x40, 0x01, 0, 0, 0, 0, 0, 1]).await;
+        assert_eq!(error.code(), Http2ErrorCode::FrameSizeError);
+        let error = read_error(&[0, 0, 1, 0, 0, 0, 0, 0, 0, 0]).await; // DATA on stream 0
+        assert_eq!(error.code(), Http2ErrorCode::ProtocolError);
+        let error = read_error(&[0, 0, 2, 0, 0x8, 0, 0, 0, 1, 2, 0]).await; // padding too long
+        assert_eq!(error.code(), Http2ErrorCode::ProtocolError);
+        let error = read_error(&[0, 0, 4, 2, 0, 0, 0, 0, 3, 0, 0, 0, 0]).await; // short PRIORITY
+        assert_eq!((error.code(), error.stream_id()), (Http2ErrorCode::FrameSizeError, 3));
+        let error = read_error(&[0, 0, 6, 4, 0, 0, 0, 0, 0, 0, 4, 0x80, 0, 0, 0]).await;
+        assert_eq!(error.code(), Http2ErrorCode::FlowControlError);
+        let error = read_error(&[0, 0, 6, 4, 0x1, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1]).await; // ACK with payload
+        assert_eq!(error.code(), Http2ErrorCode::FrameSizeError);
+        let error = read_error(&[0, 0, 4, 8, 0, 0, 0, 0, 1, 0, 0, 0, 0]).await;
+        assert_eq!((error.code(), error.stream_id()), (Http2ErrorCode::ProtocolError, 1));
+        let error = read_error(&[0, 0, 8, 6, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]).await; // PING on stream
+        assert!(error.is_connection_error());
+    }
+}
@@ -0,0 +1,556 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+use async_std::prelude::*;
+use async_std::io::{Read, Write};
+use crate::{read_exact};
+
+/// The largest value of a QUIC variable-length integer.
+pub const QUIC_VARINT_MAX: u64 = (1 << 62) - 1;
+
+const DATA: u64 = 0x0;
+const HEADERS: u64 = 0x1;
+const CANCEL_PUSH: u64 = 0x3;
+const SETTINGS: u64 = 0x4;
+const PUSH_PROMISE: u64 = 0x5;
+const GOAWAY: u64 = 0x7;
+const MAX_PUSH_ID: u64 = 0xd;
+
+/// Error codes of HTTP/3 stream resets and connection closes, including those of QPACK.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9114#section-8.1) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Http3ErrorCode {
+    NoError,
+    GeneralProtocolError,
+    InternalError,
+    StreamCreationError,
+    ClosedCriticalStream,
+    FrameUnexpected,
+    FrameError,
+    ExcessiveLoad,
+    IdError,
+    SettingsError,
+    MissingSettings,
+    RequestRejected,
+    RequestCancelled,
+    RequestIncomplete,
+    MessageError,
+    ConnectError,
+    VersionFallback,
+    QpackDecompressionFailed,
+    QpackEncoderStreamError,
+    QpackDecoderStreamError,
+    Unknown(u64),
+}
+
+impl From<u64> for Http3ErrorCode {
+    fn from(code: u64) -> Self {
+        match code {
+            0x100 => Http3ErrorCode::NoError,
+            0x101 => Http3ErrorCode::GeneralProtocolError,
+            0x102 => Http3ErrorCode::InternalError,
+            0x103 => Http3ErrorCode::StreamCreationError,
+            0x104 => Http3ErrorCode::ClosedCriticalStream,
+            0x105 => Http3ErrorCode::FrameUnexpected,
+            0x106 => Http3ErrorCode::FrameError,
+            0x107 => Http3ErrorCode::ExcessiveLoad,
+            0x108 => Http3ErrorCode::IdError,
+            0x109 => Http3ErrorCode::SettingsError,
+            0x10a => Http3ErrorCode::MissingSettings,
+            0x10b => Http3ErrorCode::RequestRejected,
+            0x10c => Http3ErrorCode::RequestCancelled,
+            0x10d => Http3ErrorCode::RequestIncomplete,
+            0x10e => Http3ErrorCode::MessageError,
+            0x10f => Http3ErrorCode::ConnectError,
+            0x110 => Http3ErrorCode::VersionFallback,
+            0x200 => Http3ErrorCode::QpackDecompressionFailed,
+            0x201 => Http3ErrorCode::QpackEncoderStreamError,
+            0x202 => Http3ErrorCode::QpackDecoderStreamError,
+            code => Http3ErrorCode::Unknown(code),
+        }
+    }
+}
+
+impl From<Http3ErrorCode> for u64 {
+    fn from(code: Http3ErrorCode) -> Self {
+        match code {
+            Http3ErrorCode::NoError => 0x100,
+            Http3ErrorCode::GeneralProtocolError => 0x101,
+            Http3ErrorCode::InternalError => 0x102,
+            Http3ErrorCode::StreamCreationError => 0x103,
+            Http3ErrorCode::ClosedCriticalStream => 0x104,
+            Http3ErrorCode::FrameUnexpected => 0x105,
+            Http3ErrorCode::FrameError => 0x106,
+            Http3ErrorCode::ExcessiveLoad => 0x107,
+            Http3ErrorCode::IdError => 0x108,
+            Http3ErrorCode::SettingsError => 0x109,
+            Http3ErrorCode::MissingSettings => 0x10a,
+            Http3ErrorCode::RequestRejected => 0x10b,
+            Http3ErrorCode::RequestCancelled => 0x10c,
+            Http3ErrorCode::RequestIncomplete => 0x10d,
+            Http3ErrorCode::MessageError => 0x10e,
+            Http3ErrorCode::ConnectError => 0x10f,
+            Http3ErrorCode::VersionFallback => 0x110,
+            Http3ErrorCode::QpackDecompressionFailed => 0x200,
+            Http3ErrorCode::QpackEncoderStreamError => 0x201,
+            Http3ErrorCode::QpackDecoderStreamError => 0x202,
+            Http3ErrorCode::Unknown(code) => code,
+        }
+    }
+}
+
+/// Violation of the HTTP/3 protocol carrying the code to reset the stream or close the
+/// connection with.
+///
+/// Returned inside an `std::io::Error` of kind `InvalidData` from where it can be recovered
+/// with `Http3Error::from_io`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http3Error {
+    code: Http3ErrorCode,
+    message: String,
+}
+
+impl Http3Error {
+
+    pub fn new(code: Http3ErrorCode, message: &str) -> Self {
+        Self { code, message: message.to_string() }
+    }
+
+    pub fn code(&self) -> Http3ErrorCode {
+        self.code
+    }
+
+    /// Returns the HTTP/3 error wrapped by an I/O error.
+    pub fn from_io(error: &Error) -> Option<&Http3Error> {
+        error.get_ref().and_then(|e| e.downcast_ref::<Http3Error>())
+    }
+}
+
+impl Display for Http3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Http3Error {}
+
+impl From<Http3Error> for Error {
+    fn from(error: Http3Error) -> Self {
+        Error::new(ErrorKind::InvalidData, error)
+    }
+}
+
+/// Type of a unidirectional stream, sent as its first varint. Push streams continue with the
+/// push ID.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9114#section-6.2) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Http3StreamType {
+    Control,
+    Push,
+    QpackEncoder,
+    QpackDecoder,
+    Unknown(u64),
+}
+
+impl From<u64> for Http3StreamType {
+    fn from(kind: u64) -> Self {
+        match kind {
+            0x0 => Http3StreamType::Control,
+            0x1 => Http3StreamType::Push,
+            0x2 => Http3StreamType::QpackEncoder,
+            0x3 => Http3StreamType::QpackDecoder,
+            kind => Http3StreamType::Unknown(kind),
+        }
+    }
+}
+
+impl From<Http3StreamType> for u64 {
+    fn from(kind: Http3StreamType) -> Self {
+        match kind {
+            Http3StreamType::Control => 0x0,
+            Http3StreamType::Push => 0x1,
+            Http3StreamType::QpackEncoder => 0x2,
+            Http3StreamType::QpackDecoder => 0x3,
+            Http3StreamType::Unknown(kind) => kind,
+        }
+    }
+}
+
+/// Parameters of a `SETTINGS` frame. Unknown and reserved settings are ignored.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9114#section-7.2.4) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Http3Settings {
+    pub qpack_max_table_capacity: Option<u64>,
+    pub max_field_section_size: Option<u64>,
+    pub qpack_blocked_streams: Option<u64>,
+}
+
+impl Http3Settings {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the payload of a `SETTINGS` frame. Settings must not repeat and the identifiers
+    /// of HTTP/2 settings are rejected.
+    pub fn decode(payload: &[u8]) -> Result<Self, Http3Error> {
+        let invalid = |message: &str| Http3Error::new(Http3ErrorCode::SettingsError, message);
+        let mut settings = Self::new();
+        let mut seen = Vec::new();
+        let mut index = 0;
+
+        while index < payload.len() {
+            let id = decode_varint(payload, &mut index).ok_or_else(|| Http3Error::new(Http3ErrorCode::FrameError, "The HTTP/3 SETTINGS frame is truncated."))?;
+            let value = decode_varint(payload, &mut index).ok_or_else(|| Http3Error::new(Http3ErrorCode::FrameError, "The HTTP/3 SETTINGS frame is truncated."))?;
+            if seen.contains(&id) {
+                return Err(invalid("The HTTP/3 setting is repeated."));
+            }
+            seen.push(id);
+
+            match id {
+                0x1 => settings.qpack_max_table_capacity = Some(value),
+                0x6 => settings.max_field_section_size = Some(value),
+                0x7 => settings.qpack_blocked_streams = Some(value),
+                0x0 | 0x2..=0x5 => return Err(invalid("The HTTP/3 setting is reserved for HTTP/2.")),
+                _ => (),
+            };
+        }
+
+        Ok(settings)
+    }
+
+    /// Returns the payload of a `SETTINGS` frame holding the parameters which are set.
+    pub fn encode(&self) -> Vec<u8> {
+        let params = [
+            (0x1, self.qpack_max_table_capacity),
+            (0x6, self.max_field_section_size),
+            (0x7, self.qpack_blocked_streams),
+        ];
+
+        let mut payload = Vec::new();
+        for (id, value) in params.iter() {
+            if let Some(value) = value {
+                push_varint(*id, &mut payload);
+                push_varint(*value, &mut payload);
+            }
+        }
+        payload
+    }
+}
+
+/// HTTP/3 frame.
+///
+/// Frame types reserved for extensions and greasing are read as `Unknown` frames which must be
+/// ignored.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9114#section-7.2) for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Http3Frame {
+    Data {
+        data: Vec<u8>,
+    },
+    Headers {
+        block: Vec<u8>,
+    },
+    CancelPush {
+        push_id: u64,
+    },
+    Settings(Http3Settings),
+    PushPromise {
+        push_id: u64,
+        block: Vec<u8>,
+    },
+    /// The last stream ID (servers) or push ID (clients) which may still be processed.
+    GoAway {
+        id: u64,
+    },
+    MaxPushId {
+        push_id: u64,
+    },
+    Unknown {
+        kind: u64,
+        payload: Vec<u8>,
+    },
+}
+
+/// Appends a QUIC variable-length integer in its shortest encoding. Fails for values above
+/// `QUIC_VARINT_MAX`.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9000#section-16) for more details.
+pub fn encode_varint(value: u64, output: &mut Vec<u8>) -> Result<(), Error> {
+    if value > QUIC_VARINT_MAX {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("The value {} does not fit a QUIC varint.", value)));
+    }
+    push_varint(value, output);
+    Ok(())
+}
+
+/// Reads a QUIC variable-length integer starting at `index` and moves past it. Returns `None`
+/// if the data ends before the integer.
+pub fn decode_varint(data: &[u8], index: &mut usize) -> Option<u64> {
+    let length = 1 << (data.get(*index)? >> 6);
+    let bytes = data.get(*index..*index + length)?;
+    *index += length;
+    Some(bytes[1..].iter().fold(bytes[0] as u64 & 0x3f, |value, byte| value << 8 | *byte as u64))
+}
+
+/// Reads a QUIC variable-length integer.
+pub async fn read_varint<I>(input: &mut I) -> Result<u64, Error>
+    where
+    I: Read + Unpin,
+{
+    let mut data = Vec::with_capacity(8);
+    read_exact(input, &mut data, 1).await?;
+    let length = 1 << (data[0] >> 6);
+    read_exact(input, &mut data, length - 1).await?;
+
+    Ok(decode_varint(&data, &mut 0).unwrap_or(0))
+}
+
+/// Writes a QUIC variable-length integer. Fails for values above `QUIC_VARINT_MAX`.
+pub async fn write_varint<O>(output: &mut O, value: u64) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let mut data = Vec::with_capacity(8);
+    encode_varint(value, &mut data)?;
+
+    output.write_all(&data).await?;
+
+    Ok(data.len())
+}
+
+/// Reads the type which starts a unidirectional stream.
+pub async fn read_stream_type<I>(input: &mut I) -> Result<Http3StreamType, Error>
+    where
+    I: Read + Unpin,
+{
+    Ok(read_varint(input).await?.into())
+}
+
+/// Writes the type which starts a unidirectional stream.
+pub async fn write_stream_type<O>(output: &mut O, kind: Http3StreamType) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    write_varint(output, kind.into()).await
+}
+
+/// Reads the next frame of a stream or returns `None` if the stream ends before it.
+///
+/// Frames larger than `limit` bytes are rejected with `H3_EXCESSIVE_LOAD`, frames which end
+/// early or whose payload is malformed with `H3_FRAME_ERROR` and frame types which only
+/// exist in HTTP/2 with `H3_FRAME_UNEXPECTED`. Which frames may appear on which stream is
+/// left to the caller.
+pub async fn read_http3_frame<I>(input: &mut I, limit: Option<usize>) -> Result<Option<Http3Frame>, Error>
+    where
+    I: Read + Unpin,
+{
+    let truncated = || Http3Error::new(Http3ErrorCode::FrameError, "The HTTP/3 frame is truncated.");
+    let mut first = [0u8];
+    if input.read(&mut first).await? == 0 {
+        return Ok(None);
+    }
+
+    let mut head = first.to_vec();
+    read_exact(input, &mut head, (1 << (first[0] >> 6)) - 1).await.map_err(|_| truncated())?;
+    let kind = decode_varint(&head, &mut 0).unwrap_or(0);
+    let length = read_varint(input).await.map_err(|_| truncated())?;
+
+    if let Some(limit) = limit.filter(|limit| length > *limit as u64) {
+        return Err(Http3Error::new(Http3ErrorCode::ExcessiveLoad, &format!("The operation hit the limit of {} bytes while reading the HTTP/3 frame.", limit)).into());
+    } else if length > usize::MAX as u64 {
+        return Err(Http3Error::new(Http3ErrorCode::ExcessiveLoad, "The HTTP/3 frame is too large for this platform.").into());
+    }
+    let mut payload = Vec::new();
+    read_exact(input, &mut payload, length as usize).await.map_err(|_| truncated())?;
+
+    Ok(Some(decode_frame(kind, payload)?))
+}
+
+/// Writes a frame with its type and length.
+pub async fn write_http3_frame<O>(output: &mut O, frame: &Http3Frame) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let (kind, payload) = encode_frame(frame);
+    let mut data = Vec::with_capacity(payload.len() + 16);
+    encode_varint(kind, &mut data)?;
+    encode_varint(payload.len() as u64, &mut data)?;
+    data.extend_from_slice(&payload);
+
+    output.write_all(&data).await?;
+
+    Ok(data.len())
+}
+
+fn decode_frame(kind: u64, payload: Vec<u8>) -> Result<Http3Frame, Http3Error> {
+    let id = |payload: &[u8]| {
+        let mut index = 0;
+        match decode_varint(payload, &mut index) {
+            Some(id) if index == payload.len() => Ok(id),
+            _ => Err(Http3Error::new(Http3ErrorCode::FrameError, "The HTTP/3 frame payload is invalid.")),
+        }
+    };
+
+    Ok(match kind {
+        DATA => Http3Frame::Data { data: payload },
+        HEADERS => Http3Frame::Headers { block: payload },
+        CANCEL_PUSH => Http3Frame::CancelPush { push_id: id(&payload)? },
+        SETTINGS => Http3Frame::Settings(Http3Settings::decode(&payload)?),
+        PUSH_PROMISE => {
+            let mut index = 0;
+            let push_id = decode_varint(&payload, &mut index)
+                .ok_or_else(|| Http3Error::new(Http3ErrorCode::FrameError, "The HTTP/3 frame payload is invalid."))?;
+            Http3Frame::PushPromise { push_id, block: payload[index..].to_vec() }
+        },
+        GOAWAY => Http3Frame::GoAway { id: id(&payload)? },
+        MAX_PUSH_ID => Http3Frame::MaxPushId { push_id: id(&payload)? },
+        0x2 | 0x6 | 0x8 | 0x9 => return Err(Http3Error::new(Http3ErrorCode::FrameUnexpected, "The HTTP/2 frame type is not allowed in HTTP/3.")),
+        kind => Http3Frame::Unknown { kind, payload },
+    })
+}
+
+fn encode_frame(frame: &Http3Frame) -> (u64, Vec<u8>) {
+    let id = |id: u64| {
+        let mut payload = Vec::with_capacity(8);
+        push_varint(id, &mut payload);
+        payload
+    };
+
+    match frame {
+        Http3Frame::Data { data } => (DATA, data.clone()),
+        Http3Frame::Headers { block } => (HEADERS, block.clone()),
+        Http3Frame::CancelPush { push_id } => (CANCEL_PUSH, id(*push_id)),
+        Http3Frame::Settings(settings) => (SETTINGS, settings.encode()),
+        Http3Frame::PushPromise { push_id, block } => {
+            let mut payload = id(*push_id);
+            payload.extend_from_slice(block);
+            (PUSH_PROMISE, payload)
+        },
+        Http3Frame::GoAway { id: last } => (GOAWAY, id(*last)),
+        Http3Frame::MaxPushId { push_id } => (MAX_PUSH_ID, id(*push_id)),
+        Http3Frame::Unknown { kind, payload } => (*kind, payload.clone()),
+    }
+}
+
+/// Appends a varint whose value is known to fit.
+fn push_varint(value: u64, output: &mut Vec<u8>) {
+    let value = value.min(QUIC_VARINT_MAX);
+    let (length, tag) = match value {
+        0..=0x3f => (1, 0x00),
+        0x40..=0x3fff => (2, 0x40),
+        0x4000..=0x3fff_ffff => (4, 0x80),
+        _ => (8, 0xc0),
+    };
+    let bytes = value.to_be_bytes();
+    output.push(bytes[8 - length] | tag);
+    output.extend_from_slice(&bytes[9 - length..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Accepts a single byte per write.
+    struct ShortWriter(Vec<u8>);
+
+    impl Write for ShortWriter {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+            let size = buf.len().min(1);
+            self.get_mut().0.extend_from_slice(&buf[..size]);
+            Poll::Ready(Ok(size))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn round_trip(frame: Http3Frame) -> Http3Frame {
+        let mut output = ShortWriter(Vec::new());
+        write_http3_frame(&mut output, &frame).await.unwrap();
+        read_http3_frame(&mut output.0.as_slice(), None).await.unwrap().unwrap()
+    }
+
+    async fn read_error(data: &[u8], limit: Option<usize>) -> Http3ErrorCode {
+        let error = read_http3_frame(&mut &data[..], limit).await.unwrap_err();
+        Http3Error::from_io(&error).unwrap().code()
+    }
+
+    #[async_std::test]
+    async fn codes_varints() {
+        let vectors: [(u64, &[u8]); 5] = [
+            (37, &[0x25]),
+            (15_293, &[0x7b, 0xbd]),
+            (494_878_333, &[0x9d, 0x7f, 0x3e, 0x7d]),
+            (151_288_809_941_952_652, &[0xc2, 0x19, 0x7c, 0x5e, 0xff, 0x14, 0xe8, 0x8c]),
+            (QUIC_VARINT_MAX, &[0xff; 8]),
+        ];
+        for (value, bytes) in vectors.iter() {
+            let mut output = Vec::new();
+            write_varint(&mut output, *value).await.unwrap();
+            assert_eq!(&output, bytes);
+            assert_eq!(read_varint(&mut &bytes[..]).await.unwrap(), *value);
+        }
+        assert_eq!(decode_varint(&[0x40, 0x25], &mut 0), Some(37));
+        assert_eq!(decode_varint(&[0x7b], &mut 0), None);
+        assert!(encode_varint(QUIC_VARINT_MAX + 1, &mut Vec::new()).is_err());
+        assert!(read_varint(&mut &[0x9d, 0x7f][..]).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn codes_all_frames() {
+        let settings = Http3Settings { qpack_max_table_capacity: Some(4096), qpack_blocked_streams: Some(16), ..Http3Settings::new() };
+        let frames = vec![
+            Http3Frame::Data { data: b"hello".to_vec() },
+            Http3Frame::Headers { block: vec![0, 0, 0xd1] },
+            Http3Frame::CancelPush { push_id: 3 },
+            Http3Frame::Settings(settings),
+            Http3Frame::Settings(Http3Settings::new()),
+            Http3Frame::PushPromise { push_id: 1000, block: vec![0, 0, 0xd1] },
+            Http3Frame::GoAway { id: 4 },
+            Http3Frame::MaxPushId { push_id: 64 },
+            Http3Frame::Unknown { kind: 0x21, payload: vec![1, 2] },
+        ];
+        for frame in frames.into_iter() {
+            assert_eq!(round_trip(frame.clone()).await, frame);
+        }
+    }
+
+    #[async_std::test]
+    async fn reads_streams() {
+        let mut output = Vec::new();
+        write_stream_type(&mut output, Http3StreamType::Control).await.unwrap();
+        write_http3_frame(&mut output, &Http3Frame::Settings(Http3Settings { max_field_section_size: Some(100), ..Http3Settings::new() })).await.unwrap();
+        assert_eq!(output, vec![0x00, 0x04, 0x03, 0x06, 0x40, 0x64]);
+
+        let mut input = output.as_slice();
+        assert_eq!(read_stream_type(&mut input).await.unwrap(), Http3StreamType::Control);
+        assert!(matches!(read_http3_frame(&mut input, None).await.unwrap(), Some(Http3Frame::Settings(_))));
+        assert_eq!(read_http3_frame(&mut input, None).await.unwrap(), None);
+        assert_eq!(read_stream_type(&mut &[0x54, 0x11][..]).await.unwrap(), Http3StreamType::Unknown(0x1411));
+    }
+
+    #[async_std::test]
+    async fn rejects_invalid_frames() {
+        assert_eq!(read_error(&[0x00, 0x05, b'h', b'i'], None).await, Http3ErrorCode::FrameError);
+        assert_eq!(read_error(&[0x00, 0x05, b'h', b'i', b'!', b'!', b'!'], Some(4)).await, Http3ErrorCode::ExcessiveLoad);
+        assert_eq!(read_error(&[0x07, 0x02, 0x01, 0x02], None).await, Http3ErrorCode::FrameError);
+        assert_eq!(read_error(&[0x04, 0x04, 0x01, 0x00, 0x01, 0x00], None).await, Http3ErrorCode::SettingsError);
+        assert_eq!(read_error(&[0x04, 0x02, 0x03, 0x00], None).await, Http3ErrorCode::SettingsError);
+        assert_eq!(read_error(&[0x06, 0x00], None).await, Http3ErrorCode::FrameUnexpected);
+        assert_eq!(read_error(&[0x01], None).await, Http3ErrorCode::FrameError);
+        assert_eq!(read_error(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, b'h', b'i', b'!'], None).await, Http3ErrorCode::FrameError); // huge length
+        assert_eq!(Http3ErrorCode::from(0x10c), Http3ErrorCode::RequestCancelled);
+        assert_eq!(u64::from(Http3ErrorCode::QpackDecompressionFailed), 0x200);
+    }
+}
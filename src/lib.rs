@@ -1,15 +1,64 @@
+mod base64;
+mod cache;
+mod cache_control;
+mod conditional;
+mod connection;
+mod cookie;
+mod date;
+mod error;
+mod expect;
+mod hpack;
+mod http2;
+mod http2_frame;
+mod http3_frame;
+mod huffman;
+mod limits;
+mod media;
 mod method;
+mod negotiate;
 mod parse;
+mod qpack;
+mod range;
 mod read;
 mod relay;
+mod sha1;
+mod sse;
 mod status;
+mod timeout;
+mod upgrade;
 mod version;
+mod websocket;
+mod websocket_deflate;
+mod websocket_frame;
 mod write;
 
+pub use cache::*;
+pub use cache_control::*;
+pub use conditional::*;
+pub use connection::*;
+pub use cookie::*;
+pub use date::*;
+pub use error::*;
+pub use expect::*;
+pub use hpack::*;
+pub use http2::*;
+pub use http2_frame::*;
+pub use http3_frame::*;
+pub use limits::*;
+pub use media::*;
 pub use method::*;
+pub use negotiate::*;
 pub use parse::*;
+pub use qpack::*;
+pub use range::*;
 pub use read::*;
 pub use relay::*;
+pub use sse::*;
 pub use status::*;
+pub use timeout::*;
+pub use upgrade::*;
 pub use version::*;
+pub use websocket::*;
+pub use websocket_deflate::*;
+pub use websocket_frame::*;
 pub use write::*;
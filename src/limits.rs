@@ -0,0 +1,117 @@
+/// Sizes which reading, writing and relaying an HTTP/1.x message must not exceed. A `None`
+/// removes the limit.
+///
+/// Framed protocols (WebSocket, server-sent events, HTTP/2, HTTP/3, HPACK and QPACK) do not use
+/// these limits and take the maximum size of their frames, events or header lists directly.
+///
+/// The defaults protect a server from oversized heads while leaving the body size to the
+/// application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Limits {
+    /// Length of the request or status line.
+    pub first_line: Option<usize>,
+    /// Length of a single header or trailer line.
+    pub header_line: Option<usize>,
+    /// Number of header fields.
+    pub headers: Option<usize>,
+    /// Total length of the header section.
+    pub head: Option<usize>,
+    /// Length of the body data, excluding the chunked framing.
+    pub body: Option<usize>,
+    /// Length of a single chunk's data. Also caps the size of the chunks `write_chunks` writes.
+    pub chunk: Option<usize>,
+    /// Total length of the trailer section.
+    pub trailers: Option<usize>,
+    /// Length of a chunk line's extensions.
+    pub extension: Option<usize>,
+}
+
+impl Limits {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns limits with every size unlimited.
+    pub fn unlimited() -> Self {
+        Self {
+            first_line: None,
+            header_line: None,
+            headers: None,
+            head: None,
+            body: None,
+            chunk: None,
+            trailers: None,
+            extension: None,
+        }
+    }
+
+    pub fn with_first_line(mut self, limit: Option<usize>) -> Self {
+        self.first_line = limit;
+        self
+    }
+
+    pub fn with_header_line(mut self, limit: Option<usize>) -> Self {
+        self.header_line = limit;
+        self
+    }
+
+    pub fn with_headers(mut self, limit: Option<usize>) -> Self {
+        self.headers = limit;
+        self
+    }
+
+    pub fn with_head(mut self, limit: Option<usize>) -> Self {
+        self.head = limit;
+        self
+    }
+
+    pub fn with_body(mut self, limit: Option<usize>) -> Self {
+        self.body = limit;
+        self
+    }
+
+    pub fn with_chunk(mut self, limit: Option<usize>) -> Self {
+        self.chunk = limit;
+        self
+    }
+
+    pub fn with_trailers(mut self, limit: Option<usize>) -> Self {
+        self.trailers = limit;
+        self
+    }
+
+    pub fn with_extension(mut self, limit: Option<usize>) -> Self {
+        self.extension = limit;
+        self
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            first_line: Some(8 * 1024),
+            header_line: Some(8 * 1024),
+            headers: Some(100),
+            head: Some(64 * 1024),
+            body: None,
+            chunk: Some(1024 * 1024),
+            trailers: Some(8 * 1024),
+            extension: Some(1024),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_limits() {
+        let limits = Limits::new().with_body(Some(10)).with_headers(None);
+        assert_eq!(limits.body, Some(10));
+        assert_eq!(limits.headers, None);
+        assert_eq!(limits.first_line, Limits::default().first_line);
+        assert_eq!(Limits::unlimited().with_chunk(Some(3)), Limits { chunk: Some(3), ..Limits::unlimited() });
+    }
+}
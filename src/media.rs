@@ -0,0 +1,260 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+/// Media type of the `Content-Type` and `Accept` headers.
+///
+/// As defined by [rfc7231 section 3.1.1.1](https://tools.ietf.org/html/rfc7231#section-3.1.1.1).
+/// The type, subtype and parameter names are case-insensitive and are stored in lowercase.
+/// [Read more](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Type)
+#[derive(Debug, Clone, Eq)]
+pub struct MediaType {
+    kind: String,
+    subtype: String,
+    params: Vec<(String, String)>,
+}
+
+impl MediaType {
+
+    pub fn new(kind: &str, subtype: &str) -> Self {
+        Self {
+            kind: kind.to_lowercase(),
+            subtype: subtype.to_lowercase(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Returns the media type with the parameter added or replaced.
+    pub fn with_param(mut self, name: &str, value: &str) -> Self {
+        self.set_param(name, value);
+        self
+    }
+
+    pub fn set_param(&mut self, name: &str, value: &str) {
+        let name = name.to_lowercase();
+        match self.params.iter_mut().find(|(n, _)| *n == name) {
+            Some(param) => param.1 = value.to_string(),
+            None => self.params.push((name, value.to_string())),
+        };
+    }
+
+    /// The top-level type (e.g. `text` of `text/html`).
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// The subtype including the suffix (e.g. `ld+json` of `application/ld+json`).
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// The structured syntax suffix (e.g. `json` of `application/ld+json`).
+    pub fn suffix(&self) -> Option<&str> {
+        self.subtype.rfind('+').map(|index| &self.subtype[index + 1..])
+    }
+
+    /// The type and subtype without parameters (e.g. `text/html`).
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.kind, self.subtype)
+    }
+
+    pub fn params(&self) -> &Vec<(String, String)> {
+        &self.params
+    }
+
+    /// Returns the value of a parameter where the name is matched case-insensitively.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.params.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+
+    pub fn boundary(&self) -> Option<&str> {
+        self.param("boundary")
+    }
+
+    pub fn is_multipart(&self) -> bool {
+        self.kind == "multipart"
+    }
+}
+
+impl Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.kind, self.subtype)?;
+        for (name, value) in self.params.iter() {
+            match !value.is_empty() && value.bytes().all(is_token_char) {
+                true => write!(f, ";{}={}", name, value)?,
+                false => write!(f, ";{}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\""))?,
+            };
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MediaType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("The media type `{}` is invalid.", s));
+        let bytes = s.as_bytes();
+        let mut index = skip_whitespace(bytes, 0);
+
+        let kind = read_token(bytes, &mut index);
+        if kind.is_empty() || bytes.get(index) != Some(&b'/') {
+            return Err(invalid());
+        }
+        index += 1;
+        let subtype = read_token(bytes, &mut index);
+        if subtype.is_empty() {
+            return Err(invalid());
+        }
+        let mut media = Self::new(&kind, &subtype);
+
+        loop {
+            index = skip_whitespace(bytes, index);
+            match bytes.get(index) {
+                None => break,
+                Some(b';') => index = skip_whitespace(bytes, index + 1),
+                Some(_) => return Err(invalid()),
+            };
+            if index == bytes.len() || bytes[index] == b';' {
+                continue; // empty parameter
+            }
+
+            let name = read_token(bytes, &mut index);
+            if name.is_empty() || bytes.get(index) != Some(&b'=') {
+                return Err(invalid());
+            }
+            index += 1;
+            let value = match bytes.get(index) {
+                Some(b'"') => read_quoted_string(bytes, &mut index).ok_or_else(invalid)?,
+                _ => read_token(bytes, &mut index),
+            };
+            if !media.params.iter().any(|(n, _)| n.eq_ignore_ascii_case(&name)) {
+                media.set_param(&name, &value);
+            }
+        }
+
+        Ok(media)
+    }
+}
+
+impl std::convert::TryFrom<&[u8]> for MediaType {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(txt) => Self::from_str(&txt),
+            Err(e) => Err(Error::new(ErrorKind::InvalidInput, e.to_string())),
+        }
+    }
+}
+
+impl PartialEq for MediaType {
+    fn eq(&self, other: &MediaType) -> bool {
+        self.kind == other.kind
+            && self.subtype == other.subtype
+            && self.params.len() == other.params.len()
+            && self.params.iter().all(|(name, value)| match other.param(name) {
+                Some(other) if name == "charset" => value.eq_ignore_ascii_case(other),
+                Some(other) => value == other,
+                None => false,
+            })
+    }
+}
+
+/// See [the spec](https://tools.ietf.org/html/rfc7230#section-3.2.6) for more details.
+pub(crate) fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+pub(crate) fn skip_whitespace(bytes: &[u8], mut index: usize) -> usize {
+    while index < bytes.len() && (bytes[index] == b' ' || bytes[index] == b'\t') {
+        index += 1;
+    }
+    index
+}
+
+pub(crate) fn read_token(bytes: &[u8], index: &mut usize) -> String {
+    let start = *index;
+    while *index < bytes.len() && is_token_char(bytes[*index]) {
+        *index += 1;
+    }
+    String::from_utf8_lossy(&bytes[start..*index]).to_string()
+}
+
+/// Reads a quoted-string starting at the opening quote and returns its unescaped content.
+pub(crate) fn read_quoted_string(bytes: &[u8], index: &mut usize) -> Option<String> {
+    let mut value = Vec::new();
+    *index += 1;
+    while *index < bytes.len() {
+        match bytes[*index] {
+            b'"' => {
+                *index += 1;
+                return String::from_utf8(value).ok();
+            },
+            b'\\' if *index + 1 < bytes.len() => {
+                value.push(bytes[*index + 1]);
+                *index += 2;
+            },
+            b => {
+                value.push(b);
+                *index += 1;
+            },
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn implements_from_str() {
+        let media = MediaType::from_str("Text/HTML; Charset=\"UTF-8\" ;foo=bar").unwrap();
+        assert_eq!(media.kind(), "text");
+        assert_eq!(media.subtype(), "html");
+        assert_eq!(media.charset(), Some("UTF-8"));
+        assert_eq!(media.param("FOO"), Some("bar"));
+        let media = MediaType::from_str("multipart/form-data; boundary=\"a \\\"b\\\" c\"").unwrap();
+        assert_eq!(media.boundary(), Some("a \"b\" c"));
+        assert!(media.is_multipart());
+        assert!(MediaType::from_str("text").is_err());
+        assert!(MediaType::from_str("text/html; charset").is_err());
+        assert!(MediaType::from_str("text/html; charset=\"utf-8").is_err());
+    }
+
+    #[test]
+    fn implements_try_from() {
+        let media = MediaType::try_from("application/json".as_bytes()).unwrap();
+        assert_eq!(media.essence(), "application/json");
+    }
+
+    #[test]
+    fn implements_to_string() {
+        let media = MediaType::new("multipart", "form-data").with_param("boundary", "a b");
+        assert_eq!(media.to_string(), "multipart/form-data;boundary=\"a b\"");
+        let media = MediaType::from_str("text/plain ; charset=utf-8").unwrap();
+        assert_eq!(media.to_string(), "text/plain;charset=utf-8");
+    }
+
+    #[test]
+    fn implements_suffix() {
+        let media = MediaType::from_str("application/vnd.api+json").unwrap();
+        assert_eq!(media.suffix(), Some("json"));
+        assert_eq!(MediaType::new("text", "html").suffix(), None);
+    }
+
+    #[test]
+    fn implements_equality() {
+        let a = MediaType::from_str("text/html; charset=UTF-8; level=1").unwrap();
+        let b = MediaType::from_str("TEXT/html;LEVEL=1;charset=utf-8").unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, MediaType::from_str("text/html; charset=utf-8").unwrap());
+    }
+}
@@ -20,33 +20,21 @@ impl Method {
 
     /// See [the spec](https://tools.ietf.org/html/rfc7231#section-4.2.1) for more details.
     pub fn is_safe(&self) -> bool {
-        match self {
-            Method::Get | Method::Head | Method::Options | Method::Trace => true,
-            _ => false,
-        }
+        matches!(self, Method::Get | Method::Head | Method::Options | Method::Trace)
     }
 
     /// See [the spec](https://tools.ietf.org/html/rfc7231#section-4.2.2) for more details.
     pub fn is_idempotent(&self) -> bool {
-        match self {
-            Method::Get | Method::Head | Method::Options | Method::Trace | Method::Put | Method::Delete => true,
-            _ => false,
-        }
+        matches!(self, Method::Get | Method::Head | Method::Options | Method::Trace | Method::Put | Method::Delete)
     }
 
     /// See [the spec](https://tools.ietf.org/html/rfc7231#section-4.2.3) for more details.
     pub fn is_cacheable(&self) -> bool {
-        match self {
-            Method::Get | Method::Head => true,
-            _ => false,
-        }
+        matches!(self, Method::Get | Method::Head)
     }
 
     pub fn has_body(&self) -> bool {
-        match self {
-            Method::Post | Method::Put | Method::Delete | Method::Patch => true,
-            _ => false,
-        }
+        matches!(self, Method::Post | Method::Put | Method::Delete | Method::Patch)
     }
 }
 
@@ -86,7 +74,7 @@ impl FromStr for Method {
     }
 }
 
-impl<'a> std::convert::TryFrom<&[u8]> for Method {
+impl std::convert::TryFrom<&[u8]> for Method {
     type Error = Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
@@ -0,0 +1,375 @@
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use crate::{MediaType, Status};
+use crate::media::is_token_char;
+use crate::parse::split_list;
+
+/// Media range of the `Accept` header with its weight.
+///
+/// As defined by [rfc7231 section 5.3.2](https://tools.ietf.org/html/rfc7231#section-5.3.2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaRange {
+    media: MediaType,
+    quality: u16,
+}
+
+impl MediaRange {
+
+    pub fn media(&self) -> &MediaType {
+        &self.media
+    }
+
+    /// The weight in thousandths (`q=0.5` is `500`).
+    pub fn quality(&self) -> u16 {
+        self.quality
+    }
+
+    /// Returns `true` if the range includes the media type.
+    pub fn matches(&self, media: &MediaType) -> bool {
+        let kind = self.media.kind() == "*" || self.media.kind() == media.kind();
+        let subtype = self.media.subtype() == "*" || self.media.subtype() == media.subtype();
+        let params = self.media.params().iter().all(|(name, value)| match media.param(name) {
+            Some(other) if name == "charset" => value.eq_ignore_ascii_case(other),
+            Some(other) => value == other,
+            None => false,
+        });
+        kind && subtype && params
+    }
+
+    /// Returns the precedence of the range where more specific ranges override less specific
+    /// ones.
+    fn precedence(&self) -> (u8, usize) {
+        let specificity = match (self.media.kind(), self.media.subtype()) {
+            ("*", _) => 0,
+            (_, "*") => 1,
+            _ => 2,
+        };
+        (specificity, self.media.params().len())
+    }
+}
+
+impl FromStr for MediaRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = MediaType::from_str(s)?;
+        if parsed.kind() == "*" && parsed.subtype() != "*" {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("The media range `{}` is invalid.", s)));
+        }
+
+        let mut media = MediaType::new(parsed.kind(), parsed.subtype());
+        let mut quality = 1000;
+        for (name, value) in parsed.params().iter() {
+            if name == "q" {
+                quality = parse_quality(value)?;
+                break; // accept-ext parameters follow the weight
+            }
+            media.set_param(name, value);
+        }
+
+        Ok(Self { media, quality })
+    }
+}
+
+/// Parses the `Accept` header value into a list of weighted media ranges.
+pub fn parse_accept(value: &str) -> Result<Vec<MediaRange>, Error> {
+    split_list(value).into_iter().map(MediaRange::from_str).collect()
+}
+
+/// Picks the best of the offered media types for the `Accept` media ranges.
+///
+/// Each offer is weighted by the most specific matching range. Offers are expected in the
+/// server's order of preference which breaks ties. An empty list of ranges (no `Accept`
+/// header) accepts the first offer. Returns `Status::NotAcceptable` when no offer is
+/// acceptable.
+pub fn negotiate_media_type<'a>(ranges: &[MediaRange], offers: &'a [MediaType]) -> Result<&'a MediaType, Status> {
+    if ranges.is_empty() {
+        return offers.first().ok_or(Status::NotAcceptable);
+    }
+
+    let mut best: Option<(&MediaType, u16)> = None;
+    for offer in offers.iter() {
+        let quality = ranges.iter()
+            .filter(|range| range.matches(offer))
+            .max_by_key(|range| range.precedence())
+            .map(|range| range.quality)
+            .unwrap_or(0);
+        if quality > 0 && best.map(|(_, q)| quality > q).unwrap_or(true) {
+            best = Some((offer, quality));
+        }
+    }
+
+    best.map(|(offer, _)| offer).ok_or(Status::NotAcceptable)
+}
+
+/// Weighted token of the `Accept-Encoding`, `Accept-Language` and `Accept-Charset` headers.
+///
+/// As defined by [rfc7231 section 5.3.1](https://tools.ietf.org/html/rfc7231#section-5.3.1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualityItem {
+    value: String,
+    quality: u16,
+}
+
+impl QualityItem {
+
+    /// The lowercase token (e.g. `gzip` or `en-us`).
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The weight in thousandths (`q=0.5` is `500`).
+    pub fn quality(&self) -> u16 {
+        self.quality
+    }
+}
+
+impl FromStr for QualityItem {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';');
+        let value = parts.next().unwrap_or("").trim().to_lowercase();
+        if value.is_empty() || !value.bytes().all(is_token_char) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("The value `{}` is invalid.", s)));
+        }
+
+        let mut quality = 1000;
+        for param in parts {
+            let param = param.trim();
+            if let Some(weight) = param.strip_prefix("q=").or_else(|| param.strip_prefix("Q=")) {
+                quality = parse_quality(weight)?;
+            }
+        }
+
+        Ok(Self { value, quality })
+    }
+}
+
+/// Parses a list of weighted tokens (e.g. the `Accept-Encoding` header value).
+pub fn parse_quality_list(value: &str) -> Result<Vec<QualityItem>, Error> {
+    split_list(value).into_iter().map(QualityItem::from_str).collect()
+}
+
+/// Result of negotiating a content coding or a language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated<'a> {
+    value: &'a str,
+    vary: bool,
+}
+
+impl<'a> Negotiated<'a> {
+
+    /// The selected coding or language.
+    pub fn value(&self) -> &'a str {
+        self.value
+    }
+
+    /// Returns `true` if the selection depends on the request header which must thus be listed
+    /// in the `Vary` response header.
+    pub fn vary(&self) -> bool {
+        self.vary
+    }
+}
+
+/// Picks a content coding for the `Accept-Encoding` header.
+///
+/// The `items` are `None` when the request has no `Accept-Encoding` header in which case the
+/// `identity` coding is selected. The `identity` coding is acceptable unless excluded by
+/// `identity;q=0` or `*;q=0` and is selected last on ties, after the available codings in the
+/// server's order of preference. Returns `Status::NotAcceptable` when no coding is acceptable.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7231#section-5.3.4) for more details.
+pub fn negotiate_encoding<'a>(items: Option<&[QualityItem]>, available: &[&'a str]) -> Result<Negotiated<'a>, Status> {
+    let vary = available.iter().any(|coding| !coding.eq_ignore_ascii_case("identity"));
+    let items = match items {
+        Some(items) => items,
+        None => return Ok(Negotiated { value: "identity", vary }),
+    };
+    let weight = |coding: &str| {
+        let exact = items.iter().find(|item| item.value.eq_ignore_ascii_case(coding));
+        let any = items.iter().find(|item| item.value == "*");
+        match (exact, any) {
+            (Some(item), _) | (None, Some(item)) => item.quality,
+            (None, None) if coding.eq_ignore_ascii_case("identity") => 1000,
+            (None, None) => 0,
+        }
+    };
+
+    let mut best: Option<(&'a str, u16)> = None;
+    for coding in available.iter().chain(["identity"].iter()) {
+        let quality = weight(coding);
+        if quality > 0 && best.map(|(_, q)| quality > q).unwrap_or(true) {
+            best = Some((coding, quality));
+        }
+    }
+
+    best.map(|(value, _)| Negotiated { value, vary }).ok_or(Status::NotAcceptable)
+}
+
+/// Picks a language for the `Accept-Language` header using the lookup scheme.
+///
+/// Language ranges are tried in the order of their weight and are progressively truncated
+/// (`de-ch-1996` > `de-ch` > `de`) until one of the available tags matches. The first available
+/// language is the default when the request has no `Accept-Language` header or nothing
+/// matches. Returns `Status::NotAcceptable` only when no language is available.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc4647#section-3.4) for more details.
+pub fn negotiate_language<'a>(items: Option<&[QualityItem]>, available: &[&'a str]) -> Result<Negotiated<'a>, Status> {
+    let default = *available.first().ok_or(Status::NotAcceptable)?;
+    let vary = available.len() > 1;
+
+    let mut ranges: Vec<&QualityItem> = items.unwrap_or(&[]).iter()
+        .filter(|item| item.quality > 0 && item.value != "*")
+        .collect();
+    ranges.sort_by_key(|item| std::cmp::Reverse(item.quality)); // stable, keeps request order
+
+    for range in ranges {
+        let mut range = range.value.as_str();
+        loop {
+            if let Some(tag) = available.iter().find(|tag| tag.eq_ignore_ascii_case(range)) {
+                return Ok(Negotiated { value: tag, vary });
+            }
+            match range.rfind('-') {
+                Some(index) => range = &range[..index],
+                None => break,
+            };
+            if range.len() >= 2 && range.as_bytes()[range.len() - 2] == b'-' {
+                range = &range[..range.len() - 2]; // drop single-character subtags
+            }
+        }
+    }
+
+    Ok(Negotiated { value: default, vary })
+}
+
+/// Returns the available language tags matched by the `Accept-Language` ranges using the basic
+/// filtering scheme, ordered by weight.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc4647#section-3.3.1) for more details.
+pub fn filter_languages<'a>(items: &[QualityItem], available: &[&'a str]) -> Vec<&'a str> {
+    let weight = |tag: &str| {
+        items.iter()
+            .filter(|item| item.value == "*" || language_match(tag, &item.value))
+            .max_by_key(|item| if item.value == "*" { 0 } else { item.value.len() })
+            .map(|item| item.quality)
+            .unwrap_or(0)
+    };
+
+    let mut tags: Vec<(&'a str, u16)> = available.iter()
+        .map(|tag| (*tag, weight(tag)))
+        .filter(|(_, quality)| *quality > 0)
+        .collect();
+    tags.sort_by_key(|(_, quality)| std::cmp::Reverse(*quality));
+
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+fn language_match(tag: &str, range: &str) -> bool {
+    tag.len() >= range.len()
+        && tag[..range.len()].eq_ignore_ascii_case(range)
+        && (tag.len() == range.len() || tag.as_bytes()[range.len()] == b'-')
+}
+
+/// Parses a weight (`qvalue`) into thousandths.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7231#section-5.3.1) for more details.
+pub(crate) fn parse_quality(value: &str) -> Result<u16, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("The weight `{}` is invalid.", value));
+    let (int, frac) = match value.find('.') {
+        Some(index) => (&value[..index], &value[index + 1..]),
+        None => (value, ""),
+    };
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let frac = format!("{:0<3}", frac).parse::<u16>().map_err(|_| invalid())?;
+    match int {
+        "0" => Ok(frac),
+        "1" if frac == 0 => Ok(1000),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn media_types(items: &[&str]) -> Vec<MediaType> {
+        items.iter().map(|item| MediaType::from_str(item).unwrap()).collect()
+    }
+
+    #[test]
+    fn parses_quality() {
+        assert_eq!(parse_quality("1").unwrap(), 1000);
+        assert_eq!(parse_quality("0.5").unwrap(), 500);
+        assert_eq!(parse_quality("0.125").unwrap(), 125);
+        assert_eq!(parse_quality("0").unwrap(), 0);
+        assert!(parse_quality("1.1").is_err());
+        assert!(parse_quality("0.1234").is_err());
+    }
+
+    #[test]
+    fn parses_accept() {
+        let ranges = parse_accept("text/*;q=0.3, text/html;level=1;q=0.7;ext=1, */*").unwrap();
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[0].media().essence(), "text/*");
+        assert_eq!(ranges[0].quality(), 300);
+        assert_eq!(ranges[1].media().param("level"), Some("1"));
+        assert_eq!(ranges[1].media().param("ext"), None);
+        assert_eq!(ranges[2].quality(), 1000);
+        assert!(parse_accept("*/html").is_err());
+    }
+
+    #[test]
+    fn negotiates_media_type() {
+        let ranges = parse_accept("text/*;q=0.3, text/html;q=0.7, text/html;level=1, */*;q=0.5").unwrap();
+        let offers = media_types(&["text/plain", "image/jpeg", "text/html;level=1"]);
+        assert_eq!(negotiate_media_type(&ranges, &offers).unwrap().to_string(), "text/html;level=1");
+        let offers = media_types(&["text/plain", "image/jpeg", "text/html"]);
+        assert_eq!(negotiate_media_type(&ranges, &offers).unwrap().to_string(), "text/html");
+        let ranges = parse_accept("application/json, */*;q=0").unwrap();
+        assert_eq!(negotiate_media_type(&ranges, &offers), Err(Status::NotAcceptable));
+        assert_eq!(negotiate_media_type(&[], &offers).unwrap().to_string(), "text/plain");
+    }
+
+    #[test]
+    fn negotiates_encoding() {
+        let items = parse_quality_list("gzip;q=0.5, br").unwrap();
+        let chosen = negotiate_encoding(Some(&items), &["gzip", "br"]).unwrap();
+        assert_eq!(chosen.value(), "br");
+        assert!(chosen.vary());
+        let chosen = negotiate_encoding(Some(&items), &["deflate"]).unwrap();
+        assert_eq!(chosen.value(), "identity");
+        let chosen = negotiate_encoding(None, &["gzip"]).unwrap();
+        assert_eq!(chosen.value(), "identity");
+        let chosen = negotiate_encoding(Some(&[]), &[]).unwrap();
+        assert_eq!(chosen.value(), "identity");
+        assert!(!chosen.vary());
+        let items = parse_quality_list("*;q=0").unwrap();
+        assert_eq!(negotiate_encoding(Some(&items), &["gzip"]), Err(Status::NotAcceptable));
+        let items = parse_quality_list("identity;q=0, *").unwrap();
+        assert_eq!(negotiate_encoding(Some(&items), &["gzip"]).unwrap().value(), "gzip");
+    }
+
+    #[test]
+    fn negotiates_language() {
+        let items = parse_quality_list("fr;q=0.5, de-CH-x-phonebk, en;q=0.8").unwrap();
+        let chosen = negotiate_language(Some(&items), &["en-US", "de", "fr"]).unwrap();
+        assert_eq!(chosen.value(), "de");
+        assert!(chosen.vary());
+        let chosen = negotiate_language(Some(&items), &["it", "fr-FR"]).unwrap();
+        assert_eq!(chosen.value(), "it");
+        let chosen = negotiate_language(None, &["en"]).unwrap();
+        assert_eq!(chosen.value(), "en");
+        assert!(!chosen.vary());
+        assert_eq!(negotiate_language(None, &[]), Err(Status::NotAcceptable));
+    }
+
+    #[test]
+    fn filters_languages() {
+        let items = parse_quality_list("de-de, en;q=0.5, *;q=0.1, fr;q=0").unwrap();
+        let tags = filter_languages(&items, &["fr-FR", "en-GB", "de-DE", "de", "it"]);
+        assert_eq!(tags, vec!["de-DE", "en-GB", "de", "it"]);
+    }
+}
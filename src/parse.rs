@@ -1,6 +1,6 @@
-use std::io::{Error, ErrorKind};
+use std::io::{Error};
 use std::convert::TryFrom;
-use crate::{Method, Status, Version};
+use crate::{HttpDate, HttpError, MediaType, Method, Status, Version};
 
 pub fn parse_method(data: Vec<u8>) -> Result<Method, Error> {
     let data: &[u8] = &data;
@@ -10,7 +10,7 @@ pub fn parse_method(data: Vec<u8>) -> Result<Method, Error> {
 pub fn parse_uri(data: Vec<u8>) -> Result<String, Error> {
     match String::from_utf8(data) {
         Ok(uri) => Ok(uri),
-        Err(e) => Err(Error::new(ErrorKind::InvalidData, e.to_string()))
+        Err(e) => Err(HttpError::MalformedFirstLine { offset: e.utf8_error().valid_up_to() }.into()),
     }
 }
 
@@ -20,6 +20,46 @@ pub fn parse_status(data: Vec<u8>) -> Result<Status, Error> {
 }
 
 pub fn parse_version(data: Vec<u8>) -> Result<Version, Error> {
+    let bytes: &[u8] = &data;
+    match Version::try_from(bytes) {
+        Err(_) if data.starts_with(b"HTTP/") => Err(HttpError::UnsupportedVersion {
+            version: String::from_utf8_lossy(&data).into_owned(),
+            offset: 0,
+        }.into()),
+        Err(_) => Err(HttpError::MalformedFirstLine { offset: 0 }.into()),
+        version => version,
+    }
+}
+
+pub fn parse_date(data: Vec<u8>) -> Result<HttpDate, Error> {
+    let data: &[u8] = &data;
+    HttpDate::try_from(data)
+}
+
+pub fn parse_media_type(data: Vec<u8>) -> Result<MediaType, Error> {
     let data: &[u8] = &data;
-    Version::try_from(data)
+    MediaType::try_from(data)
+}
+
+/// Splits a comma-separated header value into trimmed, non-empty elements while respecting
+/// quoted strings.
+pub(crate) fn split_list(value: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+
+    for (index, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == ',' && !quoted {
+            items.push(value[start..index].trim());
+            start = index + 1;
+        }
+    }
+    items.push(value[start..].trim());
+
+    items.into_iter().filter(|item| !item.is_empty()).collect()
 }
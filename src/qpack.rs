@@ -0,0 +1,849 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind};
+use crate::{HeaderField, Http3Error, Http3ErrorCode};
+use crate::hpack::{encode_integer, decode_integer, encode_string, encode_string_with_flags, decode_text};
+
+/// Static table entries, indexed from 0.
+///
+/// As defined by [rfc9204 appendix A](https://www.rfc-editor.org/rfc/rfc9204#appendix-A).
+const STATIC_TABLE: [(&str, &str); 99] = [
+    (":authority", ""),
+    (":path", "/"),
+    ("age", "0"),
+    ("content-disposition", ""),
+    ("content-length", "0"),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("referer", ""),
+    ("set-cookie", ""),
+    (":method", "CONNECT"),
+    (":method", "DELETE"),
+    (":method", "GET"),
+    (":method", "HEAD"),
+    (":method", "OPTIONS"),
+    (":method", "POST"),
+    (":method", "PUT"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "103"),
+    (":status", "200"),
+    (":status", "304"),
+    (":status", "404"),
+    (":status", "503"),
+    ("accept", "*/*"),
+    ("accept", "application/dns-message"),
+    ("accept-encoding", "gzip, deflate, br"),
+    ("accept-ranges", "bytes"),
+    ("access-control-allow-headers", "cache-control"),
+    ("access-control-allow-headers", "content-type"),
+    ("access-control-allow-origin", "*"),
+    ("cache-control", "max-age=0"),
+    ("cache-control", "max-age=2592000"),
+    ("cache-control", "max-age=604800"),
+    ("cache-control", "no-cache"),
+    ("cache-control", "no-store"),
+    ("cache-control", "public, max-age=31536000"),
+    ("content-encoding", "br"),
+    ("content-encoding", "gzip"),
+    ("content-type", "application/dns-message"),
+    ("content-type", "application/javascript"),
+    ("content-type", "application/json"),
+    ("content-type", "application/x-www-form-urlencoded"),
+    ("content-type", "image/gif"),
+    ("content-type", "image/jpeg"),
+    ("content-type", "image/png"),
+    ("content-type", "text/css"),
+    ("content-type", "text/html; charset=utf-8"),
+    ("content-type", "text/plain"),
+    ("content-type", "text/plain;charset=utf-8"),
+    ("range", "bytes=0-"),
+    ("strict-transport-security", "max-age=31536000"),
+    ("strict-transport-security", "max-age=31536000; includesubdomains"),
+    ("strict-transport-security", "max-age=31536000; includesubdomains; preload"),
+    ("vary", "accept-encoding"),
+    ("vary", "origin"),
+    ("x-content-type-options", "nosniff"),
+    ("x-xss-protection", "1; mode=block"),
+    (":status", "100"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "302"),
+    (":status", "400"),
+    (":status", "403"),
+    (":status", "421"),
+    (":status", "425"),
+    (":status", "500"),
+    ("accept-language", ""),
+    ("access-control-allow-credentials", "FALSE"),
+    ("access-control-allow-credentials", "TRUE"),
+    ("access-control-allow-headers", "*"),
+    ("access-control-allow-methods", "get"),
+    ("access-control-allow-methods", "get, post, options"),
+    ("access-control-allow-methods", "options"),
+    ("access-control-expose-headers", "content-length"),
+    ("access-control-request-headers", "content-type"),
+    ("access-control-request-method", "get"),
+    ("access-control-request-method", "post"),
+    ("alt-svc", "clear"),
+    ("authorization", ""),
+    ("content-security-policy", "script-src 'none'; object-src 'none'; base-uri 'none'"),
+    ("early-data", "1"),
+    ("expect-ct", ""),
+    ("forwarded", ""),
+    ("if-range", ""),
+    ("origin", ""),
+    ("purpose", "prefetch"),
+    ("server", ""),
+    ("timing-allow-origin", "*"),
+    ("upgrade-insecure-requests", "1"),
+    ("user-agent", ""),
+    ("x-forwarded-for", ""),
+    ("x-frame-options", "deny"),
+    ("x-frame-options", "sameorigin"),
+];
+
+/// Dynamic table addressed by absolute indexes which count every insertion, oldest entry
+/// first.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9204#section-3.2) for more details.
+#[derive(Debug, Clone, Default)]
+struct DynamicTable {
+    entries: VecDeque<(String, String)>,
+    dropped: usize,
+    size: usize,
+    capacity: usize,
+}
+
+impl DynamicTable {
+
+    /// Returns the number of entries inserted so far.
+    fn insert_count(&self) -> usize {
+        self.dropped + self.entries.len()
+    }
+
+    fn get(&self, index: usize) -> Option<&(String, String)> {
+        index.checked_sub(self.dropped).and_then(|i| self.entries.get(i))
+    }
+
+    /// Returns the absolute index of the newest entry matching the name and whether the value
+    /// matches too. Exact matches are preferred.
+    fn find(&self, name: &str, value: &str) -> Option<(usize, bool)> {
+        let mut found = None;
+        for (index, (n, v)) in self.entries.iter().enumerate().rev() {
+            if n == name && v == value {
+                return Some((self.dropped + index, true));
+            } else if n == name && found.is_none() {
+                found = Some((self.dropped + index, false));
+            }
+        }
+        found
+    }
+
+    /// Returns the number of oldest entries to evict so that `space` more bytes fit.
+    fn evictions(&self, space: usize) -> usize {
+        let mut size = self.size;
+        let mut count = 0;
+        for (name, value) in self.entries.iter() {
+            if size + space <= self.capacity {
+                break;
+            }
+            size -= name.len() + value.len() + 32;
+            count += 1;
+        }
+        count
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict(self.evictions(0));
+    }
+
+    /// Inserts an entry after evicting old ones. Fails if the entry is larger than the table.
+    fn insert(&mut self, name: &str, value: &str) -> Result<usize, Error> {
+        let size = name.len() + value.len() + 32;
+        if size > self.capacity {
+            return Err(Error::new(ErrorKind::InvalidData, "The QPACK entry exceeds the dynamic table capacity."));
+        }
+        self.evict(self.evictions(size));
+        self.entries.push_back((name.to_string(), value.to_string()));
+        self.size += size;
+        Ok(self.insert_count() - 1)
+    }
+
+    fn evict(&mut self, count: usize) {
+        for (name, value) in self.entries.drain(..count) {
+            self.size -= name.len() + value.len() + 32;
+        }
+        self.dropped += count;
+    }
+}
+
+/// Field section which the decoder has not acknowledged yet.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    required_insert_count: usize,
+    min_reference: Option<usize>,
+}
+
+/// Field line of a section being encoded.
+#[derive(Debug, Clone, Copy)]
+enum Line<'a> {
+    Static(usize),
+    Dynamic(usize),
+    Literal(&'a HeaderField),
+}
+
+/// Compresses header lists into field sections of HTTP/3 request streams, writing dynamic table
+/// updates to the encoder stream.
+///
+/// The dynamic table is only used after `set_capacity`. Entries which the decoder has not
+/// acknowledged are referenced only as long as fewer than the allowed number of streams are
+/// blocked on them.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9204#section-2.1) for more details.
+#[derive(Debug, Clone)]
+pub struct QpackEncoder {
+    table: DynamicTable,
+    max_capacity: usize,
+    max_blocked_streams: usize,
+    known_received_count: usize,
+    pending: HashMap<u64, VecDeque<Section>>,
+    huffman: bool,
+    buffer: Vec<u8>,
+}
+
+impl QpackEncoder {
+
+    /// Creates an encoder for a decoder which announced `SETTINGS_QPACK_MAX_TABLE_CAPACITY`
+    /// and `SETTINGS_QPACK_BLOCKED_STREAMS`.
+    pub fn new(max_capacity: usize, max_blocked_streams: usize) -> Self {
+        Self {
+            table: DynamicTable::default(),
+            max_capacity,
+            max_blocked_streams,
+            known_received_count: 0,
+            pending: HashMap::new(),
+            huffman: true,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Enables Huffman coding of string literals which do not grow by it (the default).
+    pub fn with_huffman(mut self, huffman: bool) -> Self {
+        self.huffman = huffman;
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.table.capacity
+    }
+
+    /// Returns the number of insertions acknowledged by the decoder.
+    pub fn known_received_count(&self) -> usize {
+        self.known_received_count
+    }
+
+    /// Changes the dynamic table capacity and appends the instruction to `encoder_stream`.
+    ///
+    /// Fails if the capacity exceeds the decoder's maximum or entries which are still
+    /// referenced would have to be evicted.
+    pub fn set_capacity(&mut self, capacity: usize, encoder_stream: &mut Vec<u8>) -> Result<(), Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidInput, message.to_string());
+        let mut table = self.table.clone();
+        table.capacity = capacity;
+        if capacity > self.max_capacity {
+            return Err(invalid("The QPACK table capacity exceeds the decoder's maximum."));
+        } else if !self.evictable(table.evictions(0), None) {
+            return Err(invalid("The QPACK table capacity is too small for the referenced entries."));
+        }
+
+        self.table.set_capacity(capacity);
+        encode_integer(capacity, 5, 0x20, encoder_stream);
+        Ok(())
+    }
+
+    /// Appends the field section of `fields` sent on `stream_id` to `output` and the dynamic
+    /// table insertions it relies on to `encoder_stream`.
+    ///
+    /// See [the spec](https://www.rfc-editor.org/rfc/rfc9204#section-4.5) for more details.
+    pub fn encode(&mut self, stream_id: u64, fields: &[HeaderField], output: &mut Vec<u8>, encoder_stream: &mut Vec<u8>) {
+        let blocked = self.pending.get(&stream_id)
+            .map(|sections| sections.iter().any(|s| s.required_insert_count > self.known_received_count))
+            .unwrap_or(false);
+        let blocking = blocked || self.blocked_streams() < self.max_blocked_streams;
+        let mut section = Section { required_insert_count: 0, min_reference: None };
+        let mut lines = Vec::new();
+
+        for field in fields.iter() {
+            let (name, value) = (field.name(), field.value());
+            let found = STATIC_TABLE.iter().position(|(n, v)| *n == name && *v == value);
+            if let (Some(index), false) = (found, field.is_sensitive()) {
+                lines.push(Line::Static(index));
+                continue;
+            }
+
+            let entry = match self.table.find(name, value) {
+                _ if field.is_sensitive() => None,
+                Some((index, true)) => Some(index),
+                _ => self.insert(name, value, section.min_reference, encoder_stream),
+            };
+            match entry {
+                Some(index) if index < self.known_received_count || blocking => {
+                    section.required_insert_count = section.required_insert_count.max(index + 1);
+                    section.min_reference = Some(section.min_reference.map_or(index, |min| min.min(index)));
+                    lines.push(Line::Dynamic(index));
+                },
+                _ => lines.push(Line::Literal(field)),
+            };
+        }
+
+        // the base equals the required insert count so that all references are relative
+        let base = section.required_insert_count;
+        encode_integer(self.encode_insert_count(base), 8, 0, output);
+        encode_integer(0, 7, 0, output);
+        for line in lines.into_iter() {
+            match line {
+                Line::Static(index) => encode_integer(index, 6, 0xc0, output),
+                Line::Dynamic(index) => encode_integer(base - 1 - index, 6, 0x80, output),
+                Line::Literal(field) => self.encode_literal(field, output),
+            };
+        }
+
+        if base > 0 {
+            self.pending.entry(stream_id).or_default().push_back(section);
+        }
+    }
+
+    /// Processes decoder stream instructions: section acknowledgements, stream cancellations
+    /// and insert count increments. An incomplete instruction at the end is kept until more
+    /// data arrives.
+    ///
+    /// Errors carry `Http3ErrorCode::QpackDecoderStreamError`.
+    ///
+    /// See [the spec](https://www.rfc-editor.org/rfc/rfc9204#section-4.4) for more details.
+    pub fn receive_decoder_stream(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.process_decoder_stream(data).map_err(|e| qpack_error(e, Http3ErrorCode::QpackDecoderStreamError))
+    }
+
+    fn process_decoder_stream(&mut self, data: &[u8]) -> Result<(), Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        self.buffer.extend_from_slice(data);
+        let buffer = std::mem::take(&mut self.buffer);
+        let mut index = 0;
+
+        while index < buffer.len() {
+            let byte = buffer[index];
+            let prefix = if byte & 0x80 != 0 { 7 } else { 6 };
+            if instruction_end(&buffer, index, &[(prefix, false)], 0)?.is_none() {
+                break;
+            }
+            let value = decode_integer(&buffer, &mut index, prefix)?;
+
+            if byte & 0x80 != 0 { // section acknowledgement
+                let sections = self.pending.get_mut(&(value as u64));
+                let section = sections.and_then(|s| s.pop_front())
+                    .ok_or_else(|| invalid("The QPACK section acknowledgement refers to no pending section."))?;
+                self.known_received_count = self.known_received_count.max(section.required_insert_count);
+                self.pending.retain(|_, sections| !sections.is_empty());
+            } else if byte & 0x40 != 0 { // stream cancellation
+                self.pending.remove(&(value as u64));
+            } else { // insert count increment
+                if value == 0 || self.known_received_count + value > self.table.insert_count() {
+                    return Err(invalid("The QPACK insert count increment is invalid."));
+                }
+                self.known_received_count += value;
+            }
+        }
+
+        self.buffer = buffer[index..].to_vec();
+        Ok(())
+    }
+
+    /// Encodes the required insert count modulo twice the number of entries which fit.
+    fn encode_insert_count(&self, count: usize) -> usize {
+        match count {
+            0 => 0,
+            count => count % (2 * (self.max_capacity / 32)) + 1,
+        }
+    }
+
+    fn encode_literal(&self, field: &HeaderField, output: &mut Vec<u8>) {
+        let never = if field.is_sensitive() { 0x20 } else { 0 };
+        match STATIC_TABLE.iter().position(|(n, _)| *n == field.name()) {
+            Some(index) => encode_integer(index, 4, 0x50 | never, output),
+            None => encode_string_with_flags(field.name().as_bytes(), 3, 0x20 | never >> 1, self.huffman, output),
+        };
+        encode_string(field.value().as_bytes(), 7, self.huffman, output);
+    }
+
+    /// Inserts an entry unless it would evict one which is still referenced and returns its
+    /// absolute index.
+    fn insert(&mut self, name: &str, value: &str, min_reference: Option<usize>, encoder_stream: &mut Vec<u8>) -> Option<usize> {
+        let size = name.len() + value.len() + 32;
+        if size > self.table.capacity || !self.evictable(self.table.evictions(size), min_reference) {
+            return None;
+        }
+
+        match STATIC_TABLE.iter().position(|(n, _)| *n == name) {
+            Some(index) => encode_integer(index, 6, 0xc0, encoder_stream),
+            None => encode_string_with_flags(name.as_bytes(), 5, 0x40, self.huffman, encoder_stream),
+        };
+        encode_string(value.as_bytes(), 7, self.huffman, encoder_stream);
+        self.table.insert(name, value).ok()
+    }
+
+    /// Returns `true` if the oldest `count` entries are acknowledged and no longer referenced.
+    fn evictable(&self, count: usize, min_reference: Option<usize>) -> bool {
+        let limit = self.pending.values().flatten()
+            .filter_map(|s| s.min_reference)
+            .chain(min_reference)
+            .fold(self.known_received_count, usize::min);
+        self.table.dropped + count <= limit
+    }
+
+    fn blocked_streams(&self) -> usize {
+        self.pending.values()
+            .filter(|sections| sections.iter().any(|s| s.required_insert_count > self.known_received_count))
+            .count()
+    }
+}
+
+/// Decompresses field sections of HTTP/3 request streams, applying the dynamic table updates
+/// of the encoder stream and writing acknowledgements to the decoder stream.
+///
+/// A field section which refers to entries that have not arrived yet blocks its stream until
+/// `receive_encoder_stream` returns the stream ID, after which the section is decoded again.
+///
+/// See [the spec](https://www.rfc-editor.org/rfc/rfc9204#section-2.2) for more details.
+#[derive(Debug, Clone)]
+pub struct QpackDecoder {
+    table: DynamicTable,
+    max_capacity: usize,
+    max_blocked_streams: usize,
+    acknowledged: usize,
+    blocked: HashMap<u64, usize>,
+    buffer: Vec<u8>,
+}
+
+impl QpackDecoder {
+
+    /// Creates a decoder for the local `SETTINGS_QPACK_MAX_TABLE_CAPACITY` and
+    /// `SETTINGS_QPACK_BLOCKED_STREAMS`.
+    pub fn new(max_capacity: usize, max_blocked_streams: usize) -> Self {
+        Self {
+            table: DynamicTable::default(),
+            max_capacity,
+            max_blocked_streams,
+            acknowledged: 0,
+            blocked: HashMap::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Returns the number of entries inserted so far.
+    pub fn insert_count(&self) -> usize {
+        self.table.insert_count()
+    }
+
+    /// Processes encoder stream instructions and returns the blocked streams which can be
+    /// decoded now. An incomplete instruction at the end is kept until more data arrives.
+    ///
+    /// Errors carry `Http3ErrorCode::QpackEncoderStreamError`.
+    ///
+    /// See [the spec](https://www.rfc-editor.org/rfc/rfc9204#section-4.3) for more details.
+    pub fn receive_encoder_stream(&mut self, data: &[u8]) -> Result<Vec<u64>, Error> {
+        self.process_encoder_stream(data).map_err(|e| qpack_error(e, Http3ErrorCode::QpackEncoderStreamError))
+    }
+
+    fn process_encoder_stream(&mut self, data: &[u8]) -> Result<Vec<u64>, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        self.buffer.extend_from_slice(data);
+        let buffer = std::mem::take(&mut self.buffer);
+        let mut index = 0;
+
+        while index < buffer.len() {
+            let byte = buffer[index];
+            let parts: &[(u8, bool)] = match byte {
+                b if b & 0x80 != 0 => &[(6, false), (7, true)],
+                b if b & 0x40 != 0 => &[(5, true), (7, true)],
+                _ => &[(5, false)],
+            };
+            if instruction_end(&buffer, index, parts, self.table.capacity)?.is_none() {
+                break;
+            }
+
+            if byte & 0x80 != 0 { // insert with name reference
+                let name = decode_integer(&buffer, &mut index, 6)?;
+                let name = match byte & 0x40 != 0 {
+                    true => STATIC_TABLE.get(name).map(|(n, _)| n.to_string()),
+                    false => self.relative(name).map(|(n, _)| n.clone()),
+                };
+                let name = name.ok_or_else(|| invalid("The QPACK name reference is invalid."))?;
+                let value = decode_text(&buffer, &mut index, 7)?;
+                self.table.insert(&name, &value)?;
+            } else if byte & 0x40 != 0 { // insert with literal name
+                let name = decode_text(&buffer, &mut index, 5)?;
+                let value = decode_text(&buffer, &mut index, 7)?;
+                self.table.insert(&name, &value)?;
+            } else if byte & 0x20 != 0 { // set dynamic table capacity
+                let capacity = decode_integer(&buffer, &mut index, 5)?;
+                if capacity > self.max_capacity {
+                    return Err(invalid("The QPACK table capacity exceeds the allowed maximum."));
+                }
+                self.table.set_capacity(capacity);
+            } else { // duplicate
+                let relative = decode_integer(&buffer, &mut index, 5)?;
+                let (name, value) = self.relative(relative).cloned().ok_or_else(|| invalid("The QPACK duplicate is invalid."))?;
+                self.table.insert(&name, &value)?;
+            }
+        }
+        self.buffer = buffer[index..].to_vec();
+
+        let insert_count = self.table.insert_count();
+        let unblocked = self.blocked.iter().filter(|(_, count)| **count <= insert_count).map(|(id, _)| *id).collect::<Vec<u64>>();
+        self.blocked.retain(|_, count| *count > insert_count);
+        Ok(unblocked)
+    }
+
+    /// Appends an insert count increment to `decoder_stream` for insertions the encoder does
+    /// not know to be received yet.
+    pub fn acknowledge_inserts(&mut self, decoder_stream: &mut Vec<u8>) {
+        let increment = self.table.insert_count() - self.acknowledged;
+        if increment > 0 {
+            encode_integer(increment, 6, 0x00, decoder_stream);
+            self.acknowledged += increment;
+        }
+    }
+
+    /// Forgets a reset stream and appends a stream cancellation to `decoder_stream`.
+    pub fn cancel_stream(&mut self, stream_id: u64, decoder_stream: &mut Vec<u8>) {
+        self.blocked.remove(&stream_id);
+        if self.max_capacity > 0 {
+            encode_integer(stream_id as usize, 6, 0x40, decoder_stream);
+        }
+    }
+
+    /// Decodes the field section received on `stream_id`. Returns `None` if the stream is
+    /// blocked on dynamic table insertions. Header lists larger than `limit` bytes, counted as
+    /// defined by `HeaderField::size`, are rejected.
+    ///
+    /// Sections which refer to the dynamic table are acknowledged on `decoder_stream`. Errors
+    /// carry `Http3ErrorCode::QpackDecompressionFailed`.
+    pub fn decode(&mut self, stream_id: u64, block: &[u8], limit: Option<usize>, decoder_stream: &mut Vec<u8>) -> Result<Option<Vec<HeaderField>>, Error> {
+        self.decode_section(stream_id, block, limit, decoder_stream).map_err(|e| qpack_error(e, Http3ErrorCode::QpackDecompressionFailed))
+    }
+
+    fn decode_section(&mut self, stream_id: u64, block: &[u8], limit: Option<usize>, decoder_stream: &mut Vec<u8>) -> Result<Option<Vec<HeaderField>>, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        let mut index = 0;
+        let required = self.decode_insert_count(decode_integer(block, &mut index, 8)?)?;
+        let negative = block.get(index).map(|b| b & 0x80 != 0).unwrap_or(false);
+        let delta = decode_integer(block, &mut index, 7)?;
+        let base = match negative {
+            true => required.checked_sub(delta + 1),
+            false => required.checked_add(delta),
+        }.ok_or_else(|| invalid("The QPACK base is invalid."))?;
+
+        if required > self.table.insert_count() {
+            if !self.blocked.contains_key(&stream_id) && self.blocked.len() >= self.max_blocked_streams {
+                return Err(invalid("The QPACK field section blocks too many streams."));
+            }
+            self.blocked.insert(stream_id, required);
+            return Ok(None);
+        }
+        self.blocked.remove(&stream_id);
+
+        let mut fields: Vec<HeaderField> = Vec::new();
+        let (mut size, mut largest) = (0, None);
+        while index < block.len() {
+            let byte = block[index];
+            let field = if byte & 0x80 != 0 { // indexed field line
+                let entry = decode_integer(block, &mut index, 6)?;
+                let (name, value) = match byte & 0x40 != 0 {
+                    true => STATIC_TABLE.get(entry).map(|(n, v)| (n.to_string(), v.to_string())).ok_or_else(|| invalid("The QPACK static index is invalid."))?,
+                    false => self.get(base.checked_sub(entry + 1), required, &mut largest)?,
+                };
+                HeaderField::new(&name, &value)
+            } else if byte & 0x40 != 0 { // literal field line with name reference
+                let entry = decode_integer(block, &mut index, 4)?;
+                let name = match byte & 0x10 != 0 {
+                    true => STATIC_TABLE.get(entry).map(|(n, _)| n.to_string()).ok_or_else(|| invalid("The QPACK static index is invalid."))?,
+                    false => self.get(base.checked_sub(entry + 1), required, &mut largest)?.0,
+                };
+                HeaderField::new(&name, &decode_text(block, &mut index, 7)?).with_sensitive(byte & 0x20 != 0)
+            } else if byte & 0x20 != 0 { // literal field line with literal name
+                let name = decode_text(block, &mut index, 3)?;
+                HeaderField::new(&name, &decode_text(block, &mut index, 7)?).with_sensitive(byte & 0x10 != 0)
+            } else if byte & 0x10 != 0 { // indexed field line with post-base index
+                let entry = decode_integer(block, &mut index, 4)?;
+                let (name, value) = self.get(base.checked_add(entry), required, &mut largest)?;
+                HeaderField::new(&name, &value)
+            } else { // literal field line with post-base name reference
+                let entry = decode_integer(block, &mut index, 3)?;
+                let name = self.get(base.checked_add(entry), required, &mut largest)?.0;
+                HeaderField::new(&name, &decode_text(block, &mut index, 7)?).with_sensitive(byte & 0x08 != 0)
+            };
+
+            size += field.size();
+            if let Some(limit) = limit.filter(|limit| size > *limit) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while decoding the field section.", limit)));
+            }
+            fields.push(field);
+        }
+
+        if required > 0 {
+            if largest.map(|l: usize| l + 1) != Some(required) {
+                return Err(invalid("The QPACK required insert count does not match the references."));
+            }
+            encode_integer(stream_id as usize, 7, 0x80, decoder_stream);
+            self.acknowledged = self.acknowledged.max(required);
+        }
+
+        Ok(Some(fields))
+    }
+
+    /// Returns the entry at an absolute index which must be below the required insert count.
+    fn get(&self, index: Option<usize>, required: usize, largest: &mut Option<usize>) -> Result<(String, String), Error> {
+        let entry = index.filter(|index| *index < required).and_then(|index| self.table.get(index));
+        match (index, entry) {
+            (Some(index), Some(entry)) => {
+                *largest = Some(largest.unwrap_or(index).max(index));
+                Ok(entry.clone())
+            },
+            _ => Err(Error::new(ErrorKind::InvalidData, "The QPACK dynamic index is invalid.")),
+        }
+    }
+
+    /// Returns the entry at an index relative to the last insertion.
+    fn relative(&self, index: usize) -> Option<&(String, String)> {
+        self.table.insert_count().checked_sub(index + 1).and_then(|index| self.table.get(index))
+    }
+
+    /// Reconstructs the required insert count of a field section.
+    ///
+    /// See [the spec](https://www.rfc-editor.org/rfc/rfc9204#section-4.5.1.1) for more details.
+    fn decode_insert_count(&self, encoded: usize) -> Result<usize, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "The QPACK required insert count is invalid.");
+        let max_entries = self.max_capacity / 32;
+        let full_range = 2 * max_entries;
+        if encoded == 0 {
+            return Ok(0);
+        } else if encoded > full_range {
+            return Err(invalid());
+        }
+
+        let max_value = self.table.insert_count() + max_entries;
+        let mut count = max_value / full_range * full_range + encoded - 1;
+        if count > max_value {
+            if count <= full_range {
+                return Err(invalid());
+            }
+            count -= full_range;
+        }
+        match count {
+            0 => Err(invalid()),
+            count => Ok(count),
+        }
+    }
+}
+
+/// Wraps `error` into an `Http3Error` with `code` unless it already is one.
+fn qpack_error(error: Error, code: Http3ErrorCode) -> Error {
+    match Http3Error::from_io(&error) {
+        Some(_) => error,
+        None => Http3Error::new(code, &error.to_string()).into(),
+    }
+}
+
+/// Returns the end of the instruction at `index` made of integers and string literals with
+/// the given prefixes, or `None` if more data is needed. Strings longer than `max_string` are
+/// rejected as soon as their length is known since they could never fit the table.
+fn instruction_end(data: &[u8], mut index: usize, parts: &[(u8, bool)], max_string: usize) -> Result<Option<usize>, Error> {
+    for (prefix, string) in parts.iter() {
+        let max = (1usize << prefix) - 1;
+        let complete = match data.get(index) {
+            Some(byte) => (*byte as usize & max) < max
+                || data[index + 1..].iter().take(6).any(|b| b & 0x80 == 0)
+                || data.len() > index + 6, // too long to be valid
+            None => false,
+        };
+        if !complete {
+            return Ok(None);
+        }
+        let length = decode_integer(data, &mut index, *prefix)?;
+        if *string && length > max_string {
+            return Err(Error::new(ErrorKind::InvalidData, "The QPACK string is larger than the dynamic table."));
+        } else if *string {
+            index += length;
+            if index > data.len() {
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let text = text.replace(' ', "");
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn fields(list: &[(&str, &str)]) -> Vec<HeaderField> {
+        list.iter().map(|(n, v)| HeaderField::new(n, v)).collect()
+    }
+
+    #[test]
+    fn codes_static_references() {
+        let mut encoder = QpackEncoder::new(0, 0).with_huffman(false);
+        let (mut output, mut instructions) = (Vec::new(), Vec::new());
+        encoder.encode(0, &fields(&[(":path", "/index.html")]), &mut output, &mut instructions);
+        assert_eq!(output, hex("0000 510b 2f69 6e64 6578 2e68 746d 6c"));
+        assert!(instructions.is_empty());
+
+        let mut decoder = QpackDecoder::new(0, 0);
+        let list = vec![HeaderField::new(":method", "GET"), HeaderField::new("authorization", "secret").with_sensitive(true), HeaderField::new("x-id", "1")];
+        let mut output = Vec::new();
+        QpackEncoder::new(0, 0).encode(0, &list, &mut output, &mut instructions);
+        assert_eq!(decoder.decode(0, &output, None, &mut instructions).unwrap(), Some(list));
+        assert!(instructions.is_empty());
+        assert!(decoder.decode(0, &output, Some(60), &mut instructions).is_err());
+    }
+
+    #[test]
+    fn decodes_dynamic_examples() {
+        let mut decoder = QpackDecoder::new(220, 1);
+        let mut acknowledgements = Vec::new();
+        decoder.receive_encoder_stream(&hex("3fbd01 c00f7777772e6578616d706c652e636f6d c10c2f73616d706c652f70617468")).unwrap();
+        let list = decoder.decode(4, &hex("0381 10 11"), None, &mut acknowledgements).unwrap();
+        assert_eq!(list, Some(fields(&[(":authority", "www.example.com"), (":path", "/sample/path")])));
+        assert_eq!(acknowledgements, hex("84"));
+
+        decoder.receive_encoder_stream(&hex("4a637573746f6d2d6b65790c637573746f6d2d76616c7565")).unwrap();
+        decoder.acknowledge_inserts(&mut acknowledgements);
+        assert_eq!(acknowledgements, hex("84 01"));
+
+        decoder.receive_encoder_stream(&hex("02")).unwrap();
+        let list = decoder.decode(8, &hex("0500 80 c1 81"), None, &mut acknowledgements).unwrap();
+        assert_eq!(list, Some(fields(&[(":authority", "www.example.com"), (":path", "/"), ("custom-key", "custom-value")])));
+        decoder.cancel_stream(8, &mut acknowledgements);
+        assert_eq!(acknowledgements, hex("84 01 88 48"));
+
+        for byte in hex("810d637573746f6d2d76616c756532").iter() {
+            decoder.receive_encoder_stream(&[*byte]).unwrap();
+        }
+        assert_eq!(decoder.insert_count(), 5);
+        assert_eq!(decoder.table.size, 215);
+        assert!(decoder.table.get(0).is_none());
+        assert_eq!(decoder.table.get(4), Some(&("custom-key".to_string(), "custom-value2".to_string())));
+    }
+
+    #[test]
+    fn encodes_with_dynamic_table() {
+        let mut encoder = QpackEncoder::new(220, 1).with_huffman(false);
+        let mut decoder = QpackDecoder::new(220, 1);
+        let (mut instructions, mut acknowledgements) = (Vec::new(), Vec::new());
+        encoder.set_capacity(220, &mut instructions).unwrap();
+        assert!(encoder.set_capacity(221, &mut instructions).is_err());
+
+        let list = fields(&[(":authority", "www.example.com"), (":path", "/sample/path")]);
+        let mut output = Vec::new();
+        encoder.encode(4, &list, &mut output, &mut instructions);
+        assert_eq!(instructions, hex("3fbd01 c00f7777772e6578616d706c652e636f6d c10c2f73616d706c652f70617468"));
+        assert_eq!(output, hex("0300 81 80"));
+
+        assert_eq!(decoder.decode(4, &output, None, &mut acknowledgements).unwrap(), None);
+        assert_eq!(decoder.receive_encoder_stream(&instructions).unwrap(), vec![4]);
+        assert_eq!(decoder.decode(4, &output, None, &mut acknowledgements).unwrap(), Some(list.clone()));
+        encoder.receive_decoder_stream(&acknowledgements).unwrap();
+        assert_eq!(encoder.known_received_count(), 2);
+
+        let mut output = Vec::new();
+        encoder.encode(8, &list, &mut output, &mut instructions);
+        assert_eq!(output, hex("0300 81 80"));
+        assert!(encoder.receive_decoder_stream(&hex("8c")).is_err());
+    }
+
+    #[test]
+    fn limits_blocked_streams() {
+        let mut encoder = QpackEncoder::new(100, 0);
+        let mut decoder = QpackDecoder::new(100, 0);
+        let (mut instructions, mut acknowledgements) = (Vec::new(), Vec::new());
+        encoder.set_capacity(100, &mut instructions).unwrap();
+
+        let list = fields(&[("custom-key", "custom-value")]);
+        let mut output = Vec::new();
+        encoder.encode(0, &list, &mut output, &mut instructions);
+        assert_eq!(&output[..2], &[0, 0]);
+        assert_eq!(decoder.decode(0, &output, None, &mut acknowledgements).unwrap(), Some(list.clone()));
+        assert!(decoder.decode(4, &hex("0200 80"), None, &mut acknowledgements).is_err());
+
+        decoder.receive_encoder_stream(&instructions).unwrap();
+        decoder.acknowledge_inserts(&mut acknowledgements);
+        encoder.receive_decoder_stream(&acknowledgements).unwrap();
+        let mut output = Vec::new();
+        encoder.encode(4, &list, &mut output, &mut instructions);
+        assert_eq!(output, hex("0200 80"));
+    }
+
+    #[test]
+    fn keeps_referenced_entries() {
+        let mut encoder = QpackEncoder::new(64, 2).with_huffman(false);
+        let mut instructions = Vec::new();
+        encoder.set_capacity(64, &mut instructions).unwrap();
+
+        let mut output = Vec::new();
+        encoder.encode(0, &fields(&[("custom-key", "custom-value")]), &mut output, &mut instructions);
+        assert_eq!(output, hex("0200 80"));
+        let mut output = Vec::new();
+        encoder.encode(4, &fields(&[("other-key", "other-value")]), &mut output, &mut instructions);
+        assert_eq!(&output[..2], &[0, 0]);
+        assert!(encoder.set_capacity(0, &mut instructions).is_err());
+
+        encoder.receive_decoder_stream(&hex("80")).unwrap();
+        let mut output = Vec::new();
+        encoder.encode(4, &fields(&[("other-key", "other-value")]), &mut output, &mut instructions);
+        assert_eq!(output, hex("0300 80"));
+    }
+
+    #[test]
+    fn rejects_invalid_sections() {
+        let mut decoder = QpackDecoder::new(220, 1);
+        let mut acknowledgements = Vec::new();
+        assert!(decoder.decode(0, &hex("0d00"), None, &mut acknowledgements).is_err());
+        assert!(QpackDecoder::new(0, 1).decode(0, &hex("0100"), None, &mut acknowledgements).is_err());
+        assert!(decoder.decode(0, &hex("0000 80"), None, &mut acknowledgements).is_err());
+        let error = decoder.decode(0, &hex("0000 ff7f"), None, &mut acknowledgements).unwrap_err();
+        assert_eq!(Http3Error::from_io(&error).unwrap().code(), Http3ErrorCode::QpackDecompressionFailed);
+        let error = decoder.receive_encoder_stream(&hex("3fbe01")).unwrap_err();
+        assert_eq!(Http3Error::from_io(&error).unwrap().code(), Http3ErrorCode::QpackEncoderStreamError);
+        let error = QpackDecoder::new(220, 1).receive_encoder_stream(&hex("00")).unwrap_err(); // duplicate
+        assert_eq!(Http3Error::from_io(&error).unwrap().code(), Http3ErrorCode::QpackEncoderStreamError);
+        let error = QpackEncoder::new(220, 1).receive_decoder_stream(&hex("80")).unwrap_err();
+        assert_eq!(Http3Error::from_io(&error).unwrap().code(), Http3ErrorCode::QpackDecoderStreamError);
+
+        let mut decoder = QpackDecoder::new(220, 1);
+        decoder.receive_encoder_stream(&hex("3fbd01 c00f7777772e6578616d706c652e636f6d c10c2f73616d706c652f70617468")).unwrap();
+        assert!(decoder.decode(0, &hex("0300 81"), None, &mut acknowledgements).is_err());
+        assert!(decoder.decode(0, &hex("0200 81"), None, &mut acknowledgements).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_strings_early() {
+        let mut decoder = QpackDecoder::new(220, 1);
+        decoder.receive_encoder_stream(&hex("3fbd01")).unwrap();
+        let error = decoder.receive_encoder_stream(&hex("5fff ff0f")).unwrap_err(); // name of 262,174 bytes
+        assert_eq!(Http3Error::from_io(&error).unwrap().code(), Http3ErrorCode::QpackEncoderStreamError);
+        assert!(QpackDecoder::new(220, 1).receive_encoder_stream(&hex("5fff ffff ffff ffff")).is_err());
+        assert_eq!(QpackDecoder::new(220, 1).receive_encoder_stream(&hex("5fff ff")).unwrap(), vec![]);
+    }
+}
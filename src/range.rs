@@ -0,0 +1,242 @@
+use std::io::{Error, ErrorKind, SeekFrom};
+use std::str::FromStr;
+use async_std::prelude::*;
+use async_std::io::{Read, Seek, Write};
+use crate::{relay_exact, flush_write, Status};
+use crate::parse::split_list;
+
+/// Byte range of the `Range` header.
+///
+/// As defined by [rfc7233 section 2.1](https://tools.ietf.org/html/rfc7233#section-2.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ByteRange {
+
+    /// `first-last`
+    Bounded(u64, u64),
+
+    /// `first-` up to the end of the representation.
+    From(u64),
+
+    /// `-length` of the final bytes of the representation.
+    Suffix(u64),
+}
+
+impl ByteRange {
+
+    /// Returns the inclusive `(first, last)` positions within a representation of `length`
+    /// bytes or `None` if the range is not satisfiable.
+    pub fn resolve(&self, length: u64) -> Option<(u64, u64)> {
+        match *self {
+            _ if length == 0 => None,
+            Self::Bounded(first, _) | Self::From(first) if first >= length => None,
+            Self::Bounded(first, last) => Some((first, last.min(length - 1))),
+            Self::From(first) => Some((first, length - 1)),
+            Self::Suffix(0) => None,
+            Self::Suffix(size) => Some((length - size.min(length), length - 1)),
+        }
+    }
+}
+
+impl FromStr for ByteRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("The byte range `{}` is invalid.", s));
+        let parse = |v: &str| match !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()) {
+            true => v.parse::<u64>().map_err(|_| invalid()),
+            false => Err(invalid()),
+        };
+
+        let index = s.find('-').ok_or_else(invalid)?;
+        let (first, last) = (s[..index].trim(), s[index + 1..].trim());
+        match (first.is_empty(), last.is_empty()) {
+            (true, true) => Err(invalid()),
+            (true, false) => Ok(Self::Suffix(parse(last)?)),
+            (false, true) => Ok(Self::From(parse(first)?)),
+            (false, false) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                match first <= last {
+                    true => Ok(Self::Bounded(first, last)),
+                    false => Err(invalid()),
+                }
+            },
+        }
+    }
+}
+
+/// Parses the `Range` header value of the `bytes` unit.
+pub fn parse_range(value: &str) -> Result<Vec<ByteRange>, Error> {
+    let value = value.trim();
+    let ranges = match value.find('=') {
+        Some(index) if value[..index].trim().eq_ignore_ascii_case("bytes") => &value[index + 1..],
+        _ => return Err(Error::new(ErrorKind::InvalidInput, format!("The range `{}` is not a byte range.", value))),
+    };
+
+    let ranges = split_list(ranges).into_iter()
+        .map(ByteRange::from_str)
+        .collect::<Result<Vec<ByteRange>, Error>>()?;
+    match ranges.is_empty() {
+        true => Err(Error::new(ErrorKind::InvalidInput, "The range has no byte ranges.")),
+        false => Ok(ranges),
+    }
+}
+
+/// Validates the byte ranges against a representation of `length` bytes and returns the
+/// sorted, coalesced inclusive `(first, last)` positions.
+///
+/// Overlapping and adjacent ranges are merged. Returns `Status::RequestedRangeNotSatisfiable`
+/// when none of the ranges is satisfiable.
+pub fn resolve_ranges(ranges: &[ByteRange], length: u64) -> Result<Vec<(u64, u64)>, Status> {
+    let mut resolved: Vec<(u64, u64)> = ranges.iter().filter_map(|range| range.resolve(length)).collect();
+    resolved.sort_unstable();
+
+    let mut coalesced: Vec<(u64, u64)> = Vec::with_capacity(resolved.len());
+    for (first, last) in resolved {
+        match coalesced.last_mut() {
+            Some(prev) if first <= prev.1 + 1 => prev.1 = prev.1.max(last),
+            _ => coalesced.push((first, last)),
+        };
+    }
+
+    match coalesced.is_empty() {
+        true => Err(Status::RequestedRangeNotSatisfiable),
+        false => Ok(coalesced),
+    }
+}
+
+/// Formats the `Content-Range` header value of a `206 Partial Content` response (e.g.
+/// `bytes 0-499/1234`).
+pub fn format_content_range(range: (u64, u64), length: u64) -> String {
+    format!("bytes {}-{}/{}", range.0, range.1, length)
+}
+
+/// Formats the `Content-Range` header value of a `416 Range Not Satisfiable` response (e.g.
+/// `bytes */1234`).
+pub fn format_unsatisfied_range(length: u64) -> String {
+    format!("bytes */{}", length)
+}
+
+/// Returns the `Content-Length` of the body written by `relay_ranges`.
+pub fn ranges_length(ranges: &[(u64, u64)], length: u64, content_type: Option<&str>, boundary: &str) -> u64 {
+    match ranges {
+        [(first, last)] => last - first + 1,
+        _ => ranges.iter()
+            .map(|range| part_head(*range, length, content_type, boundary).len() as u64 + range.1 - range.0 + 1 + 2)
+            .sum::<u64>() + boundary.len() as u64 + 6,
+    }
+}
+
+/// Relays the resolved ranges of a seekable representation of `length` bytes.
+///
+/// A single range is relayed as is. Multiple ranges are relayed as a `multipart/byteranges`
+/// body delimited with `boundary` where each part carries the `Content-Type` (if given) and
+/// the `Content-Range` headers. The response must then declare the
+/// `multipart/byteranges; boundary=...` content type.
+pub async fn relay_ranges<I, O>(input: &mut I, output: &mut O, ranges: &[(u64, u64)], length: u64, content_type: Option<&str>, boundary: &str) -> Result<usize, Error>
+    where
+    I: Read + Seek + Unpin,
+    O: Write + Unpin,
+{
+    if let [(first, last)] = ranges {
+        input.seek(SeekFrom::Start(*first)).await?;
+        return relay_exact(input, output, (last - first + 1) as usize).await;
+    }
+
+    let mut total = 0;
+    for range in ranges.iter() {
+        let head = part_head(*range, length, content_type, boundary);
+        output.write_all(head.as_bytes()).await?;
+        input.seek(SeekFrom::Start(range.0)).await?;
+        total += head.len() + relay_exact(input, output, (range.1 - range.0 + 1) as usize).await?;
+        output.write_all(b"\r\n").await?;
+        total += 2;
+    }
+    let end = format!("--{}--\r\n", boundary);
+    output.write_all(end.as_bytes()).await?;
+    flush_write(output).await?;
+    total += end.len();
+
+    Ok(total)
+}
+
+fn part_head(range: (u64, u64), length: u64, content_type: Option<&str>, boundary: &str) -> String {
+    let mut head = format!("--{}\r\n", boundary);
+    if let Some(content_type) = content_type {
+        head.push_str(&format!("Content-Type: {}\r\n", content_type));
+    }
+    head.push_str(&format!("Content-Range: {}\r\n\r\n", format_content_range(range, length)));
+    head
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use async_std::io::Cursor;
+
+    /// Accepts a single byte per write.
+    struct ShortWriter(Vec<u8>);
+
+    impl Write for ShortWriter {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+            let size = buf.len().min(1);
+            self.get_mut().0.extend_from_slice(&buf[..size]);
+            Poll::Ready(Ok(size))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn parses_range() {
+        let ranges = parse_range("bytes=0-499, 500-, -200").unwrap();
+        assert_eq!(ranges, vec![ByteRange::Bounded(0, 499), ByteRange::From(500), ByteRange::Suffix(200)]);
+        assert!(parse_range("bytes=5-1").is_err());
+        assert!(parse_range("bytes=-").is_err());
+        assert!(parse_range("items=0-1").is_err());
+        assert!(parse_range("bytes=").is_err());
+    }
+
+    #[test]
+    fn resolves_ranges() {
+        let ranges = parse_range("bytes=500-600,601-999,0-10,5-20").unwrap();
+        assert_eq!(resolve_ranges(&ranges, 10000).unwrap(), vec![(0, 20), (500, 999)]);
+        let ranges = parse_range("bytes=-500,9500-").unwrap();
+        assert_eq!(resolve_ranges(&ranges, 10000).unwrap(), vec![(9500, 9999)]);
+        let ranges = parse_range("bytes=0-99999").unwrap();
+        assert_eq!(resolve_ranges(&ranges, 100).unwrap(), vec![(0, 99)]);
+        let ranges = parse_range("bytes=100-,-0").unwrap();
+        assert_eq!(resolve_ranges(&ranges, 100), Err(Status::RequestedRangeNotSatisfiable));
+    }
+
+    #[test]
+    fn formats_content_range() {
+        assert_eq!(format_content_range((0, 499), 1234), "bytes 0-499/1234");
+        assert_eq!(format_unsatisfied_range(1234), "bytes */1234");
+    }
+
+    #[async_std::test]
+    async fn relays_ranges() {
+        let mut output = Vec::new();
+        let size = relay_ranges(&mut Cursor::new(b"0123456789"), &mut output, &[(2, 4)], 10, None, "b").await.unwrap();
+        assert_eq!(size, 3);
+        assert_eq!(output, b"234");
+
+        let mut output = Vec::new();
+        let ranges = [(0, 1), (8, 9)];
+        let size = relay_ranges(&mut Cursor::new(b"0123456789"), &mut output, &ranges, 10, Some("text/plain"), "b").await.unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "--b\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n--b\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--b--\r\n");
+        assert_eq!(size as u64, ranges_length(&ranges, 10, Some("text/plain"), "b"));
+        let mut short = ShortWriter(Vec::new());
+        let size = relay_ranges(&mut Cursor::new(b"0123456789"), &mut short, &ranges, 10, Some("text/plain"), "b").await.unwrap();
+        assert_eq!(size as u64, ranges_length(&ranges, 10, Some("text/plain"), "b"));
+        assert_eq!(short.0.len(), size);
+    }
+}
@@ -1,24 +1,26 @@
-use std::io::{Error, ErrorKind};
+use std::io::{Error};
 use async_std::prelude::*;
 use async_std::io::{Read};
+use crate::{HttpError, Limit, Limits, offset_error};
 
-pub async fn read_first_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>, &mut Vec<u8>), limit: Option<usize>) -> Result<usize, Error>
+pub async fn read_first_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>, &mut Vec<u8>), limits: &Limits) -> Result<usize, Error>
     where
     I: Read + Unpin,
 {
+    let limit = limits.first_line;
     let mut part = 0;
     let mut length = 0;
     let mut stage = 0; // 0..data, 1..\r, 2..\n
 
     loop {
         let mut bytes = [0u8];
-        let size = input.read(&mut bytes).await?;
+        let size = input.read(&mut bytes).await.map_err(|e| offset_error(e, length))?;
         length += size;
 
         if size == 0 {
-            break;
-        } else if limit.is_some() && limit.unwrap() < length { // method + url + version = 2065
-            return Err(Error::new(ErrorKind::InvalidData, "The operation hit the limit of {} bytes while reading the HTTP first line."));
+            return eof(length);
+        } else if let Some(max) = limit.filter(|max| *max < length) { // method + url + version = 2065
+            return Err(HttpError::LimitExceeded { limit: Limit::FirstLine, max, offset: length }.into());
         } else if bytes[0] == 32 { // space
             part += 1;
             continue;
@@ -29,7 +31,7 @@ pub async fn read_first_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>
             if stage == 1 {
                 break;
             } else {
-                return Err(Error::new(ErrorKind::InvalidData, "The data is not a valid HTTP first line."));
+                return Err(HttpError::MalformedFirstLine { offset: length }.into());
             }
         }
 
@@ -43,22 +45,23 @@ pub async fn read_first_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>
     Ok(length)
 }
 
-pub async fn read_header_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>), limit: Option<usize>) -> Result<usize, Error>
+pub async fn read_header_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>), limits: &Limits) -> Result<usize, Error>
     where
     I: Read + Unpin,
 {
+    let limit = limits.header_line;
     let mut length = 0;
     let mut stage = 0; // 0..name, 1..:, 2..space, 3..value, 4..\r, 5..\n
 
     loop {
         let mut bytes = [0u8];
-        let size = input.read(&mut bytes).await?;
+        let size = input.read(&mut bytes).await.map_err(|e| offset_error(e, length))?;
         length += size;
 
         if size == 0 {
-            break;
-        } else if limit.is_some() && limit.unwrap() < length {
-            return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while reading the HTTP header line.", limit.unwrap())));
+            return eof(length);
+        } else if let Some(max) = limit.filter(|max| *max < length) {
+            return Err(HttpError::LimitExceeded { limit: Limit::HeaderLine, max, offset: length }.into());
         } else if stage == 0 && bytes[0] == 58 { // first :
             stage = 1;
             continue;
@@ -70,13 +73,13 @@ pub async fn read_header_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8
                 stage = 3;
                 continue;
             } else {
-                return Err(Error::new(ErrorKind::InvalidData, "The data is not a valid HTTP header line."));
+                return Err(HttpError::MalformedHeader { offset: length }.into());
             }
         } else if bytes[0] == 10 { // first/second \n
             if stage == 3 {
                 break;
             } else {
-                return Err(Error::new(ErrorKind::InvalidData, "The data is not a valid HTTP header line."));
+                return Err(HttpError::MalformedHeader { offset: length }.into());
             }
         }
 
@@ -90,18 +93,68 @@ pub async fn read_header_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8
     Ok(length)
 }
 
+/// Reads header lines up to the empty line which ends the head.
+///
+/// The `header_line`, `headers` and `head` limits apply.
+pub async fn read_headers<I>(input: &mut I, data: &mut Vec<(String, String)>, limits: &Limits) -> Result<usize, Error>
+    where
+    I: Read + Unpin,
+{
+    let mut length = 0;
+    let mut count = 0;
+
+    loop {
+        let (mut name, mut value) = (vec![], vec![]);
+        let size = read_header_line(input, (&mut name, &mut value), limits).await.map_err(|e| offset_error(e, length))?;
+        length += size;
+
+        if size == 0 {
+            return Err(HttpError::UnexpectedEof { offset: length }.into());
+        } else if let Some(max) = limits.head.filter(|max| length > *max) {
+            return Err(HttpError::LimitExceeded { limit: Limit::Head, max, offset: length }.into());
+        } else if name.is_empty() {
+            break; // end of head
+        }
+
+        count += 1;
+        if let Some(max) = limits.headers.filter(|max| count > *max) {
+            return Err(HttpError::LimitExceeded { limit: Limit::Headers, max, offset: length }.into());
+        }
+        match (String::from_utf8(name), String::from_utf8(value)) {
+            (Ok(name), Ok(value)) => data.push((name, value)),
+            _ => return Err(HttpError::MalformedHeader { offset: length }.into()),
+        };
+    }
+
+    Ok(length)
+}
+
+/// Reads exactly `length` bytes. The buffer grows with the received data so that a length
+/// announced by the peer is never allocated upfront.
 pub async fn read_exact<I>(input: &mut I, data: &mut Vec<u8>, length: usize) -> Result<usize, Error>
     where
     I: Read + Unpin,
 {
-    let mut bytes = vec![0u8; length];
-    input.read_exact(&mut bytes).await?;
-    data.append(&mut bytes);
+    let mut bytes = vec![0u8; length.min(16 * 1024)];
+    let mut total = 0;
+
+    while total < length {
+        let max = bytes.len().min(length - total);
+        let size = input.read(&mut bytes[..max]).await.map_err(|e| offset_error(e, total))?;
+        if size == 0 {
+            return Err(HttpError::UnexpectedEof { offset: total }.into());
+        }
+        data.extend_from_slice(&bytes[..size]);
+        total += size;
+    }
 
     Ok(length)
 }
 
-pub async fn read_chunk_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>), limit: Option<usize>) -> Result<usize, Error>
+/// Reads a chunk line into its size and extensions.
+///
+/// The `extension` limit applies. Sizes longer than 16 hexadecimal digits are rejected.
+pub async fn read_chunk_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>), limits: &Limits) -> Result<usize, Error>
     where
     I: Read + Unpin,
 {
@@ -110,13 +163,11 @@ pub async fn read_chunk_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>
 
     loop {
         let mut bytes = [0u8];
-        let size = input.read(&mut bytes).await?;
+        let size = input.read(&mut bytes).await.map_err(|e| offset_error(e, length))?;
         length += size;
 
         if size == 0 { // end of data
-            break;
-        } else if limit.is_some() && limit.unwrap() < length {
-            return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while reading the HTTP body chunk line.", limit.unwrap())));
+            return eof(length);
         } else if stage == 0 && bytes[0] == 59 { // char ;
             stage = 1;
             continue;
@@ -125,7 +176,7 @@ pub async fn read_chunk_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>
                 stage = 2;
                 continue;
             } else {
-                return Err(Error::new(ErrorKind::InvalidData, "The data is not a valid HTTP chunk line."));
+                return Err(HttpError::MalformedChunk { offset: length }.into());
             }
         } else if bytes[0] == 10 { // char \n
             break;
@@ -135,27 +186,33 @@ pub async fn read_chunk_line<I>(input: &mut I, data: (&mut Vec<u8>, &mut Vec<u8>
             1 => data.1.push(bytes[0]),
             _ => (),
         };
+
+        if data.0.len() > 16 {
+            return Err(HttpError::MalformedChunk { offset: length }.into());
+        } else if let Some(max) = limits.extension.filter(|max| data.1.len() > *max) {
+            return Err(HttpError::LimitExceeded { limit: Limit::Extension, max, offset: length }.into());
+        }
     }
 
     Ok(length)
 }
 
-pub async fn read_chunks<I>(input: &mut I, data: &mut Vec<u8>, limit: Option<usize>) -> Result<usize, Error>
+/// Reads a chunked body and its trailer section. Returns the number of bytes read.
+///
+/// The `body`, `chunk`, `extension`, `header_line` and `trailers` limits apply.
+pub async fn read_chunks<I>(input: &mut I, data: &mut Vec<u8>, limits: &Limits) -> Result<usize, Error>
     where
     I: Read + Unpin,
 {
     let mut length = 0;
+    let mut total = 0; // actual data size
 
     loop {
-        let limit = match limit {
-            Some(limit) => Some(limit - length),
-            None => None,
-        };
         let mut buff = Vec::new();
-        let size = read_chunk(input, &mut buff, limit).await?;
-        length += size;
+        length += read_body_chunk(input, &mut buff, limits, total).await.map_err(|e| offset_error(e, length))?;
+        total += buff.len();
 
-        if size == 0 || buff.len() == 0 {
+        if buff.is_empty() {
             break; // last chunk
         } else {
             data.append(&mut buff);
@@ -165,30 +222,84 @@ pub async fn read_chunks<I>(input: &mut I, data: &mut Vec<u8>, limit: Option<usi
     Ok(length)
 }
 
-pub async fn read_chunk<I>(input: &mut I, data: &mut Vec<u8>, limit: Option<usize>) -> Result<usize, Error>
+/// Reads a single chunk. The last chunk also reads the trailer section.
+pub async fn read_chunk<I>(input: &mut I, data: &mut Vec<u8>, limits: &Limits) -> Result<usize, Error>
+    where
+    I: Read + Unpin,
+{
+    read_body_chunk(input, data, limits, 0).await
+}
+
+/// Reads trailer lines up to the empty line which ends the chunked body.
+pub(crate) async fn read_trailer_lines<I>(input: &mut I, data: &mut Vec<(Vec<u8>, Vec<u8>)>, limits: &Limits) -> Result<usize, Error>
+    where
+    I: Read + Unpin,
+{
+    let mut length = 0;
+
+    loop {
+        let (mut name, mut value) = (vec![], vec![]);
+        let size = read_header_line(input, (&mut name, &mut value), limits).await.map_err(|e| offset_error(e, length))?;
+        length += size;
+
+        if size == 0 {
+            return Err(HttpError::UnexpectedEof { offset: length }.into());
+        } else if let Some(max) = limits.trailers.filter(|max| length > *max) {
+            return Err(HttpError::LimitExceeded { limit: Limit::Trailers, max, offset: length }.into());
+        } else if name.is_empty() {
+            break;
+        }
+        data.push((name, value));
+    }
+
+    Ok(length)
+}
+
+async fn read_body_chunk<I>(input: &mut I, data: &mut Vec<u8>, limits: &Limits, total: usize) -> Result<usize, Error>
     where
     I: Read + Unpin,
 {
     let (mut length, mut ext) = (vec![], vec![]);
-    let mut size = read_chunk_line(input, (&mut length, &mut ext), limit).await?;
-    let length = match String::from_utf8(length) {
-        Ok(length) => match i64::from_str_radix(&length, 16) {
-            Ok(length) => length as usize,
-            Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
-        },
-        Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+    let mut size = read_chunk_line(input, (&mut length, &mut ext), limits).await?;
+    let length = match parse_chunk_size(&length) {
+        Some(length) => length,
+        None if size == 0 => return Err(HttpError::UnexpectedEof { offset: 0 }.into()),
+        None => return Err(HttpError::MalformedChunk { offset: size }.into()),
     };
 
-    if limit.is_some() && length > limit.unwrap() {
-        return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while reading the HTTP body chunk.", limit.unwrap())));
+    if let Some(max) = limits.chunk.filter(|max| length > *max) {
+        return Err(HttpError::LimitExceeded { limit: Limit::Chunk, max, offset: size }.into());
+    } else if let Some(max) = limits.body.filter(|max| total.saturating_add(length) > *max) {
+        return Err(HttpError::LimitExceeded { limit: Limit::Body, max, offset: size }.into());
+    } else if length == 0 {
+        size += read_trailer_lines(input, &mut Vec::new(), limits).await.map_err(|e| offset_error(e, size))?;
     } else {
-        size += read_exact(input, data, length).await?;
-        size += read_exact(input, &mut Vec::new(), 2).await?;
+        size += read_exact(input, data, length).await.map_err(|e| offset_error(e, size))?;
+        let mut end = Vec::with_capacity(2);
+        size += read_exact(input, &mut end, 2).await.map_err(|e| offset_error(e, size))?;
+        if end != b"\r\n" {
+            return Err(HttpError::MalformedChunk { offset: size }.into());
+        }
     }
 
     Ok(size)
 }
 
+/// Parses the hexadecimal size of a chunk line.
+pub(crate) fn parse_chunk_size(data: &[u8]) -> Option<usize> {
+    std::str::from_utf8(data).ok()
+        .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|size| usize::from_str_radix(size, 16).ok())
+}
+
+/// Accepts the end of the input only before the first byte of a line.
+fn eof(length: usize) -> Result<usize, Error> {
+    match length {
+        0 => Ok(0),
+        offset => Err(HttpError::UnexpectedEof { offset }.into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -196,59 +307,90 @@ mod tests {
     #[async_std::test]
     async fn reads_first_line() {
         let (mut a, mut b, mut c) = (vec![], vec![], vec![]);
-        let size = read_first_line(&mut "OPTIONS /path HTTP/1.1\r\n".as_bytes(), (&mut a, &mut b, &mut c), None).await.unwrap();
+        let size = read_first_line(&mut "OPTIONS /path HTTP/1.1\r\n".as_bytes(), (&mut a, &mut b, &mut c), &Limits::unlimited()).await.unwrap();
         assert_eq!(size, 24);
         assert_eq!(a, b"OPTIONS");
         assert_eq!(b, b"/path");
         assert_eq!(c, b"HTTP/1.1");
         let (mut a, mut b, mut c) = (vec![], vec![], vec![]);
-        let exceeded = read_first_line(&mut "OPTI\r\n".as_bytes(), (&mut a, &mut b, &mut c), Some(1)).await;
-        assert!(exceeded.is_err());
+        let exceeded = read_first_line(&mut "OPTI\r\n".as_bytes(), (&mut a, &mut b, &mut c), &Limits::new().with_first_line(Some(1))).await.unwrap_err();
+        assert_eq!(HttpError::from_io(&exceeded).unwrap().status(), crate::Status::UriTooLong);
+        let size = read_first_line(&mut "".as_bytes(), (&mut a, &mut b, &mut c), &Limits::unlimited()).await.unwrap();
+        assert_eq!(size, 0);
     }
 
     #[async_std::test]
     async fn reads_header() {
         let (mut name, mut value) = (vec![], vec![]);
-        let size = read_header_line(&mut "Foo: foo\r\nBar: bar\r\n".as_bytes(), (&mut name, &mut value), None).await.unwrap();
+        let size = read_header_line(&mut "Foo: foo\r\nBar: bar\r\n".as_bytes(), (&mut name, &mut value), &Limits::unlimited()).await.unwrap();
         assert_eq!(size, 10);
         assert_eq!(name, b"Foo");
         assert_eq!(value, b"foo");
         let (mut name, mut value) = (vec![], vec![]);
-        let size = read_header_line(&mut "\r\n".as_bytes(), (&mut name, &mut value), None).await.unwrap();
+        let size = read_header_line(&mut "\r\n".as_bytes(), (&mut name, &mut value), &Limits::unlimited()).await.unwrap();
         assert_eq!(size, 2);
         assert_eq!(name, b"");
         assert_eq!(value, b"");
-        let exceeded = read_header_line(&mut "Foo".as_bytes(), (&mut name, &mut value), Some(1)).await;
+        let exceeded = read_header_line(&mut "Foo".as_bytes(), (&mut name, &mut value), &Limits::new().with_header_line(Some(1))).await;
         assert!(exceeded.is_err());
     }
 
+    #[async_std::test]
+    async fn reads_headers() {
+        let mut headers = Vec::new();
+        let size = read_headers(&mut "Foo: foo\r\nBar: bar\r\n\r\nbody".as_bytes(), &mut headers, &Limits::unlimited()).await.unwrap();
+        assert_eq!(size, 22);
+        assert_eq!(headers, vec![(String::from("Foo"), String::from("foo")), (String::from("Bar"), String::from("bar"))]);
+        let truncated = read_headers(&mut "Foo: foo\r\nBar: b".as_bytes(), &mut headers, &Limits::unlimited()).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&truncated), Some(HttpError::UnexpectedEof { offset: 16 })));
+        let malformed = read_headers(&mut "Foo: foo\r\nBar\n".as_bytes(), &mut headers, &Limits::unlimited()).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&malformed), Some(HttpError::MalformedHeader { offset: 14 })));
+        let input = "Foo: foo\r\nBar: bar\r\n\r\n";
+        let exceeded = read_headers(&mut input.as_bytes(), &mut headers, &Limits::new().with_headers(Some(1))).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&exceeded), Some(HttpError::LimitExceeded { limit: Limit::Headers, offset: 20, .. })));
+        let exceeded = read_headers(&mut input.as_bytes(), &mut headers, &Limits::new().with_head(Some(12))).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&exceeded), Some(HttpError::LimitExceeded { limit: Limit::Head, offset: 20, .. })));
+    }
+
     #[async_std::test]
     async fn reads_exact() {
         let mut output = Vec::new();
         read_exact(&mut "0123456789".as_bytes(), &mut output, 5).await.unwrap();
         assert_eq!(String::from_utf8(output).unwrap(), "01234");
+        let truncated = read_exact(&mut "012".as_bytes(), &mut Vec::new(), 5).await.unwrap_err();
+        assert_eq!(truncated.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(HttpError::from_io(&truncated).unwrap().offset(), 3);
     }
 
     #[async_std::test]
     async fn reads_chunk_line() {
         let (mut number, mut ext) = (vec![], vec![]);
-        let size = read_chunk_line(&mut "6;ex;ex\r\n".as_bytes(), (&mut number, &mut ext), None).await.unwrap();
+        let size = read_chunk_line(&mut "6;ex;ex\r\n".as_bytes(), (&mut number, &mut ext), &Limits::unlimited()).await.unwrap();
         assert_eq!(size, 9);
         assert_eq!(String::from_utf8(number).unwrap(), "6");
         assert_eq!(String::from_utf8(ext).unwrap(), "ex;ex");
         let (mut number, mut ext) = (vec![], vec![]);
-        let exceeded = read_chunk_line(&mut "6\r\n".as_bytes(), (&mut number, &mut ext), Some(1)).await;
+        let exceeded = read_chunk_line(&mut "6;ex\r\n".as_bytes(), (&mut number, &mut ext), &Limits::new().with_extension(Some(1))).await;
         assert!(exceeded.is_err());
+        let (mut number, mut ext) = (vec![], vec![]);
+        let malformed = read_chunk_line(&mut "00000000000000000\r\n".as_bytes(), (&mut number, &mut ext), &Limits::unlimited()).await;
+        assert!(malformed.is_err());
     }
 
     #[async_std::test]
     async fn reads_chunks() {
         let mut output = Vec::new();
-        let size = read_chunks(&mut "6\r\nHello \r\n6;ex=fo\r\nWorld!\r\n0\r\n\r\nTrail: er\r\n\r\n".as_bytes(), &mut output, None).await.unwrap(); // with extension `ex=fo` and trailer `Trail: er`
-        assert_eq!(size, 33);
+        let size = read_chunks(&mut "6\r\nHello \r\n6;ex=fo\r\nWorld!\r\n0\r\nTrail: er\r\n\r\n".as_bytes(), &mut output, &Limits::unlimited()).await.unwrap(); // with extension `ex=fo` and trailer `Trail: er`
+        assert_eq!(size, 44);
         assert_eq!(String::from_utf8(output).unwrap(), "Hello World!");
         let mut output = Vec::new();
-        let exceeded = read_chunks(&mut "6\r\nHello 0\r\n\r\n".as_bytes(), &mut output, Some(1)).await;
-        assert!(exceeded.is_err());
+        let exceeded = read_chunks(&mut "6\r\nHello \r\n0\r\n\r\n".as_bytes(), &mut output, &Limits::new().with_body(Some(1))).await.unwrap_err();
+        assert_eq!(HttpError::from_io(&exceeded).unwrap().status(), crate::Status::PayloadTooLarge);
+        let exceeded = read_chunks(&mut "0\r\nTrail: er\r\n\r\n".as_bytes(), &mut output, &Limits::new().with_trailers(Some(4))).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&exceeded), Some(HttpError::LimitExceeded { limit: Limit::Trailers, offset: 14, .. })));
+        let malformed = read_chunks(&mut "3\r\nabc\r\nzz\r\n".as_bytes(), &mut output, &Limits::unlimited()).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&malformed), Some(HttpError::MalformedChunk { offset: 12 })));
+        let exceeded = read_chunks(&mut "5\r\nhello\r\nffffffffffffffff\r\n".as_bytes(), &mut output, &Limits::unlimited().with_body(Some(10))).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&exceeded), Some(HttpError::LimitExceeded { limit: Limit::Body, .. })));
     }
 }
@@ -1,7 +1,8 @@
-use std::io::{Error, ErrorKind};
+use std::io::{Error};
 use async_std::prelude::*;
 use async_std::io::{Read, Write};
-use crate::{read_chunk_line, write_slice, flush_write};
+use crate::{read_chunk_line, read_exact, read_trailer_lines, parse_chunk_size, write_slice, flush_write, HttpError, Limit, Limits,
+    offset_error};
 
 pub async fn relay_exact<I, O>(input: &mut I, output: &mut O, length: usize) -> Result<usize, Error>
     where
@@ -22,11 +23,15 @@ pub async fn relay_exact<I, O>(input: &mut I, output: &mut O, length: usize) ->
         };
 
         let mut bytes = vec![0u8; bufsize];
-        let size = input.read(&mut bytes).await?;
-        total += size;
+        let size = input.read(&mut bytes).await.map_err(|e| offset_error(e, total))?;
 
-        write_slice(output, &bytes).await?;
-        flush_write(output).await?;
+        if size == 0 {
+            return Err(HttpError::UnexpectedEof { offset: total }.into());
+        }
+
+        output.write_all(&bytes[0..size]).await.map_err(|e| offset_error(e, total))?;
+        flush_write(output).await.map_err(|e| offset_error(e, total))?;
+        total += size;
 
         if total == length {
             break;
@@ -36,43 +41,63 @@ pub async fn relay_exact<I, O>(input: &mut I, output: &mut O, length: usize) ->
     Ok(total)
 }
 
-pub async fn relay_chunks<I, O>(input: &mut I, output: &mut O, limits: (Option<usize>, Option<usize>)) -> Result<usize, Error>
+/// Relays a chunked body and its trailer section. Returns the number of bytes written.
+///
+/// The `body`, `chunk`, `extension`, `header_line` and `trailers` limits apply.
+pub async fn relay_chunks<I, O>(input: &mut I, output: &mut O, limits: &Limits) -> Result<usize, Error>
     where
     I: Read + Unpin,
     O: Write + Unpin,
 {
-    let (chunklimit, datalimit) = limits;
     let mut length = 0;
-    let mut total = 0; // actual data size
+    let mut total: usize = 0; // actual data size
 
     loop {
-        let (mut size, mut ext) = (vec![], vec![]);
-        read_chunk_line(input, (&mut size, &mut ext), chunklimit).await?;
+        let (mut hex, mut ext) = (vec![], vec![]);
+        let line = read_chunk_line(input, (&mut hex, &mut ext), limits).await.map_err(|e| offset_error(e, length))?;
+        if line == 0 {
+            return Err(HttpError::UnexpectedEof { offset: length }.into());
+        }
+
+        let size = match parse_chunk_size(&hex) {
+            Some(size) => size,
+            None => return Err(HttpError::MalformedChunk { offset: length }.into()),
+        };
+
+        if let Some(max) = limits.chunk.filter(|max| size > *max) {
+            return Err(HttpError::LimitExceeded { limit: Limit::Chunk, max, offset: length }.into());
+        } else if let Some(max) = limits.body.filter(|max| total.saturating_add(size) > *max) {
+            return Err(HttpError::LimitExceeded { limit: Limit::Body, max, offset: length }.into());
+        }
 
-        length += write_slice(output, &size).await?;
+        length += write_slice(output, &hex).await?;
         if !ext.is_empty() {
             length += write_slice(output, b";").await?;
             length += write_slice(output, &ext).await?;
         }
         length += write_slice(output, b"\r\n").await?;
 
-        let size = match String::from_utf8(size) {
-            Ok(length) => match i64::from_str_radix(&length, 16) {
-                Ok(length) => length as usize,
-                Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
-            },
-            Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
-        };
-
         if size == 0 {
-            length += relay_exact(input, output, 2).await?;
+            let mut trailers = Vec::new();
+            read_trailer_lines(input, &mut trailers, limits).await.map_err(|e| offset_error(e, length))?;
+            for (name, value) in trailers {
+                length += write_slice(output, &name).await?;
+                length += write_slice(output, b": ").await?;
+                length += write_slice(output, &value).await?;
+                length += write_slice(output, b"\r\n").await?;
+            }
+            length += write_slice(output, b"\r\n").await?;
+            flush_write(output).await?;
             break; // last chunk
-        } else if datalimit.is_some() && total + size > datalimit.unwrap() {
-            return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while relaying chunked HTTP body.", datalimit.unwrap())));
         } else {
-            total += size;
-            length += relay_exact(input, output, size).await?;
-            length += relay_exact(input, output, 2).await?;
+            total = total.saturating_add(size);
+            length += relay_exact(input, output, size).await.map_err(|e| offset_error(e, length))?;
+            let mut end = Vec::with_capacity(2);
+            read_exact(input, &mut end, 2).await.map_err(|e| offset_error(e, length))?;
+            if end != b"\r\n" {
+                return Err(HttpError::MalformedChunk { offset: length }.into());
+            }
+            length += write_slice(output, &end).await?;
         }
     }
 
@@ -94,11 +119,32 @@ mod tests {
     #[async_std::test]
     async fn relays_chunks() {
         let mut output = Vec::new();
-        let size = relay_chunks(&mut "6\r\nHello \r\n6;ex;ey\r\nWorld!\r\n0\r\n\r\nFoo: bar\r\n\r\n".as_bytes(), &mut output, (None, None)).await.unwrap();
-        assert_eq!(size, 33);
-        assert_eq!(output, "6\r\nHello \r\n6;ex;ey\r\nWorld!\r\n0\r\n\r\n".as_bytes());
+        let size = relay_chunks(&mut "6\r\nHello \r\n6;ex;ey\r\nWorld!\r\n0\r\nFoo: bar\r\n\r\n".as_bytes(), &mut output, &Limits::unlimited()).await.unwrap();
+        assert_eq!(size, 43);
+        assert_eq!(output, "6\r\nHello \r\n6;ex;ey\r\nWorld!\r\n0\r\nFoo: bar\r\n\r\n".as_bytes());
+        let mut output = Vec::new();
+        let exceeds = relay_chunks(&mut "3\r\nHel\r\n0;ex;".as_bytes(), &mut output, &Limits::new().with_body(Some(2))).await;
+        assert!(exceeds.is_err());
+        let mut output = Vec::new();
+        let exceeds = relay_chunks(&mut "3\r\nHel\r\n0\r\n".as_bytes(), &mut output, &Limits::new().with_chunk(Some(2))).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&exceeds), Some(HttpError::LimitExceeded { limit: Limit::Chunk, .. })));
+        assert!(output.is_empty());
         let mut output = Vec::new();
-        let exceeds = relay_chunks(&mut "3\r\nHel\r\n0;ex;".as_bytes(), &mut output, (None, Some(2))).await;
+        let exceeds = relay_chunks(&mut "5\r\nhello\r\nffffffffffffffff\r\n".as_bytes(), &mut output, &Limits::unlimited().with_body(Some(10))).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&exceeds), Some(HttpError::LimitExceeded { limit: Limit::Body, .. })));
+        let exceeds = relay_chunks(&mut "5\r\nhello\r\nffffffffffffffff\r\n".as_bytes(), &mut Vec::new(), &Limits::unlimited()).await;
         assert!(exceeds.is_err());
     }
+
+    #[async_std::test]
+    async fn rejects_malformed_chunks() {
+        let mut output = Vec::new();
+        let error = relay_chunks(&mut "3\r\nHelXX0\r\n\r\n".as_bytes(), &mut output, &Limits::unlimited()).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&error), Some(HttpError::MalformedChunk { offset: 6 })));
+        assert_eq!(output, b"3\r\nHel");
+        let mut output = Vec::new();
+        let error = relay_chunks(&mut "x\r\nHel\r\n".as_bytes(), &mut output, &Limits::unlimited()).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&error), Some(HttpError::MalformedChunk { offset: 0 })));
+        assert!(output.is_empty());
+    }
 }
@@ -0,0 +1,314 @@
+use std::fmt::{self, Display};
+use std::io::{Error, ErrorKind};
+use async_std::prelude::*;
+use async_std::io::{Read, Write};
+use crate::write_chunk;
+
+/// Event of a `text/event-stream` body.
+///
+/// See [the spec](https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation)
+/// for more details.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Event {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl Event {
+
+    pub fn new(data: &str) -> Self {
+        Self {
+            data: data.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_event(mut self, event: &str) -> Self {
+        self.event = Some(event.to_string());
+        self
+    }
+
+    pub fn with_id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// Sets the reconnection time in milliseconds.
+    pub fn with_retry(mut self, retry: u64) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// The event type. `None` stands for the default `message` type.
+    pub fn event(&self) -> Option<&str> {
+        self.event.as_deref()
+    }
+
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    /// The event ID. Events read by `read_event` carry the last event ID of the stream.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn retry(&self) -> Option<u64> {
+        self.retry
+    }
+}
+
+/// Formats the event fields including the terminating blank line. Data lines are split on
+/// any line break.
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {}", event)?;
+        }
+        for line in self.data.replace("\r\n", "\n").split(['\r', '\n']) {
+            writeln!(f, "data: {}", line)?;
+        }
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {}", id)?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// State of a client reading a `text/event-stream` body.
+///
+/// The last event ID survives a dropped connection and should be sent in the `Last-Event-ID`
+/// header when reconnecting.
+#[derive(Debug, Clone, Default)]
+pub struct EventParser {
+    last_event_id: String,
+    retry: Option<u64>,
+    event: Option<String>,
+    data: Option<String>,
+    block_retry: Option<u64>,
+    started: bool,
+    after_cr: bool,
+}
+
+impl EventParser {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a parser for a reconnection which continues with `last_event_id`.
+    pub fn with_last_event_id(last_event_id: &str) -> Self {
+        Self {
+            last_event_id: last_event_id.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the value for the `Last-Event-ID` header of a reconnection.
+    pub fn last_event_id(&self) -> Option<&str> {
+        match self.last_event_id.is_empty() {
+            true => None,
+            false => Some(&self.last_event_id),
+        }
+    }
+
+    /// Returns the reconnection time in milliseconds last requested by the server.
+    pub fn retry(&self) -> Option<u64> {
+        self.retry
+    }
+
+    /// Processes a line without its line break and returns an event when a blank line ends it.
+    pub fn push_line(&mut self, line: &str) -> Option<Event> {
+        let line = match self.started {
+            true => line,
+            false => line.strip_prefix('\u{feff}').unwrap_or(line),
+        };
+        self.started = true;
+
+        if line.is_empty() {
+            return self.dispatch();
+        } else if line.starts_with(':') {
+            return None; // comment
+        }
+        let (name, value) = match line.find(':') {
+            Some(index) => (&line[..index], &line[index + 1..]),
+            None => (line, ""),
+        };
+        let value = value.strip_prefix(' ').unwrap_or(value);
+
+        match name {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                let data = self.data.get_or_insert_with(String::new);
+                data.push_str(value);
+                data.push('\n');
+            },
+            "id" if !value.contains('\0') => self.last_event_id = value.to_string(),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                self.retry = value.parse().ok();
+                self.block_retry = self.retry;
+            },
+            _ => (),
+        };
+        None
+    }
+
+    /// Discards an incomplete event when the stream ends. A reconnected stream starts afresh.
+    fn discard(&mut self) {
+        self.event = None;
+        self.data = None;
+        self.block_retry = None;
+        self.started = false;
+        self.after_cr = false;
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = self.event.take().filter(|e| !e.is_empty() && e != "message");
+        let retry = self.block_retry.take();
+        let mut data = self.data.take()?;
+        data.pop(); // trailing line feed
+
+        Some(Event {
+            event,
+            data,
+            id: self.last_event_id().map(String::from),
+            retry,
+        })
+    }
+}
+
+/// Writes the event as a single chunk of a chunked `text/event-stream` body and flushes it.
+///
+/// Fails if the event type or ID contains a line break.
+pub async fn write_event<O>(output: &mut O, event: &Event) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let broken = |value: &Option<String>| value.as_ref().map(|v| v.contains(['\r', '\n'])).unwrap_or(false);
+    if broken(&event.event) || broken(&event.id) || event.id.as_ref().map(|id| id.contains('\0')).unwrap_or(false) {
+        return Err(Error::new(ErrorKind::InvalidInput, "The event type and ID must be single lines."));
+    }
+
+    write_chunk(output, event.to_string().as_bytes()).await
+}
+
+/// Writes a comment as a single chunk which keeps an idle connection open. Comments are
+/// ignored by clients.
+pub async fn write_heartbeat<O>(output: &mut O, comment: &str) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let text = comment.replace("\r\n", "\n").split(['\r', '\n'])
+        .map(|line| format!(":{}\n", match line.is_empty() { true => String::new(), false => format!(" {}", line) }))
+        .collect::<String>();
+
+    write_chunk(output, text.as_bytes()).await
+}
+
+/// Reads the next event from a decoded `text/event-stream` body.
+///
+/// Returns `None` once the body ends; an incomplete event at the end is discarded. Events
+/// larger than `limit` bytes are rejected. Comments and blank lines do not count towards the
+/// next event.
+pub async fn read_event<I>(input: &mut I, parser: &mut EventParser, limit: Option<usize>) -> Result<Option<Event>, Error>
+    where
+    I: Read + Unpin,
+{
+    let mut length = 0;
+    let mut line = Vec::new();
+
+    loop {
+        let mut bytes = [0u8];
+        let size = input.read(&mut bytes).await?;
+        length += size;
+
+        if size == 0 {
+            parser.discard();
+            return Ok(None);
+        } else if limit.is_some() && limit.unwrap() < length {
+            return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while reading the event stream.", limit.unwrap())));
+        }
+
+        let after_cr = parser.after_cr;
+        parser.after_cr = bytes[0] == b'\r';
+        match bytes[0] {
+            b'\n' if after_cr => continue, // second half of \r\n
+            b'\r' | b'\n' => {
+                let text = String::from_utf8_lossy(&line).to_string();
+                line.clear();
+                if let Some(event) = parser.push_line(&text) {
+                    return Ok(Some(event));
+                } else if text.is_empty() || text.trim_start_matches('\u{feff}').starts_with(':') {
+                    length = 0; // nothing is pending after a comment or an empty event
+                }
+            },
+            b => line.push(b),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_event() {
+        let event = Event::new("first\r\nsecond\rthird").with_event("update").with_id("7").with_retry(1000);
+        assert_eq!(event.to_string(), "event: update\ndata: first\ndata: second\ndata: third\nid: 7\nretry: 1000\n\n");
+        assert_eq!(Event::new("").to_string(), "data: \n\n");
+    }
+
+    #[async_std::test]
+    async fn writes_events() {
+        let mut output = Vec::new();
+        write_event(&mut output, &Event::new("hi")).await.unwrap();
+        write_heartbeat(&mut output, "").await.unwrap();
+        assert_eq!(output, "a\r\ndata: hi\n\n\r\n2\r\n:\n\r\n".as_bytes());
+        assert!(write_event(&mut output, &Event::new("hi").with_id("1\n2")).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn reads_events() {
+        let mut input = "\u{feff}: comment\r\ndata: first\rdata:second\n\nevent: add\ndata\nid: 5\nretry: 30\n\nid\ndata: x\n\ndata: cut".as_bytes();
+        let mut parser = EventParser::new();
+        let event = read_event(&mut input, &mut parser, None).await.unwrap().unwrap();
+        assert_eq!(event, Event::new("first\nsecond"));
+        let event = read_event(&mut input, &mut parser, None).await.unwrap().unwrap();
+        assert_eq!(event, Event::new("").with_event("add").with_id("5").with_retry(30));
+        assert_eq!(parser.last_event_id(), Some("5"));
+        let event = read_event(&mut input, &mut parser, None).await.unwrap().unwrap();
+        assert_eq!(event.id(), None);
+        assert_eq!(read_event(&mut input, &mut parser, None).await.unwrap(), None);
+        assert_eq!(parser.retry(), Some(30));
+    }
+
+    #[async_std::test]
+    async fn resumes_with_last_event_id() {
+        let mut parser = EventParser::with_last_event_id("41");
+        let event = read_event(&mut "event: ping\n\ndata: 1\n\n".as_bytes(), &mut parser, None).await.unwrap().unwrap();
+        assert_eq!(event.id(), Some("41"));
+        let exceeded = read_event(&mut "data: 0123456789\n\n".as_bytes(), &mut EventParser::new(), Some(8)).await;
+        assert!(exceeded.is_err());
+    }
+
+    #[async_std::test]
+    async fn ignores_heartbeats_in_limit() {
+        let mut input = ": ping\n: ping\n\n: ping\ndata: x\n\n".as_bytes();
+        let event = read_event(&mut input, &mut EventParser::new(), Some(10)).await.unwrap();
+        assert_eq!(event, Some(Event::new("x")));
+    }
+
+    #[async_std::test]
+    async fn discards_incomplete_event_at_end() {
+        let mut parser = EventParser::new();
+        assert_eq!(read_event(&mut "event: add\nretry: 5\ndata: a\n".as_bytes(), &mut parser, None).await.unwrap(), None);
+        let event = read_event(&mut "\ndata: next\n\n".as_bytes(), &mut parser, None).await.unwrap().unwrap();
+        assert_eq!(event, Event::new("next"));
+        assert_eq!(parser.retry(), Some(5));
+    }
+}
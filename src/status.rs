@@ -365,8 +365,8 @@ impl Status {
     ///
     /// If this returns `true` it indicates that the request was received, continuing process.
     pub fn is_informational(&self) -> bool {
-        let num: u16 = self.clone().into();
-        num >= 100 && num < 200
+        let num: u16 = (*self).into();
+        (100..200).contains(&num)
     }
 
     /// Returns `true` if the status code is the `2xx` range.
@@ -374,8 +374,8 @@ impl Status {
     /// If this returns `true` it indicates that the request was successfully received, understood,
     /// and accepted.
     pub fn is_success(&self) -> bool {
-        let num: u16 = self.clone().into();
-        num >= 200 && num < 300
+        let num: u16 = (*self).into();
+        (200..300).contains(&num)
     }
 
     /// Returns `true` if the status code is the `3xx` range.
@@ -383,8 +383,8 @@ impl Status {
     /// If this returns `true` it indicates that further action needs to be taken in order to
     /// complete the request.
     pub fn is_redirection(&self) -> bool {
-        let num: u16 = self.clone().into();
-        num >= 300 && num < 400
+        let num: u16 = (*self).into();
+        (300..400).contains(&num)
     }
 
     /// Returns `true` if the status code is the `4xx` range.
@@ -392,8 +392,8 @@ impl Status {
     /// If this returns `true` it indicates that the request contains bad syntax or cannot be
     /// fulfilled.
     pub fn is_client_error(&self) -> bool {
-        let num: u16 = self.clone().into();
-        num >= 400 && num < 500
+        let num: u16 = (*self).into();
+        (400..500).contains(&num)
     }
 
     /// Returns `true` if the status code is the `5xx` range.
@@ -401,8 +401,19 @@ impl Status {
     /// If this returns `true` it indicates that the server failed to fulfill an apparently valid
     /// request.
     pub fn is_server_error(&self) -> bool {
-        let num: u16 = self.clone().into();
-        num >= 500 && num < 600
+        let num: u16 = (*self).into();
+        (500..600).contains(&num)
+    }
+
+    /// Returns `true` if the response with this status code can be reused by a cache with a
+    /// heuristic expiration time.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7231#section-6.1) for more details.
+    pub fn is_heuristically_cacheable(&self) -> bool {
+        matches!(self, Status::Ok | Status::NonAuthoritativeInformation | Status::NoContent
+            | Status::PartialContent | Status::MultipleChoice | Status::MovedPermanently
+            | Status::PermanentRedirect | Status::NotFound | Status::MethodNotAllowed | Status::Gone
+            | Status::UriTooLong | Status::NotImplemented)
     }
 
     /// Status code
@@ -428,7 +439,7 @@ impl Status {
             Status::MovedPermanently => "Moved Permanently",
             Status::Found => "Found",
             Status::SeeOther => "See Other",
-            Status::NotModified => "Modified",
+            Status::NotModified => "Not Modified",
             Status::TemporaryRedirect => "Temporary Redirect",
             Status::PermanentRedirect => "Permanent Redirect",
             Status::BadRequest => "Bad Request",
@@ -539,7 +550,7 @@ impl std::convert::TryFrom<u16> for Status {
     }
 }
 
-impl<'a> std::convert::TryFrom<&[u8]> for Status {
+impl std::convert::TryFrom<&[u8]> for Status {
     type Error = Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
@@ -599,6 +610,8 @@ mod tests {
     fn implements_data() {
         assert_eq!(Status::Ok.code(), 200);
         assert_eq!(Status::Ok.reason(), "OK");
+        assert!(Status::NotFound.is_heuristically_cacheable());
+        assert!(!Status::Found.is_heuristically_cacheable());
     }
 
     #[test]
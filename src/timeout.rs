@@ -0,0 +1,360 @@
+use std::fmt;
+use std::future::Future;
+use std::io::Error;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use async_std::io::{Read, Write};
+use async_std::task;
+use crate::{HttpError, Timeout};
+
+type Timer = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Deadlines which protect reading an HTTP/1.x message from slow peers. A `None` removes the
+/// timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Timeouts {
+    /// Time for reading the whole head, from the first line to the empty line.
+    pub head: Option<Duration>,
+    /// Time a body read or any write may wait for the peer.
+    pub idle: Option<Duration>,
+    /// Time for reading the whole body.
+    pub body: Option<Duration>,
+    /// Bytes per second at which the body must be read, checked after its first second.
+    pub min_rate: Option<usize>,
+}
+
+impl Timeouts {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns timeouts which never expire.
+    pub fn unlimited() -> Self {
+        Self {
+            head: None,
+            idle: None,
+            body: None,
+            min_rate: None,
+        }
+    }
+
+    pub fn with_head(mut self, timeout: Option<Duration>) -> Self {
+        self.head = timeout;
+        self
+    }
+
+    pub fn with_idle(mut self, timeout: Option<Duration>) -> Self {
+        self.idle = timeout;
+        self
+    }
+
+    pub fn with_body(mut self, timeout: Option<Duration>) -> Self {
+        self.body = timeout;
+        self
+    }
+
+    pub fn with_min_rate(mut self, rate: Option<usize>) -> Self {
+        self.min_rate = rate;
+        self
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            head: Some(Duration::from_secs(30)),
+            idle: Some(Duration::from_secs(30)),
+            body: None,
+            min_rate: None,
+        }
+    }
+}
+
+/// Stream which enforces `Timeouts` on the reading, writing and relaying functions it is
+/// handed to.
+///
+/// The stream starts in the head phase. Call `start_body` once the head has been read and
+/// `start_head` before reading the next message of the connection. An expired timeout fails
+/// the pending read or write with an `HttpError::TimedOut`, for which a server replies with
+/// `Status::RequestTimeout`. A stream created with `TimedStream::client` fails with an
+/// `HttpError::ClientTimedOut` instead. The error's offset counts the bytes read in the
+/// current phase.
+pub struct TimedStream<S> {
+    stream: S,
+    timeouts: Timeouts,
+    client: bool,
+    body: bool,
+    started: Instant,
+    active: Instant,
+    transferred: usize,
+    reading: Option<(Instant, Timer)>,
+    writing: Option<(Instant, Timer)>,
+}
+
+impl<S> TimedStream<S> {
+
+    pub fn new(stream: S, timeouts: Timeouts) -> Self {
+        let now = Instant::now();
+        Self { stream, timeouts, client: false, body: false, started: now, active: now, transferred: 0, reading: None, writing: None }
+    }
+
+    /// Creates a stream for a client. The head timeout covers reading the response head.
+    pub fn client(stream: S, timeouts: Timeouts) -> Self {
+        Self { client: true, ..Self::new(stream, timeouts) }
+    }
+
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// Restarts the head read timeout for the next message.
+    pub fn start_head(&mut self) {
+        self.restart(false);
+    }
+
+    /// Stops the head read timeout and starts the body timeouts.
+    pub fn start_body(&mut self) {
+        self.restart(true);
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn restart(&mut self, body: bool) {
+        let now = Instant::now();
+        self.body = body;
+        self.started = now;
+        self.active = now;
+        self.transferred = 0;
+        self.reading = None;
+    }
+
+    /// Returns the earliest deadline of the current phase. The minimum rate expires once the
+    /// next byte would arrive too late, so a peer which stops sending is caught as well.
+    fn read_deadline(&self) -> Option<(Instant, Timeout)> {
+        let deadlines = match self.body {
+            true => [
+                (self.timeouts.body.map(|t| self.started + t), Timeout::Body),
+                (self.timeouts.idle.map(|t| self.active + t), Timeout::Idle),
+                (self.timeouts.min_rate.filter(|rate| *rate > 0).map(|rate| {
+                    self.started + Duration::from_secs_f64((self.transferred + 1) as f64 / rate as f64).max(Duration::from_secs(1))
+                }), Timeout::Rate),
+            ],
+            false => [
+                (self.timeouts.head.map(|t| self.started + t), Timeout::Head),
+                (None, Timeout::Idle),
+                (None, Timeout::Rate),
+            ],
+        };
+        deadlines.iter().filter_map(|(at, timeout)| at.map(|at| (at, *timeout))).min_by_key(|(at, _)| *at)
+    }
+
+    fn check_rate(&self) -> Result<(), Error> {
+        let elapsed = self.started.elapsed();
+        match self.timeouts.min_rate {
+            Some(rate) if self.body && elapsed >= Duration::from_secs(1) && (self.transferred as f64) < rate as f64 * elapsed.as_secs_f64() => {
+                Err(self.expired(Timeout::Rate))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn expired(&self, timeout: Timeout) -> Error {
+        match self.client {
+            true => HttpError::ClientTimedOut { timeout, offset: self.transferred }.into(),
+            false => HttpError::TimedOut { timeout, offset: self.transferred }.into(),
+        }
+    }
+
+    fn poll_written<T>(&mut self, cx: &mut Context<'_>, poll: Poll<Result<T, Error>>) -> Poll<Result<T, Error>> {
+        if poll.is_ready() {
+            self.writing = None;
+            return poll;
+        }
+        let deadline = match &self.writing {
+            Some((at, _)) => Some(*at),
+            None => self.timeouts.idle.map(|t| Instant::now() + t),
+        };
+        poll_timer(&mut self.writing, cx, deadline.map(|at| (at, Timeout::Idle))).map(|timeout| Err(self.expired(timeout)))
+    }
+}
+
+impl<S> fmt::Debug for TimedStream<S>
+    where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimedStream")
+            .field("stream", &self.stream)
+            .field("timeouts", &self.timeouts)
+            .field("client", &self.client)
+            .field("body", &self.body)
+            .field("transferred", &self.transferred)
+            .finish()
+    }
+}
+
+impl<S> Read for TimedStream<S>
+    where
+    S: Read + Unpin,
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize, Error>> {
+        let this = self.get_mut();
+        let deadline = this.read_deadline();
+        if let Some((_, timeout)) = deadline.filter(|(at, _)| Instant::now() >= *at) {
+            return Poll::Ready(Err(this.expired(timeout)));
+        }
+
+        match Pin::new(&mut this.stream).poll_read(cx, buf) {
+            Poll::Ready(Ok(size)) => {
+                this.reading = None;
+                this.active = Instant::now();
+                this.transferred += size;
+                Poll::Ready(this.check_rate().map(|_| size))
+            },
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => poll_timer(&mut this.reading, cx, deadline).map(|timeout| Err(this.expired(timeout))),
+        }
+    }
+}
+
+impl<S> Write for TimedStream<S>
+    where
+    S: Write + Unpin,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_write(cx, buf);
+        this.poll_written(cx, poll)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_flush(cx);
+        this.poll_written(cx, poll)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().stream).poll_close(cx)
+    }
+}
+
+/// Waits for the `deadline` of a pending operation and keeps its timer in `slot`. Returns the
+/// expired timeout.
+fn poll_timer(slot: &mut Option<(Instant, Timer)>, cx: &mut Context<'_>, deadline: Option<(Instant, Timeout)>) -> Poll<Timeout> {
+    let (at, timeout) = match deadline {
+        Some(deadline) => deadline,
+        None => return Poll::Pending,
+    };
+    if slot.as_ref().map(|(current, _)| *current != at).unwrap_or(true) {
+        let delay = at.saturating_duration_since(Instant::now());
+        *slot = Some((at, Box::pin(task::sleep(delay))));
+    }
+
+    match slot.as_mut().map(|(_, timer)| timer.as_mut().poll(cx)) {
+        Some(Poll::Ready(())) => {
+            *slot = None;
+            Poll::Ready(timeout)
+        },
+        _ => Poll::Pending,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::io::ReadExt;
+    use crate::{read_first_line, read_headers, relay_exact, Limits, Status};
+
+    /// Reads one byte after each `delay`.
+    struct Trickle {
+        data: Vec<u8>,
+        delay: Duration,
+        timer: Option<Timer>,
+    }
+
+    impl Trickle {
+
+        fn new(data: &str, delay: Duration) -> Self {
+            Self { data: data.as_bytes().to_vec(), delay, timer: None }
+        }
+    }
+
+    impl Read for Trickle {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize, Error>> {
+            let this = self.get_mut();
+            let delay = this.delay;
+            let timer = this.timer.get_or_insert_with(|| Box::pin(task::sleep(delay)));
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.timer = None;
+            if this.data.is_empty() || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            buf[0] = this.data.remove(0);
+            Poll::Ready(Ok(1))
+        }
+    }
+
+    #[async_std::test]
+    async fn times_out_head() {
+        let input = "GET / HTTP/1.1\r\nHost: a\r\n".as_bytes().chain(Trickle::new("\r\n", Duration::from_secs(3600)));
+        let mut input = TimedStream::new(input, Timeouts::unlimited().with_head(Some(Duration::from_millis(50))));
+        let (mut method, mut target, mut version) = (vec![], vec![], vec![]);
+        read_first_line(&mut input, (&mut method, &mut target, &mut version), &Limits::default()).await.unwrap();
+        let error = read_headers(&mut input, &mut Vec::new(), &Limits::default()).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        let error = HttpError::from_io(&error).unwrap();
+        assert!(matches!(error, HttpError::TimedOut { timeout: Timeout::Head, offset: 25 }));
+        assert_eq!(error.status(), Status::RequestTimeout);
+    }
+
+    #[async_std::test]
+    async fn times_out_response() {
+        let input = Trickle::new("HTTP/1.1 200 OK\r\n\r\n", Duration::from_millis(50));
+        let mut input = TimedStream::client(input, Timeouts::unlimited().with_head(Some(Duration::from_millis(20))));
+        let (mut version, mut status, mut reason) = (vec![], vec![], vec![]);
+        let error = read_first_line(&mut input, (&mut version, &mut status, &mut reason), &Limits::default()).await.unwrap_err();
+        let error = HttpError::from_io(&error).unwrap();
+        assert!(matches!(error, HttpError::ClientTimedOut { timeout: Timeout::Head, offset: 0 }));
+        assert_eq!(error.status(), Status::GatewayTimeout);
+    }
+
+    #[async_std::test]
+    async fn times_out_body() {
+        let timeouts = Timeouts::unlimited().with_idle(Some(Duration::from_millis(20)));
+        let mut input = TimedStream::new(Trickle::new("hello", Duration::from_millis(200)), timeouts);
+        input.start_body();
+        let error = relay_exact(&mut input, &mut Vec::new(), 5).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&error), Some(HttpError::TimedOut { timeout: Timeout::Idle, offset: 0 })));
+
+        let timeouts = Timeouts::unlimited().with_min_rate(Some(100));
+        let mut input = TimedStream::new(Trickle::new(&"a".repeat(100), Duration::from_millis(20)), timeouts);
+        input.start_body();
+        let error = relay_exact(&mut input, &mut Vec::new(), 100).await.unwrap_err();
+        assert!(matches!(HttpError::from_io(&error), Some(HttpError::TimedOut { timeout: Timeout::Rate, .. })));
+
+        let timeouts = Timeouts::unlimited().with_min_rate(Some(100));
+        let mut input = TimedStream::new(Trickle::new("hello", Duration::from_secs(3600)), timeouts);
+        input.start_body();
+        let error = relay_exact(&mut input, &mut Vec::new(), 5).await.unwrap_err(); // stalled peer
+        assert!(matches!(HttpError::from_io(&error), Some(HttpError::TimedOut { timeout: Timeout::Rate, .. })));
+
+        let timeouts = Timeouts::unlimited().with_idle(Some(Duration::from_millis(100)));
+        let mut input = TimedStream::new(Trickle::new("hello", Duration::from_millis(1)), timeouts);
+        input.start_body();
+        assert_eq!(relay_exact(&mut input, &mut Vec::new(), 5).await.unwrap(), 5);
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use async_std::prelude::*;
+use async_std::io::{Read, Write};
+use crate::{read_first_line, read_headers, flush_write, parse_status, parse_version,
+    parse_connection, Limits, Status, Version};
+use crate::parse::split_list;
+
+/// Stream handed over to another protocol after an upgrade.
+///
+/// Reads return the bytes which were already buffered while parsing the HTTP/1.1 head (e.g.
+/// the content of `BufReader::buffer`) before reading from the underlying stream. Writes go
+/// to the underlying stream.
+#[derive(Debug)]
+pub struct Upgraded<S> {
+    stream: S,
+    buffered: Vec<u8>,
+}
+
+impl<S> Upgraded<S> {
+
+    pub fn new(stream: S, buffered: Vec<u8>) -> Self {
+        Self { stream, buffered }
+    }
+
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffered
+    }
+
+    /// Returns the underlying stream and the bytes which have not been read yet.
+    pub fn into_parts(self) -> (S, Vec<u8>) {
+        (self.stream, self.buffered)
+    }
+}
+
+impl<S> Read for Upgraded<S>
+    where
+    S: Read + Unpin,
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize, Error>> {
+        let this = self.get_mut();
+        if this.buffered.is_empty() {
+            return Pin::new(&mut this.stream).poll_read(cx, buf);
+        }
+        let size = buf.len().min(this.buffered.len());
+        buf[..size].copy_from_slice(&this.buffered[..size]);
+        this.buffered.drain(..size);
+        Poll::Ready(Ok(size))
+    }
+}
+
+impl<S> Write for Upgraded<S>
+    where
+    S: Write + Unpin,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().stream).poll_close(cx)
+    }
+}
+
+/// Returns `true` if an HTTP/1.1 request asks to switch protocols with the `Upgrade` header
+/// listed in its `Connection` header.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7230#section-6.7) for more details.
+pub fn is_upgrade_request(version: Version, connection: Option<&str>, upgrade: Option<&str>) -> bool {
+    let listed = connection.map(|c| parse_connection(c).iter().any(|t| t == "upgrade")).unwrap_or(false);
+    version == Version::Http1_1 && listed && upgrade.map(|u| !split_list(u).is_empty()).unwrap_or(false)
+}
+
+/// Returns the first protocol of the `Upgrade` header, in the client's order of preference,
+/// which the server supports. Protocols are compared case-insensitively.
+pub fn select_upgrade<'a>(upgrade: &str, supported: &[&'a str]) -> Option<&'a str> {
+    split_list(upgrade).into_iter()
+        .find_map(|protocol| supported.iter().find(|s| s.eq_ignore_ascii_case(protocol)).copied())
+}
+
+/// Writes the `101 Switching Protocols` response head with additional `headers`.
+pub async fn write_switching_protocols<O>(output: &mut O, protocol: &str, headers: &[(&str, &str)]) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let mut head = format!("{} {} {}\r\nConnection: Upgrade\r\nUpgrade: {}\r\n", Version::Http1_1, Status::SwitchingProtocols, Status::SwitchingProtocols.reason(), protocol);
+    for (name, value) in headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    output.write_all(head.as_bytes()).await?;
+    flush_write(output).await?;
+
+    Ok(head.len())
+}
+
+/// Writes the `426 Upgrade Required` response without body which lists the `protocols` the
+/// client must switch to.
+pub async fn write_upgrade_required<O>(output: &mut O, protocols: &[&str]) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let head = format!("{} {} {}\r\nConnection: Upgrade\r\nUpgrade: {}\r\nContent-Length: 0\r\n\r\n", Version::Http1_1, Status::UpgradeRequired, Status::UpgradeRequired.reason(), protocols.join(", "));
+
+    output.write_all(head.as_bytes()).await?;
+    flush_write(output).await?;
+
+    Ok(head.len())
+}
+
+/// Writes a `GET` request head asking the server to switch to one of the `protocols`.
+pub async fn write_upgrade_request<O>(output: &mut O, target: &str, host: &str, protocols: &[&str], headers: &[(&str, &str)]) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let mut head = format!("GET {} {}\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: {}\r\n", target, Version::Http1_1, host, protocols.join(", "));
+    for (name, value) in headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    output.write_all(head.as_bytes()).await?;
+    flush_write(output).await?;
+
+    Ok(head.len())
+}
+
+/// Reads the response head to an upgrade request and returns its status and headers.
+///
+/// A `101 Switching Protocols` response must name one of the requested `protocols` in its
+/// `Upgrade` header after which the stream speaks that protocol. Other responses are returned
+/// as is and their body follows.
+pub async fn read_upgrade_response<I>(input: &mut I, protocols: &[&str], limits: &Limits) -> Result<(Status, Vec<(String, String)>), Error>
+    where
+    I: Read + Unpin,
+{
+    let (mut version, mut status, mut reason) = (vec![], vec![], vec![]);
+    read_first_line(input, (&mut version, &mut status, &mut reason), limits).await?;
+    parse_version(version)?;
+    let status = parse_status(status)?;
+
+    let mut headers = Vec::new();
+    read_headers(input, &mut headers, limits).await?;
+
+    if status == Status::SwitchingProtocols {
+        let upgrade = headers.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("upgrade"))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or("");
+        if select_upgrade(upgrade, protocols).is_none() {
+            return Err(Error::new(ErrorKind::InvalidData, format!("The server switched to the unrequested protocol `{}`.", upgrade)));
+        }
+    }
+
+    Ok((status, headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_upgrade_request() {
+        assert!(is_upgrade_request(Version::Http1_1, Some("keep-alive, Upgrade"), Some("websocket")));
+        assert!(!is_upgrade_request(Version::Http1_1, Some("keep-alive"), Some("websocket")));
+        assert!(!is_upgrade_request(Version::Http1_0, Some("upgrade"), Some("websocket")));
+        assert!(!is_upgrade_request(Version::Http1_1, Some("upgrade"), None));
+    }
+
+    #[test]
+    fn selects_upgrade() {
+        assert_eq!(select_upgrade("h2c, WebSocket", &["websocket", "foo"]), Some("websocket"));
+        assert_eq!(select_upgrade("h2c", &["websocket"]), None);
+    }
+
+    #[async_std::test]
+    async fn writes_switching_protocols() {
+        let mut output = Vec::new();
+        write_switching_protocols(&mut output, "foo/2", &[("X-Id", "1")]).await.unwrap();
+        assert_eq!(output, "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: foo/2\r\nX-Id: 1\r\n\r\n".as_bytes());
+        let mut output = Vec::new();
+        write_upgrade_required(&mut output, &["foo/2", "bar"]).await.unwrap();
+        assert_eq!(output, "HTTP/1.1 426 Upgrade Required\r\nConnection: Upgrade\r\nUpgrade: foo/2, bar\r\nContent-Length: 0\r\n\r\n".as_bytes());
+    }
+
+    #[async_std::test]
+    async fn performs_client_handshake() {
+        let mut output = Vec::new();
+        write_upgrade_request(&mut output, "/chat", "example.com", &["foo"], &[]).await.unwrap();
+        assert_eq!(output, "GET /chat HTTP/1.1\r\nHost: example.com\r\nConnection: Upgrade\r\nUpgrade: foo\r\n\r\n".as_bytes());
+
+        let mut input = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: FOO\r\nConnection: upgrade\r\n\r\nhello".as_bytes();
+        let (status, headers) = read_upgrade_response(&mut input, &["foo"], &Limits::default()).await.unwrap();
+        assert_eq!(status, Status::SwitchingProtocols);
+        assert_eq!(headers.len(), 2);
+        assert_eq!(input, b"hello");
+        let mut input = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: bar\r\n\r\n".as_bytes();
+        assert!(read_upgrade_response(&mut input, &["foo"], &Limits::default()).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn reads_buffered_bytes_first() {
+        let mut upgraded = Upgraded::new("world".as_bytes(), b"hello ".to_vec());
+        let mut output = String::new();
+        upgraded.read_to_string(&mut output).await.unwrap();
+        assert_eq!(output, "hello world");
+    }
+}
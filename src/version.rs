@@ -3,7 +3,7 @@ use std::cmp::Ordering;
 use std::io::{Error, ErrorKind};
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Version {
     Http0_9 = 9,
     Http1_0 = 10,
@@ -12,6 +12,17 @@ pub enum Version {
     Http3_0 = 30,
 }
 
+impl Version {
+
+    /// Returns `true` if connections are persistent unless `Connection: close` is sent.
+    ///
+    /// HTTP/1.0 connections only persist with the `Connection: keep-alive` option. See
+    /// [the spec](https://tools.ietf.org/html/rfc7230#section-6.3) for more details.
+    pub fn is_persistent_by_default(&self) -> bool {
+        *self >= Version::Http1_1
+    }
+}
+
 impl Display for Version {
 
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -48,7 +59,7 @@ impl FromStr for Version {
     }
 }
 
-impl<'a> std::convert::TryFrom<&[u8]> for Version {
+impl std::convert::TryFrom<&[u8]> for Version {
     type Error = Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
@@ -71,12 +82,6 @@ impl Ord for Version {
     }
 }
 
-impl PartialEq for Version {
-    fn eq(&self, other: &Version) -> bool {
-        (*self as usize) == (*other as usize)
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,6 +105,12 @@ mod tests {
         assert_eq!(version.to_string(), "HTTP/2");
     }
 
+    #[test]
+    fn implements_persistence() {
+        assert!(Version::Http1_1.is_persistent_by_default());
+        assert!(!Version::Http1_0.is_persistent_by_default());
+    }
+
     #[test]
     fn implements_ordering() {
         assert!(Version::Http1_1 > Version::Http0_9);
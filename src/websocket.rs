@@ -0,0 +1,432 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::io::{Error, ErrorKind};
+use async_std::prelude::*;
+use async_std::io::{Read, Write};
+use crate::{read_upgrade_response, flush_write, is_upgrade_request, select_upgrade,
+    Limits, Method, Status, Version};
+use crate::base64;
+use crate::media::{skip_whitespace, read_token, read_quoted_string, is_token_char};
+use crate::parse::split_list;
+use crate::sha1::sha1;
+
+/// The only protocol version defined by RFC 6455.
+pub const WEBSOCKET_VERSION: &str = "13";
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Extension offered or accepted in the `Sec-WebSocket-Extensions` header.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc6455#section-9.1) for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketExtension {
+    name: String,
+    params: Vec<(String, Option<String>)>,
+}
+
+impl WebSocketExtension {
+
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_lowercase(),
+            params: Vec::new(),
+        }
+    }
+
+    pub fn with_param(mut self, name: &str, value: Option<&str>) -> Self {
+        self.params.push((name.to_lowercase(), value.map(String::from)));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn params(&self) -> &Vec<(String, Option<String>)> {
+        &self.params
+    }
+
+    /// Returns `Some(None)` for a parameter without value and `None` if it is missing.
+    pub fn param(&self, name: &str) -> Option<Option<&str>> {
+        self.params.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_deref())
+    }
+}
+
+impl Display for WebSocketExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (name, value) in self.params.iter() {
+            match value {
+                None => write!(f, "; {}", name)?,
+                Some(v) if !v.is_empty() && v.bytes().all(is_token_char) => write!(f, "; {}={}", name, v)?,
+                Some(v) => write!(f, "; {}=\"{}\"", name, v.replace('\\', "\\\\").replace('"', "\\\""))?,
+            };
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for WebSocketExtension {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("The WebSocket extension `{}` is invalid.", s));
+        let bytes = s.as_bytes();
+        let mut index = skip_whitespace(bytes, 0);
+
+        let name = read_token(bytes, &mut index);
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        let mut extension = Self::new(&name);
+
+        loop {
+            index = skip_whitespace(bytes, index);
+            match bytes.get(index) {
+                None => break,
+                Some(b';') => index = skip_whitespace(bytes, index + 1),
+                Some(_) => return Err(invalid()),
+            };
+
+            let name = read_token(bytes, &mut index);
+            if name.is_empty() {
+                return Err(invalid());
+            }
+            index = skip_whitespace(bytes, index);
+            let value = match bytes.get(index) {
+                Some(b'=') => {
+                    index = skip_whitespace(bytes, index + 1);
+                    let value = match bytes.get(index) {
+                        Some(b'"') => read_quoted_string(bytes, &mut index).ok_or_else(invalid)?,
+                        _ => read_token(bytes, &mut index),
+                    };
+                    if value.is_empty() {
+                        return Err(invalid());
+                    }
+                    Some(value)
+                },
+                _ => None,
+            };
+            extension.params.push((name.to_lowercase(), value));
+        }
+
+        Ok(extension)
+    }
+}
+
+impl std::convert::TryFrom<&[u8]> for WebSocketExtension {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(txt) => Self::from_str(&txt),
+            Err(e) => Err(Error::new(ErrorKind::InvalidInput, e.to_string())),
+        }
+    }
+}
+
+/// Opening handshake of a WebSocket client validated by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketRequest {
+    key: String,
+    protocols: Vec<String>,
+    extensions: Vec<WebSocketExtension>,
+}
+
+impl WebSocketRequest {
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The subprotocols offered by the client in its order of preference.
+    pub fn protocols(&self) -> &Vec<String> {
+        &self.protocols
+    }
+
+    /// The extensions offered by the client in its order of preference.
+    pub fn extensions(&self) -> &Vec<WebSocketExtension> {
+        &self.extensions
+    }
+
+    /// Returns the value of the `Sec-WebSocket-Accept` response header.
+    pub fn accept(&self) -> String {
+        websocket_accept(&self.key)
+    }
+
+    /// Returns the first subprotocol offered by the client which the server supports.
+    /// Subprotocols are compared case-sensitively.
+    pub fn select_protocol<'a>(&self, supported: &[&'a str]) -> Option<&'a str> {
+        self.protocols.iter().find_map(|protocol| supported.iter().find(|s| *s == protocol).copied())
+    }
+}
+
+/// Returns the `Sec-WebSocket-Accept` value proving that the server read the `key`.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc6455#section-4.2.2) for more details.
+pub fn websocket_accept(key: &str) -> String {
+    base64::encode(&sha1(format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes()))
+}
+
+/// Returns a new random `Sec-WebSocket-Key` made of 16 base64-encoded bytes.
+pub fn generate_websocket_key() -> String {
+    base64::encode(&random_bytes(16))
+}
+
+/// Returns bytes from the operating system's secure random number generator for nonces and
+/// masking keys.
+///
+/// Panics if the operating system cannot provide random bytes.
+pub(crate) fn random_bytes(length: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; length];
+    getrandom::getrandom(&mut bytes).expect("The operating system failed to provide random bytes.");
+
+    bytes
+}
+
+/// Parses a `Sec-WebSocket-Extensions` header value into the listed extensions.
+pub fn parse_websocket_extensions(value: &str) -> Result<Vec<WebSocketExtension>, Error> {
+    split_list(value).into_iter().map(WebSocketExtension::from_str).collect()
+}
+
+/// Validates the opening handshake of a WebSocket client from the request line and headers.
+///
+/// Malformed handshakes are rejected with `Status::BadRequest`. A client speaking an
+/// unsupported protocol version is rejected with `Status::UpgradeRequired` which should be
+/// answered with `write_websocket_version_required`.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc6455#section-4.2.1) for more details.
+pub fn validate_websocket_request(method: Method, version: Version, headers: &[(String, String)]) -> Result<WebSocketRequest, Status> {
+    let values = |name: &str| headers.iter()
+        .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+        .collect::<Vec<&str>>();
+    let joined = |name: &str| match values(name) {
+        v if v.is_empty() => None,
+        v => Some(v.join(", ")),
+    };
+
+    let connection = joined("connection");
+    let upgrade = joined("upgrade");
+    let upgrading = is_upgrade_request(version, connection.as_deref(), upgrade.as_deref())
+        && select_upgrade(upgrade.as_deref().unwrap_or(""), &["websocket"]).is_some();
+    if method != Method::Get || values("host").len() != 1 || !upgrading {
+        return Err(Status::BadRequest);
+    }
+
+    let key = match values("sec-websocket-key").as_slice() {
+        [key] if base64::decode(key.trim()).map(|k| k.len() == 16).unwrap_or(false) => key.trim().to_string(),
+        _ => return Err(Status::BadRequest),
+    };
+    match values("sec-websocket-version").as_slice() {
+        [v] if v.trim() == WEBSOCKET_VERSION => (),
+        _ => return Err(Status::UpgradeRequired),
+    };
+
+    let protocols = values("sec-websocket-protocol").into_iter()
+        .flat_map(split_list)
+        .map(String::from)
+        .collect();
+    let extensions = match joined("sec-websocket-extensions") {
+        Some(value) => parse_websocket_extensions(&value).map_err(|_| Status::BadRequest)?,
+        None => Vec::new(),
+    };
+
+    Ok(WebSocketRequest { key, protocols, extensions })
+}
+
+/// Writes the `101 Switching Protocols` response accepting the WebSocket `request` with the
+/// selected subprotocol and the accepted extensions.
+pub async fn write_websocket_accept<O>(output: &mut O, request: &WebSocketRequest, protocol: Option<&str>, extensions: &[WebSocketExtension]) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let mut head = format!("{} {} {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {}\r\n", Version::Http1_1, Status::SwitchingProtocols, Status::SwitchingProtocols.reason(), request.accept());
+    if let Some(protocol) = protocol {
+        head.push_str(&format!("Sec-WebSocket-Protocol: {}\r\n", protocol));
+    }
+    if !extensions.is_empty() {
+        head.push_str(&format!("Sec-WebSocket-Extensions: {}\r\n", join_extensions(extensions)));
+    }
+    head.push_str("\r\n");
+
+    output.write_all(head.as_bytes()).await?;
+    flush_write(output).await?;
+
+    Ok(head.len())
+}
+
+/// Writes the `426 Upgrade Required` response without body which lists the supported
+/// `Sec-WebSocket-Version`.
+pub async fn write_websocket_version_required<O>(output: &mut O) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let head = format!("{} {} {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: {}\r\nContent-Length: 0\r\n\r\n", Version::Http1_1, Status::UpgradeRequired, Status::UpgradeRequired.reason(), WEBSOCKET_VERSION);
+
+    output.write_all(head.as_bytes()).await?;
+    flush_write(output).await?;
+
+    Ok(head.len())
+}
+
+/// Writes the opening handshake of a WebSocket client. The `key` is usually created by
+/// `generate_websocket_key`.
+pub async fn write_websocket_request<O>(output: &mut O, target: &str, host: &str, key: &str, protocols: &[&str], extensions: &[WebSocketExtension]) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let mut head = format!("GET {} {}\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: {}\r\n", target, Version::Http1_1, host, key, WEBSOCKET_VERSION);
+    if !protocols.is_empty() {
+        head.push_str(&format!("Sec-WebSocket-Protocol: {}\r\n", protocols.join(", ")));
+    }
+    if !extensions.is_empty() {
+        head.push_str(&format!("Sec-WebSocket-Extensions: {}\r\n", join_extensions(extensions)));
+    }
+    head.push_str("\r\n");
+
+    output.write_all(head.as_bytes()).await?;
+    flush_write(output).await?;
+
+    Ok(head.len())
+}
+
+/// Reads and verifies the server's answer to a WebSocket opening handshake.
+///
+/// Returns the selected subprotocol and the accepted extensions. The handshake fails unless
+/// the server switched protocols with the `Sec-WebSocket-Accept` matching the `key`, selected
+/// one of the offered `protocols` (if any) and accepted only extensions named in `extensions`.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc6455#section-4.1) for more details.
+pub async fn read_websocket_response<I>(input: &mut I, key: &str, protocols: &[&str], extensions: &[&str], limits: &Limits) -> Result<(Option<String>, Vec<WebSocketExtension>), Error>
+    where
+    I: Read + Unpin,
+{
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+    let (status, headers) = read_upgrade_response(input, &["websocket"], limits).await?;
+    if status != Status::SwitchingProtocols {
+        return Err(invalid(format!("The server refused the WebSocket handshake with `{} {}`.", status, status.reason())));
+    }
+    let values = |name: &str| headers.iter()
+        .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.trim())
+        .collect::<Vec<&str>>();
+
+    if !values("connection").iter().flat_map(|v| split_list(v)).any(|t| t.eq_ignore_ascii_case("upgrade")) {
+        return Err(invalid(String::from("The server did not confirm the connection upgrade.")));
+    }
+    match values("sec-websocket-accept").as_slice() {
+        [accept] if *accept == websocket_accept(key) => (),
+        _ => return Err(invalid(String::from("The Sec-WebSocket-Accept header is invalid."))),
+    };
+
+    let protocol = match values("sec-websocket-protocol").as_slice() {
+        [] => None,
+        [protocol] if protocols.contains(protocol) => Some(protocol.to_string()),
+        _ => return Err(invalid(String::from("The server selected an unrequested WebSocket subprotocol."))),
+    };
+
+    let accepted = parse_websocket_extensions(&values("sec-websocket-extensions").join(", "))?;
+    if let Some(extension) = accepted.iter().find(|e| !extensions.iter().any(|n| n.eq_ignore_ascii_case(e.name()))) {
+        return Err(invalid(format!("The server accepted the unrequested WebSocket extension `{}`.", extension.name())));
+    }
+
+    Ok((protocol, accepted))
+}
+
+fn join_extensions(extensions: &[WebSocketExtension]) -> String {
+    extensions.iter().map(|e| e.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    fn handshake() -> Vec<(String, String)> {
+        headers(&[
+            ("Host", "server.example.com"),
+            ("Upgrade", "websocket"),
+            ("Connection", "Upgrade"),
+            ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+            ("Sec-WebSocket-Protocol", "chat, superchat"),
+            ("Sec-WebSocket-Version", "13"),
+        ])
+    }
+
+    #[test]
+    fn computes_accept() {
+        assert_eq!(websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn generates_key() {
+        let key = generate_websocket_key();
+        assert_eq!(base64::decode(&key).unwrap().len(), 16);
+        assert_ne!(key, generate_websocket_key());
+    }
+
+    #[test]
+    fn parses_extensions() {
+        let extensions = parse_websocket_extensions("permessage-deflate; client_max_window_bits, foo; bar=\"1\"; baz = 2").unwrap();
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions[0].param("client_max_window_bits"), Some(None));
+        assert_eq!(extensions[1].param("bar"), Some(Some("1")));
+        assert_eq!(extensions[1].to_string(), "foo; bar=1; baz=2");
+        assert!(parse_websocket_extensions("foo; =1").is_err());
+    }
+
+    #[test]
+    fn validates_request() {
+        let request = validate_websocket_request(Method::Get, Version::Http1_1, &handshake()).unwrap();
+        assert_eq!(request.accept(), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(request.protocols(), &vec![String::from("chat"), String::from("superchat")]);
+        assert_eq!(request.select_protocol(&["superchat", "chat"]), Some("chat"));
+        assert_eq!(request.select_protocol(&["Chat"]), None);
+
+        assert_eq!(validate_websocket_request(Method::Post, Version::Http1_1, &handshake()), Err(Status::BadRequest));
+        assert_eq!(validate_websocket_request(Method::Get, Version::Http1_0, &handshake()), Err(Status::BadRequest));
+        let mut invalid = handshake();
+        invalid[3].1 = String::from("c2hvcnQ=");
+        assert_eq!(validate_websocket_request(Method::Get, Version::Http1_1, &invalid), Err(Status::BadRequest));
+        let mut invalid = handshake();
+        invalid[5].1 = String::from("8");
+        assert_eq!(validate_websocket_request(Method::Get, Version::Http1_1, &invalid), Err(Status::UpgradeRequired));
+    }
+
+    #[async_std::test]
+    async fn writes_server_responses() {
+        let request = validate_websocket_request(Method::Get, Version::Http1_1, &handshake()).unwrap();
+        let mut output = Vec::new();
+        write_websocket_accept(&mut output, &request, Some("chat"), &[WebSocketExtension::new("foo")]).await.unwrap();
+        assert_eq!(output, "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\nSec-WebSocket-Protocol: chat\r\nSec-WebSocket-Extensions: foo\r\n\r\n".as_bytes());
+        let mut output = Vec::new();
+        write_websocket_version_required(&mut output).await.unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("\r\nSec-WebSocket-Version: 13\r\n"));
+    }
+
+    #[async_std::test]
+    async fn performs_client_handshake() {
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let mut output = Vec::new();
+        write_websocket_request(&mut output, "/chat", "server.example.com", key, &["chat"], &[]).await.unwrap();
+        assert_eq!(output, "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: chat\r\n\r\n".as_bytes());
+
+        let response = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\nSec-WebSocket-Protocol: chat\r\n\r\n";
+        let (protocol, extensions) = read_websocket_response(&mut response.as_bytes(), key, &["chat"], &[], &Limits::default()).await.unwrap();
+        assert_eq!(protocol, Some(String::from("chat")));
+        assert!(extensions.is_empty());
+        assert!(read_websocket_response(&mut response.as_bytes(), key, &["superchat"], &[], &Limits::default()).await.is_err());
+        assert!(read_websocket_response(&mut response.as_bytes(), "c2hvcnQ=", &["chat"], &[], &Limits::default()).await.is_err());
+        let refused = "HTTP/1.1 403 Forbidden\r\n\r\n";
+        assert!(read_websocket_response(&mut refused.as_bytes(), key, &[], &[], &Limits::default()).await.is_err());
+        let extended = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\nSec-WebSocket-Extensions: foo\r\n\r\n";
+        assert!(read_websocket_response(&mut extended.as_bytes(), key, &[], &[], &Limits::default()).await.is_err());
+        assert_eq!(read_websocket_response(&mut extended.as_bytes(), key, &[], &["foo"], &Limits::default()).await.unwrap().1.len(), 1);
+    }
+}
@@ -0,0 +1,301 @@
+use std::io::{Error, ErrorKind};
+use async_std::io::Write;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+use crate::{flush_write, WebSocketExtension, WebSocketMessage, WebSocketRole};
+use crate::websocket_frame::{encode_message, write_fragments};
+
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Parameters of the `permessage-deflate` extension.
+///
+/// Compression always uses a window of 15 bits. Offers asking the server to compress with a
+/// smaller window are therefore declined while any window is accepted for decompression.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7692#section-7.1) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeflateParams {
+    pub server_no_context_takeover: bool,
+    pub client_no_context_takeover: bool,
+    pub server_max_window_bits: Option<u8>,
+    pub client_max_window_bits: Option<Option<u8>>,
+}
+
+impl DeflateParams {
+
+    pub fn new() -> Self {
+        Self {
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+            server_max_window_bits: None,
+            client_max_window_bits: None,
+        }
+    }
+
+    /// Parses the parameters of a `permessage-deflate` offer or response.
+    pub fn from_extension(extension: &WebSocketExtension) -> Result<Self, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, format!("The WebSocket extension `{}` is invalid.", extension));
+        if extension.name() != "permessage-deflate" {
+            return Err(invalid());
+        }
+
+        let mut params = Self::new();
+        for (index, (name, value)) in extension.params().iter().enumerate() {
+            if extension.params()[..index].iter().any(|(n, _)| n == name) {
+                return Err(invalid());
+            }
+            let bits = || match value.as_deref().map(|v| v.parse::<u8>()) {
+                Some(Ok(bits)) if (8..=15).contains(&bits) => Ok(bits),
+                _ => Err(invalid()),
+            };
+            match (name.as_str(), value) {
+                ("server_no_context_takeover", None) => params.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => params.client_no_context_takeover = true,
+                ("server_max_window_bits", Some(_)) => params.server_max_window_bits = Some(bits()?),
+                ("client_max_window_bits", None) => params.client_max_window_bits = Some(None),
+                ("client_max_window_bits", Some(_)) => params.client_max_window_bits = Some(Some(bits()?)),
+                _ => return Err(invalid()),
+            };
+        }
+
+        Ok(params)
+    }
+
+    /// Returns the `permessage-deflate` extension carrying these parameters.
+    pub fn to_extension(&self) -> WebSocketExtension {
+        let mut extension = WebSocketExtension::new("permessage-deflate");
+        if self.server_no_context_takeover {
+            extension = extension.with_param("server_no_context_takeover", None);
+        }
+        if self.client_no_context_takeover {
+            extension = extension.with_param("client_no_context_takeover", None);
+        }
+        if let Some(bits) = self.server_max_window_bits {
+            extension = extension.with_param("server_max_window_bits", Some(&bits.to_string()));
+        }
+        if let Some(bits) = self.client_max_window_bits {
+            extension = extension.with_param("client_max_window_bits", bits.map(|b| b.to_string()).as_deref());
+        }
+        extension
+    }
+}
+
+impl Default for DeflateParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Selects the first acceptable `permessage-deflate` offer of a client and returns the
+/// negotiated parameters together with the extension to send in the response.
+///
+/// Invalid offers and offers limiting the server window below 15 bits are skipped.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc7692#section-5) for more details.
+pub fn negotiate_deflate(offers: &[WebSocketExtension]) -> Option<(DeflateParams, WebSocketExtension)> {
+    offers.iter()
+        .filter(|offer| offer.name() == "permessage-deflate")
+        .filter_map(|offer| DeflateParams::from_extension(offer).ok())
+        .find(|params| params.server_max_window_bits.map(|bits| bits == 15).unwrap_or(true))
+        .map(|mut params| {
+            if params.client_max_window_bits == Some(None) {
+                params.client_max_window_bits = None; // the default of 15 bits is fine
+            }
+            (params, params.to_extension())
+        })
+}
+
+/// Returns the `permessage-deflate` offer of a client. The client does not allow the server to
+/// limit its window because compression always uses 15 bits.
+pub fn offer_deflate() -> WebSocketExtension {
+    DeflateParams::new().to_extension()
+}
+
+/// Validates the `permessage-deflate` response of the server to `offer_deflate`.
+pub fn accept_deflate(extension: &WebSocketExtension) -> Result<DeflateParams, Error> {
+    let params = DeflateParams::from_extension(extension)?;
+    match params.client_max_window_bits {
+        Some(Some(15)) | None => Ok(params),
+        _ => Err(Error::new(ErrorKind::InvalidData, "The server limited the client window although the client did not offer it.")),
+    }
+}
+
+/// Compresses and decompresses message payloads of one side of a connection with the
+/// negotiated `permessage-deflate` parameters.
+#[derive(Debug)]
+pub struct PerMessageDeflate {
+    compress: Compress,
+    decompress: Decompress,
+    compress_reset: bool,
+    decompress_reset: bool,
+}
+
+impl PerMessageDeflate {
+
+    pub fn new(params: DeflateParams, role: WebSocketRole) -> Self {
+        let (compress_reset, decompress_reset) = match role {
+            WebSocketRole::Server => (params.server_no_context_takeover, params.client_no_context_takeover),
+            WebSocketRole::Client => (params.client_no_context_takeover, params.server_no_context_takeover),
+        };
+        Self {
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            compress_reset,
+            decompress_reset,
+        }
+    }
+
+    /// Compresses a message payload and removes the trailing empty block.
+    ///
+    /// See [the spec](https://tools.ietf.org/html/rfc7692#section-7.2.1) for more details.
+    pub fn compress(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::with_capacity(data.len() / 2 + 64);
+        let start = self.compress.total_in();
+
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            if output.len() == output.capacity() {
+                output.reserve(1024);
+            }
+            self.compress.compress_vec(&data[consumed..], &mut output, FlushCompress::Sync)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+            if self.compress.total_in() - start == data.len() as u64 && output.len() < output.capacity() {
+                break;
+            }
+        }
+        if output.ends_with(&DEFLATE_TAIL) {
+            output.truncate(output.len() - DEFLATE_TAIL.len());
+        }
+        if self.compress_reset {
+            self.compress.reset();
+        }
+
+        Ok(output)
+    }
+
+    /// Decompresses a message payload. Payloads growing beyond `limit` bytes are rejected.
+    pub fn decompress(&mut self, data: &[u8], limit: Option<usize>) -> Result<Vec<u8>, Error> {
+        let mut input = data.to_vec();
+        input.extend_from_slice(&DEFLATE_TAIL);
+        let mut output = Vec::with_capacity(data.len() * 2 + 64);
+        let start = self.decompress.total_in();
+
+        loop {
+            let consumed = (self.decompress.total_in() - start) as usize;
+            let produced = self.decompress.total_out();
+            if output.len() == output.capacity() {
+                output.reserve(4096);
+            }
+            self.decompress.decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+            if limit.map(|limit| output.len() > limit).unwrap_or(false) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while decompressing the WebSocket message.", limit.unwrap())));
+            } else if self.decompress.total_in() - start == input.len() as u64 && output.len() < output.capacity() {
+                break;
+            } else if (self.decompress.total_in() - start) as usize == consumed && self.decompress.total_out() == produced && output.len() < output.capacity() {
+                return Err(Error::new(ErrorKind::InvalidData, "The compressed WebSocket message is truncated."));
+            }
+        }
+        if self.decompress_reset {
+            self.decompress.reset(false);
+        }
+
+        Ok(output)
+    }
+}
+
+/// Writes a message compressed with `permessage-deflate`, split into fragments of at most
+/// `limit` bytes each, and flushes the output.
+///
+/// Data messages are sent with the RSV1 bit set on their first frame. Control frames are never
+/// compressed.
+pub async fn write_deflated_message<O>(output: &mut O, message: &WebSocketMessage, role: WebSocketRole, deflate: &mut PerMessageDeflate, limit: Option<usize>) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let (opcode, payload) = encode_message(message)?;
+    let size = match opcode.is_control() {
+        true => write_fragments(output, opcode, payload, false, role, limit).await?,
+        false => write_fragments(output, opcode, deflate.compress(&payload)?, true, role, limit).await?,
+    };
+    flush_write(output).await?;
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_websocket_extensions, read_message, MessageAssembler};
+
+    #[test]
+    fn negotiates_params() {
+        let offers = parse_websocket_extensions("permessage-deflate; server_max_window_bits=10, permessage-deflate; client_max_window_bits; server_no_context_takeover").unwrap();
+        let (params, response) = negotiate_deflate(&offers).unwrap();
+        assert!(params.server_no_context_takeover);
+        assert_eq!(params.client_max_window_bits, None);
+        assert_eq!(response.to_string(), "permessage-deflate; server_no_context_takeover");
+
+        let offers = parse_websocket_extensions("permessage-deflate; client_max_window_bits=9").unwrap();
+        assert_eq!(negotiate_deflate(&offers).unwrap().1.to_string(), "permessage-deflate; client_max_window_bits=9");
+        let offers = parse_websocket_extensions("permessage-deflate; server_max_window_bits=16, permessage-deflate; foo").unwrap();
+        assert!(negotiate_deflate(&offers).is_none());
+        let offers = parse_websocket_extensions("permessage-deflate; server_no_context_takeover; server_no_context_takeover").unwrap();
+        assert!(negotiate_deflate(&offers).is_none());
+    }
+
+    #[test]
+    fn accepts_server_response() {
+        assert_eq!(offer_deflate().to_string(), "permessage-deflate");
+        let response = parse_websocket_extensions("permessage-deflate; server_max_window_bits=12").unwrap();
+        assert_eq!(accept_deflate(&response[0]).unwrap().server_max_window_bits, Some(12));
+        let response = parse_websocket_extensions("permessage-deflate; client_max_window_bits=10").unwrap();
+        assert!(accept_deflate(&response[0]).is_err());
+    }
+
+    #[test]
+    fn decompresses_rfc_example() {
+        let mut deflate = PerMessageDeflate::new(DeflateParams::new(), WebSocketRole::Client);
+        let payload = [0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00];
+        assert_eq!(deflate.decompress(&payload, None).unwrap(), b"Hello");
+        assert_eq!(deflate.decompress(&[0xf2, 0x00, 0x11, 0x00, 0x00], None).unwrap(), b"Hello"); // shared context
+        assert!(deflate.decompress(&payload, Some(4)).is_err());
+    }
+
+    #[test]
+    fn compresses_with_context_takeover() {
+        let text = "a".repeat(10000);
+        let mut server = PerMessageDeflate::new(DeflateParams::new(), WebSocketRole::Server);
+        let mut client = PerMessageDeflate::new(DeflateParams::new(), WebSocketRole::Client);
+        let first = server.compress(text.as_bytes()).unwrap();
+        let second = server.compress(text.as_bytes()).unwrap();
+        assert!(first.len() < 100 && second.len() < first.len());
+        assert_eq!(client.decompress(&first, None).unwrap(), text.as_bytes());
+        assert_eq!(client.decompress(&second, None).unwrap(), text.as_bytes());
+
+        let params = DeflateParams { server_no_context_takeover: true, ..DeflateParams::new() };
+        let mut server = PerMessageDeflate::new(params, WebSocketRole::Server);
+        assert_eq!(server.compress(b"Hello").unwrap(), server.compress(b"Hello").unwrap());
+    }
+
+    #[async_std::test]
+    async fn exchanges_compressed_messages() {
+        let mut deflate = PerMessageDeflate::new(DeflateParams::new(), WebSocketRole::Client);
+        let message = WebSocketMessage::Text("Hello ".repeat(100));
+        let mut output = Vec::new();
+        write_deflated_message(&mut output, &message, WebSocketRole::Client, &mut deflate, Some(8)).await.unwrap();
+        write_deflated_message(&mut output, &WebSocketMessage::Ping(vec![1]), WebSocketRole::Client, &mut deflate, None).await.unwrap();
+        assert_eq!(output[0], 0x41);
+
+        let mut input = output.as_slice();
+        let mut assembler = MessageAssembler::with_deflate(PerMessageDeflate::new(DeflateParams::new(), WebSocketRole::Server));
+        assert_eq!(read_message(&mut input, WebSocketRole::Server, &mut assembler, None, None).await.unwrap(), message);
+        assert_eq!(read_message(&mut input, WebSocketRole::Server, &mut assembler, None, None).await.unwrap(), WebSocketMessage::Ping(vec![1]));
+
+        let mut input = output.as_slice();
+        let mut assembler = MessageAssembler::with_deflate(PerMessageDeflate::new(DeflateParams::new(), WebSocketRole::Server));
+        assert!(read_message(&mut input, WebSocketRole::Server, &mut assembler, None, Some(100)).await.is_err());
+        let mut input = output.as_slice();
+        assert!(read_message(&mut input, WebSocketRole::Server, &mut MessageAssembler::new(), None, None).await.is_err());
+    }
+}
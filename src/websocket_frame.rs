@@ -0,0 +1,571 @@
+use std::io::{Error, ErrorKind};
+use std::convert::TryFrom;
+use async_std::prelude::*;
+use async_std::io::{Read, Write};
+use crate::{read_exact, flush_write};
+use crate::websocket::random_bytes;
+use crate::websocket_deflate::PerMessageDeflate;
+
+/// Side of a WebSocket connection. Clients mask the frames they send, servers must not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebSocketRole {
+    Client,
+    Server,
+}
+
+/// WebSocket frame opcodes.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc6455#section-5.2) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Continuation = 0x0,
+    Text = 0x1,
+    Binary = 0x2,
+    Close = 0x8,
+    Ping = 0x9,
+    Pong = 0xA,
+}
+
+impl Opcode {
+
+    /// Returns `true` for close, ping and pong frames.
+    pub fn is_control(&self) -> bool {
+        *self as u8 & 0x8 != 0
+    }
+}
+
+impl TryFrom<u8> for Opcode {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x0 => Ok(Opcode::Continuation),
+            0x1 => Ok(Opcode::Text),
+            0x2 => Ok(Opcode::Binary),
+            0x8 => Ok(Opcode::Close),
+            0x9 => Ok(Opcode::Ping),
+            0xA => Ok(Opcode::Pong),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("The WebSocket opcode {} is reserved.", value))),
+        }
+    }
+}
+
+/// Status codes sent in close frames.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc6455#section-7.4) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseCode {
+    Normal,
+    GoingAway,
+    ProtocolError,
+    UnsupportedData,
+    NoStatus,
+    Abnormal,
+    InvalidPayload,
+    PolicyViolation,
+    MessageTooBig,
+    MandatoryExtension,
+    InternalError,
+    Other(u16),
+}
+
+impl CloseCode {
+
+    /// Returns `false` for codes which must never be sent in a close frame (e.g. `1005` or
+    /// `1006` which only report a missing status) or which are not assigned.
+    pub fn is_sendable(&self) -> bool {
+        matches!(u16::from(*self), 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            1000 => CloseCode::Normal,
+            1001 => CloseCode::GoingAway,
+            1002 => CloseCode::ProtocolError,
+            1003 => CloseCode::UnsupportedData,
+            1005 => CloseCode::NoStatus,
+            1006 => CloseCode::Abnormal,
+            1007 => CloseCode::InvalidPayload,
+            1008 => CloseCode::PolicyViolation,
+            1009 => CloseCode::MessageTooBig,
+            1010 => CloseCode::MandatoryExtension,
+            1011 => CloseCode::InternalError,
+            code => CloseCode::Other(code),
+        }
+    }
+}
+
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> Self {
+        match code {
+            CloseCode::Normal => 1000,
+            CloseCode::GoingAway => 1001,
+            CloseCode::ProtocolError => 1002,
+            CloseCode::UnsupportedData => 1003,
+            CloseCode::NoStatus => 1005,
+            CloseCode::Abnormal => 1006,
+            CloseCode::InvalidPayload => 1007,
+            CloseCode::PolicyViolation => 1008,
+            CloseCode::MessageTooBig => 1009,
+            CloseCode::MandatoryExtension => 1010,
+            CloseCode::InternalError => 1011,
+            CloseCode::Other(code) => code,
+        }
+    }
+}
+
+/// Single WebSocket frame with an unmasked payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketFrame {
+    fin: bool,
+    rsv1: bool,
+    opcode: Opcode,
+    payload: Vec<u8>,
+}
+
+impl WebSocketFrame {
+
+    /// Creates the final frame of a message.
+    pub fn new(opcode: Opcode, payload: Vec<u8>) -> Self {
+        Self { fin: true, rsv1: false, opcode, payload }
+    }
+
+    pub fn with_fin(mut self, fin: bool) -> Self {
+        self.fin = fin;
+        self
+    }
+
+    /// Sets the first reserved bit which is only allowed when an extension defines it.
+    pub fn with_rsv1(mut self, rsv1: bool) -> Self {
+        self.rsv1 = rsv1;
+        self
+    }
+
+    pub fn is_fin(&self) -> bool {
+        self.fin
+    }
+
+    pub fn is_rsv1(&self) -> bool {
+        self.rsv1
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    pub fn payload(&self) -> &Vec<u8> {
+        &self.payload
+    }
+
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+}
+
+/// Complete WebSocket message or control frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+
+    /// Close frame with its optional status code and reason.
+    Close(Option<(CloseCode, String)>),
+}
+
+/// Reassembles fragmented messages from frames.
+///
+/// Control frames may arrive between the fragments of a message and are returned right away
+/// while the fragments received so far are kept.
+#[derive(Debug, Default)]
+pub struct MessageAssembler {
+    fragments: Option<(Opcode, bool, Vec<u8>)>,
+    deflate: Option<PerMessageDeflate>,
+}
+
+impl MessageAssembler {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an assembler which accepts messages compressed with the negotiated
+    /// `permessage-deflate` extension and marked with the RSV1 bit.
+    pub fn with_deflate(deflate: PerMessageDeflate) -> Self {
+        Self { fragments: None, deflate: Some(deflate) }
+    }
+
+    /// Returns `true` while a fragmented message is incomplete.
+    pub fn is_pending(&self) -> bool {
+        self.fragments.is_some()
+    }
+
+    /// Adds a frame and returns the message once it is complete. Messages larger than `limit`
+    /// bytes, before and after decompression, are rejected.
+    pub fn push(&mut self, frame: WebSocketFrame, limit: Option<usize>) -> Result<Option<WebSocketMessage>, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+
+        let compressible = self.deflate.is_some() && !frame.opcode.is_control() && frame.opcode != Opcode::Continuation;
+        if frame.rsv1 && !compressible {
+            return Err(invalid("The WebSocket frame uses a reserved bit without extension."));
+        }
+        let (opcode, compressed, data) = match (frame.opcode, self.fragments.take()) {
+            (opcode, fragments) if opcode.is_control() => {
+                self.fragments = fragments;
+                return parse_control(opcode, frame.payload).map(Some);
+            },
+            (Opcode::Continuation, None) => return Err(invalid("The WebSocket continuation frame has no message to continue.")),
+            (Opcode::Continuation, Some((opcode, compressed, mut data))) => {
+                data.extend_from_slice(&frame.payload);
+                (opcode, compressed, data)
+            },
+            (_, Some(_)) => return Err(invalid("The WebSocket message started before the previous one was complete.")),
+            (opcode, None) => (opcode, frame.rsv1, frame.payload),
+        };
+
+        if limit.map(|limit| data.len() > limit).unwrap_or(false) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while reading the WebSocket message.", limit.unwrap())));
+        } else if !frame.fin {
+            self.fragments = Some((opcode, compressed, data));
+            return Ok(None);
+        }
+
+        let data = match (compressed, self.deflate.as_mut()) {
+            (true, Some(deflate)) => deflate.decompress(&data, limit)?,
+            _ => data,
+        };
+
+        match opcode {
+            Opcode::Text => match String::from_utf8(data) {
+                Ok(text) => Ok(Some(WebSocketMessage::Text(text))),
+                Err(_) => Err(invalid("The WebSocket text message is not valid UTF-8.")),
+            },
+            _ => Ok(Some(WebSocketMessage::Binary(data))),
+        }
+    }
+}
+
+/// Reads a single frame and unmasks its payload.
+///
+/// Frames from a client must be masked and frames from a server must not. Control frames must
+/// not be fragmented and carry at most 125 bytes. Frames with a payload larger than `limit`
+/// bytes are rejected before the payload is read.
+///
+/// See [the spec](https://tools.ietf.org/html/rfc6455#section-5.2) for more details.
+pub async fn read_frame<I>(input: &mut I, role: WebSocketRole, limit: Option<usize>) -> Result<WebSocketFrame, Error>
+    where
+    I: Read + Unpin,
+{
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+
+    let mut head = Vec::with_capacity(14);
+    read_exact(input, &mut head, 2).await?;
+    let fin = head[0] & 0x80 != 0;
+    let rsv1 = head[0] & 0x40 != 0;
+    let opcode = Opcode::try_from(head[0] & 0x0F)?;
+    let masked = head[1] & 0x80 != 0;
+
+    if head[0] & 0x30 != 0 {
+        return Err(invalid("The WebSocket frame uses a reserved bit without extension."));
+    } else if masked != (role == WebSocketRole::Server) {
+        return Err(invalid("The WebSocket frame masking does not match the sender."));
+    }
+
+    let length = match head[1] & 0x7F {
+        126 => {
+            read_exact(input, &mut head, 2).await?;
+            u16::from_be_bytes([head[2], head[3]]) as u64
+        },
+        127 => {
+            read_exact(input, &mut head, 8).await?;
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&head[2..10]);
+            u64::from_be_bytes(bytes)
+        },
+        length => length as u64,
+    };
+
+    if opcode.is_control() && (!fin || length > 125) {
+        return Err(invalid("The WebSocket control frame is fragmented or too large."));
+    } else if length >> 63 != 0 || length > usize::MAX as u64 {
+        return Err(invalid("The WebSocket frame length is invalid."));
+    } else if limit.map(|limit| length > limit as u64).unwrap_or(false) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while reading the WebSocket frame.", limit.unwrap())));
+    }
+
+    let mut mask = Vec::with_capacity(4);
+    if masked {
+        read_exact(input, &mut mask, 4).await?;
+    }
+    let mut payload = Vec::new();
+    read_exact(input, &mut payload, length as usize).await?;
+    if masked {
+        apply_mask(&mut payload, &mask);
+    }
+
+    Ok(WebSocketFrame { fin, rsv1, opcode, payload })
+}
+
+/// Writes a single frame. Clients mask the payload with a random key.
+pub async fn write_frame<O>(output: &mut O, frame: &WebSocketFrame, role: WebSocketRole) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let mut data = Vec::with_capacity(frame.payload.len() + 14);
+    data.push((frame.fin as u8) << 7 | (frame.rsv1 as u8) << 6 | frame.opcode as u8);
+
+    let masked = (role == WebSocketRole::Client) as u8;
+    match frame.payload.len() {
+        length if length < 126 => data.push(masked << 7 | length as u8),
+        length if length <= u16::MAX as usize => {
+            data.push(masked << 7 | 126);
+            data.extend_from_slice(&(length as u16).to_be_bytes());
+        },
+        length => {
+            data.push(masked << 7 | 127);
+            data.extend_from_slice(&(length as u64).to_be_bytes());
+        },
+    };
+
+    let start = data.len();
+    if role == WebSocketRole::Client {
+        let mask = random_bytes(4);
+        data.extend_from_slice(&mask);
+        data.extend_from_slice(&frame.payload);
+        apply_mask(&mut data[start + 4..], &mask);
+    } else {
+        data.extend_from_slice(&frame.payload);
+    }
+
+    output.write_all(&data).await?;
+
+    Ok(data.len())
+}
+
+/// Reads frames until a message or control frame is complete.
+///
+/// The `frame_limit` and `message_limit` are the maximum sizes of a frame payload and of a
+/// reassembled message. Close frames are validated for their status code and UTF-8 reason.
+pub async fn read_message<I>(input: &mut I, role: WebSocketRole, assembler: &mut MessageAssembler, frame_limit: Option<usize>, message_limit: Option<usize>) -> Result<WebSocketMessage, Error>
+    where
+    I: Read + Unpin,
+{
+    loop {
+        let frame = read_frame(input, role, frame_limit).await?;
+        if let Some(message) = assembler.push(frame, message_limit)? {
+            return Ok(message);
+        }
+    }
+}
+
+/// Writes a message, split into fragments of at most `limit` bytes each, and flushes the
+/// output.
+pub async fn write_message<O>(output: &mut O, message: &WebSocketMessage, role: WebSocketRole, limit: Option<usize>) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let (opcode, payload) = encode_message(message)?;
+    let size = write_fragments(output, opcode, payload, false, role, limit).await?;
+    flush_write(output).await?;
+
+    Ok(size)
+}
+
+/// Returns the opcode and payload of a message after checking the control frame rules.
+pub(crate) fn encode_message(message: &WebSocketMessage) -> Result<(Opcode, Vec<u8>), Error> {
+    let (opcode, payload) = match message {
+        WebSocketMessage::Text(text) => (Opcode::Text, text.as_bytes().to_vec()),
+        WebSocketMessage::Binary(data) => (Opcode::Binary, data.clone()),
+        WebSocketMessage::Ping(data) => (Opcode::Ping, data.clone()),
+        WebSocketMessage::Pong(data) => (Opcode::Pong, data.clone()),
+        WebSocketMessage::Close(None) => (Opcode::Close, vec![]),
+        WebSocketMessage::Close(Some((code, reason))) => {
+            if !code.is_sendable() {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("The WebSocket close code {} must not be sent.", u16::from(*code))));
+            }
+            let mut payload = u16::from(*code).to_be_bytes().to_vec();
+            payload.extend_from_slice(reason.as_bytes());
+            (Opcode::Close, payload)
+        },
+    };
+    if opcode.is_control() && payload.len() > 125 {
+        return Err(Error::new(ErrorKind::InvalidInput, "The WebSocket control frame payload is larger than 125 bytes."));
+    }
+
+    Ok((opcode, payload))
+}
+
+/// Writes a payload as frames of at most `limit` bytes. Only the first frame carries `rsv1`.
+pub(crate) async fn write_fragments<O>(output: &mut O, opcode: Opcode, payload: Vec<u8>, rsv1: bool, role: WebSocketRole, limit: Option<usize>) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    match limit {
+        Some(limit) if !opcode.is_control() && limit > 0 && payload.len() > limit => {
+            let mut size = 0;
+            let count = (payload.len() + limit - 1) / limit;
+            for (index, chunk) in payload.chunks(limit).enumerate() {
+                let opcode = if index == 0 { opcode } else { Opcode::Continuation };
+                let frame = WebSocketFrame::new(opcode, chunk.to_vec()).with_fin(index + 1 == count).with_rsv1(rsv1 && index == 0);
+                size += write_frame(output, &frame, role).await?;
+            }
+            Ok(size)
+        },
+        _ => write_frame(output, &WebSocketFrame::new(opcode, payload).with_rsv1(rsv1), role).await,
+    }
+}
+
+fn apply_mask(data: &mut [u8], mask: &[u8]) {
+    for (index, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+}
+
+fn parse_control(opcode: Opcode, payload: Vec<u8>) -> Result<WebSocketMessage, Error> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+
+    match opcode {
+        Opcode::Ping => Ok(WebSocketMessage::Ping(payload)),
+        Opcode::Pong => Ok(WebSocketMessage::Pong(payload)),
+        _ => match payload.len() {
+            0 => Ok(WebSocketMessage::Close(None)),
+            1 => Err(invalid("The WebSocket close frame is invalid.")),
+            _ => {
+                let code = CloseCode::from(u16::from_be_bytes([payload[0], payload[1]]));
+                if !code.is_sendable() {
+                    return Err(invalid("The WebSocket close code is invalid."));
+                }
+                match String::from_utf8(payload[2..].to_vec()) {
+                    Ok(reason) => Ok(WebSocketMessage::Close(Some((code, reason)))),
+                    Err(_) => Err(invalid("The WebSocket close reason is not valid UTF-8.")),
+                }
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Accepts a single byte per write.
+    struct ShortWriter(Vec<u8>);
+
+    impl Write for ShortWriter {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+            let size = buf.len().min(1);
+            self.get_mut().0.extend_from_slice(&buf[..size]);
+            Poll::Ready(Ok(size))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[async_std::test]
+    async fn reads_rfc_examples() {
+        let mut input = &[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f][..];
+        let frame = read_frame(&mut input, WebSocketRole::Client, None).await.unwrap();
+        assert_eq!(frame, WebSocketFrame::new(Opcode::Text, b"Hello".to_vec()));
+
+        let mut input = &[0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58][..];
+        let frame = read_frame(&mut input, WebSocketRole::Server, None).await.unwrap();
+        assert_eq!(frame.payload(), b"Hello");
+
+        let mut input = &[0x01, 0x03, 0x48, 0x65, 0x6c, 0x89, 0x00, 0x80, 0x02, 0x6c, 0x6f][..];
+        let mut assembler = MessageAssembler::new();
+        let message = read_message(&mut input, WebSocketRole::Client, &mut assembler, None, None).await.unwrap();
+        assert_eq!(message, WebSocketMessage::Ping(vec![]));
+        assert!(assembler.is_pending());
+        let message = read_message(&mut input, WebSocketRole::Client, &mut assembler, None, None).await.unwrap();
+        assert_eq!(message, WebSocketMessage::Text(String::from("Hello")));
+        assert!(!assembler.is_pending());
+    }
+
+    #[async_std::test]
+    async fn enforces_frame_rules() {
+        let mut input = &[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f][..];
+        assert!(read_frame(&mut input, WebSocketRole::Server, None).await.is_err()); // unmasked
+        let mut input = &[0xC1, 0x00][..];
+        assert!(read_frame(&mut input, WebSocketRole::Client, None).await.is_ok());
+        let mut input = &[0xA1, 0x00][..];
+        assert!(read_frame(&mut input, WebSocketRole::Client, None).await.is_err()); // rsv2
+        let mut input = &[0x83, 0x00][..];
+        assert!(read_frame(&mut input, WebSocketRole::Client, None).await.is_err()); // opcode
+        let mut input = &[0x09, 0x00][..];
+        assert!(read_frame(&mut input, WebSocketRole::Client, None).await.is_err()); // fragmented ping
+        let mut input = &[0x82, 0x7E, 0x01, 0x00][..];
+        assert!(read_frame(&mut input, WebSocketRole::Client, Some(255)).await.is_err());
+    }
+
+    #[test]
+    fn assembles_messages() {
+        let mut assembler = MessageAssembler::new();
+        assert!(assembler.push(WebSocketFrame::new(Opcode::Continuation, vec![]), None).is_err());
+        assert!(assembler.push(WebSocketFrame::new(Opcode::Text, vec![0xff]), None).is_err());
+        assert!(assembler.push(WebSocketFrame::new(Opcode::Binary, vec![1]).with_rsv1(true), None).is_err());
+        assert_eq!(assembler.push(WebSocketFrame::new(Opcode::Binary, vec![1, 2]).with_fin(false), Some(3)).unwrap(), None);
+        assert!(assembler.push(WebSocketFrame::new(Opcode::Text, vec![]), None).is_err());
+        assert_eq!(assembler.push(WebSocketFrame::new(Opcode::Binary, vec![1, 2]).with_fin(false), Some(3)).unwrap(), None);
+        assert!(assembler.push(WebSocketFrame::new(Opcode::Continuation, vec![3, 4]), Some(3)).is_err());
+    }
+
+    #[test]
+    fn validates_close_frames() {
+        let close = |payload: Vec<u8>| parse_control(Opcode::Close, payload);
+        assert_eq!(close(vec![]).unwrap(), WebSocketMessage::Close(None));
+        assert_eq!(close(vec![0x03, 0xe8, b'o', b'k']).unwrap(), WebSocketMessage::Close(Some((CloseCode::Normal, String::from("ok")))));
+        assert!(close(vec![0x03]).is_err());
+        assert!(close(vec![0x03, 0xed]).is_err()); // 1005
+        assert!(close(vec![0x03, 0xe8, 0xff]).is_err());
+        assert!(CloseCode::Other(4000).is_sendable());
+        assert!(!CloseCode::from(2000).is_sendable());
+    }
+
+    #[async_std::test]
+    async fn writes_messages() {
+        let mut output = Vec::new();
+        write_message(&mut output, &WebSocketMessage::Text(String::from("Hello")), WebSocketRole::Server, Some(3)).await.unwrap();
+        assert_eq!(output, vec![0x01, 0x03, 0x48, 0x65, 0x6c, 0x80, 0x02, 0x6c, 0x6f]);
+
+        let mut output = Vec::new();
+        let message = WebSocketMessage::Binary(vec![7; 70000]);
+        write_message(&mut output, &message, WebSocketRole::Client, None).await.unwrap();
+        assert_eq!(&output[..2], &[0x82, 0xFF]);
+        let mut assembler = MessageAssembler::new();
+        let read = read_message(&mut output.as_slice(), WebSocketRole::Server, &mut assembler, None, None).await.unwrap();
+        assert_eq!(read, message);
+
+        let mut output = Vec::new();
+        let message = WebSocketMessage::Close(Some((CloseCode::NoStatus, String::new())));
+        assert!(write_message(&mut output, &message, WebSocketRole::Server, None).await.is_err());
+        let message = WebSocketMessage::Ping(vec![0; 126]);
+        assert!(write_message(&mut output, &message, WebSocketRole::Server, None).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn reads_huge_length_without_allocating() {
+        let mut input = &[0x82, 0x7F, 0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x02][..];
+        let error = read_frame(&mut input, WebSocketRole::Client, None).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[async_std::test]
+    async fn writes_whole_frame() {
+        let frame = WebSocketFrame::new(Opcode::Binary, vec![7; 300]);
+        let mut output = ShortWriter(Vec::new());
+        let size = write_frame(&mut output, &frame, WebSocketRole::Server).await.unwrap();
+        assert_eq!(size, 304);
+        assert_eq!(read_frame(&mut output.0.as_slice(), WebSocketRole::Client, None).await.unwrap(), frame);
+    }
+}
@@ -1,7 +1,7 @@
 use async_std::prelude::*;
 use async_std::io::{Read, Write};
-use std::io::{Error, ErrorKind};
-use crate::{relay_exact};
+use std::io::{Error};
+use crate::{relay_exact, HttpError, Limit, Limits};
 
 pub async fn write_slice<O>(output: &mut O, data: &[u8]) -> Result<usize, Error>
     where
@@ -25,7 +25,8 @@ pub async fn write_exact<O, I>(output: &mut O, input: &mut I, length: usize) ->
     relay_exact(input, output, length).await
 }
 
-pub async fn write_all<O, I>(output: &mut O, input: &mut I, limit: Option<usize>) -> Result<usize, Error>
+/// Writes the `input` until it ends. The `body` limit applies.
+pub async fn write_all<O, I>(output: &mut O, input: &mut I, limits: &Limits) -> Result<usize, Error>
     where
     O: Write + Unpin,
     I: Read + Unpin,
@@ -41,8 +42,8 @@ pub async fn write_all<O, I>(output: &mut O, input: &mut I, limit: Option<usize>
 
         if size == 0 {
             break;
-        } else if limit.is_some() && length > limit.unwrap() {
-            return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while writing.", limit.unwrap())));
+        } else if let Some(max) = limits.body.filter(|max| length > *max) {
+            return Err(HttpError::LimitExceeded { limit: Limit::Body, max, offset: total }.into());
         }
 
         total += output.write(&bytes).await?;
@@ -52,33 +53,26 @@ pub async fn write_all<O, I>(output: &mut O, input: &mut I, limit: Option<usize>
     Ok(total)
 }
 
-pub async fn write_chunks<O, I>(output: &mut O, input: &mut I, limits: (Option<usize>, Option<usize>)) -> Result<usize, Error>
+/// Writes the `input` as a chunked body until it ends. Chunks hold at most 1024 bytes, or
+/// fewer when the `chunk` limit is lower, and the `body` limit applies.
+pub async fn write_chunks<O, I>(output: &mut O, input: &mut I, limits: &Limits) -> Result<usize, Error>
     where
     O: Write + Unpin,
     I: Read + Unpin,
 {
-    let (chunklimit, datalimit) = limits;
-    let chunksize = match chunklimit {
-        Some(chunksize) => chunksize,
-        None => 1024,
-    };
+    let mut bytes = vec![0u8; limits.chunk.unwrap_or(1024).clamp(1, 1024)];
     let mut total = 0; // all written bytes
     let mut length = 0; // data written bytes
     
     loop {
-        let mut bytes = vec![0u8; chunksize];
         let size = input.read(&mut bytes).await?;
-        bytes = bytes[0..size].to_vec();
         length += size;
 
-        if datalimit.is_some() && length > datalimit.unwrap() {
-            return Err(Error::new(ErrorKind::InvalidData, format!("The operation hit the limit of {} bytes while writing chunked HTTP body.", datalimit.unwrap())));
+        if let Some(max) = limits.body.filter(|max| length > *max) {
+            return Err(HttpError::LimitExceeded { limit: Limit::Body, max, offset: total }.into());
         }
 
-        total += output.write(format!("{:x}\r\n", size).as_bytes()).await?;
-        total += output.write(&bytes).await?;
-        total += output.write(b"\r\n").await?;
-        output.flush().await?;
+        total += write_chunk(output, &bytes[0..size]).await?;
 
         if size == 0 {
             break;
@@ -88,9 +82,45 @@ pub async fn write_chunks<O, I>(output: &mut O, input: &mut I, limits: (Option<u
     Ok(total)
 }
 
+/// Writes a single chunk of the chunked transfer coding and flushes the output. An empty
+/// `data` writes the last chunk.
+pub async fn write_chunk<O>(output: &mut O, data: &[u8]) -> Result<usize, Error>
+    where
+    O: Write + Unpin,
+{
+    let line = format!("{:x}\r\n", data.len());
+    output.write_all(line.as_bytes()).await?;
+    output.write_all(data).await?;
+    output.write_all(b"\r\n").await?;
+    output.flush().await?;
+
+    Ok(line.len() + data.len() + 2)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Accepts a single byte per write.
+    struct ShortWriter(Vec<u8>);
+
+    impl Write for ShortWriter {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+            let size = buf.len().min(1);
+            self.get_mut().0.extend_from_slice(&buf[..size]);
+            Poll::Ready(Ok(size))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
 
     #[async_std::test]
     async fn writes_exact() {
@@ -103,22 +133,40 @@ mod tests {
     #[async_std::test]
     async fn writes_all() {
         let mut output = Vec::new();
-        let size = write_all(&mut output, &mut "0123456789".as_bytes(), None).await.unwrap();
+        let size = write_all(&mut output, &mut "0123456789".as_bytes(), &Limits::unlimited()).await.unwrap();
         assert_eq!(size, 10);
         assert_eq!(output, b"0123456789");
         let mut output = Vec::new();
-        let exceeded = write_all(&mut output, &mut "012".as_bytes(), Some(2)).await;
+        let exceeded = write_all(&mut output, &mut "012".as_bytes(), &Limits::new().with_body(Some(2))).await;
         assert!(exceeded.is_err());
     }
 
+    #[async_std::test]
+    async fn writes_chunk() {
+        let mut output = Vec::new();
+        let size = write_chunk(&mut output, b"0123456789abcdef").await.unwrap();
+        assert_eq!(size, 22);
+        assert_eq!(output, "10\r\n0123456789abcdef\r\n".as_bytes());
+        let mut output = ShortWriter(Vec::new());
+        let size = write_chunk(&mut output, b"0123456789abcdef").await.unwrap();
+        assert_eq!(size, 22);
+        assert_eq!(output.0, "10\r\n0123456789abcdef\r\n".as_bytes());
+    }
+
     #[async_std::test]
     async fn writes_chunks() {
         let mut output = Vec::new();
-        let size = write_chunks(&mut output, &mut "0123456789".as_bytes(), (Some(3), None)).await.unwrap();
+        let size = write_chunks(&mut output, &mut "0123456789".as_bytes(), &Limits::unlimited().with_chunk(Some(3))).await.unwrap();
         assert_eq!(size, 35);
         assert_eq!(output, "3\r\n012\r\n3\r\n345\r\n3\r\n678\r\n1\r\n9\r\n0\r\n\r\n".as_bytes());
         let mut output = Vec::new();
-        let exceeded = write_chunks(&mut output, &mut "0123456789".as_bytes(), (Some(3), Some(4))).await;
+        let exceeded = write_chunks(&mut output, &mut "0123456789".as_bytes(), &Limits::new().with_chunk(Some(3)).with_body(Some(4))).await;
         assert!(exceeded.is_err());
+        let mut output = Vec::new();
+        write_chunks(&mut output, &mut "01".as_bytes(), &Limits::new().with_chunk(Some(0))).await.unwrap();
+        assert_eq!(output, "1\r\n0\r\n1\r\n1\r\n0\r\n\r\n".as_bytes());
+        let mut output = Vec::new();
+        write_chunks(&mut output, &mut "x".repeat(1500).as_bytes(), &Limits::new()).await.unwrap();
+        assert!(output.starts_with(b"400\r\n"));
     }
 }
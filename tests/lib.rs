@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use async_httplib::{read_first_line, read_header_line, read_exact, parse_method,
-    parse_status, parse_version, Method, Status, Version};
+    parse_status, parse_version, Limits, Method, Status, Version};
 
 #[async_std::test]
 async fn reads_request() {
@@ -13,7 +13,7 @@ async fn reads_request() {
     let mut stream = stream.as_bytes();
 
     let (mut method, mut uri, mut version) = (vec![], vec![], vec![]);
-    let size = read_first_line(&mut stream, (&mut method, &mut uri, &mut version), None).await.unwrap();
+    let size = read_first_line(&mut stream, (&mut method, &mut uri, &mut version), &Limits::default()).await.unwrap();
     assert_eq!(size, 21);
 
     let method = parse_method(method).unwrap();
@@ -26,8 +26,8 @@ async fn reads_request() {
     let mut headers: HashMap<String, String> = HashMap::new();
     loop {
         let (mut name, mut value) = (vec![], vec![]);
-        read_header_line(&mut stream, (&mut name, &mut value), None).await.unwrap();
-        if name.len() == 0 {
+        read_header_line(&mut stream, (&mut name, &mut value), &Limits::default()).await.unwrap();
+        if name.is_empty() {
             break;
         } else {
             headers.insert(
@@ -57,7 +57,7 @@ async fn reads_response() {
     let mut stream = stream.as_bytes();
 
     let (mut version, mut status, mut message) = (vec![], vec![], vec![]);
-    let size = read_first_line(&mut stream, (&mut version, &mut status, &mut message), None).await.unwrap();
+    let size = read_first_line(&mut stream, (&mut version, &mut status, &mut message), &Limits::default()).await.unwrap();
     assert_eq!(size, 17);
 
     let version = parse_version(version).unwrap();
@@ -68,8 +68,8 @@ async fn reads_response() {
     let mut headers: HashMap<String, String> = HashMap::new();
     loop {
         let (mut name, mut value) = (vec![], vec![]);
-        read_header_line(&mut stream, (&mut name, &mut value), None).await.unwrap();
-        if name.len() == 0 {
+        read_header_line(&mut stream, (&mut name, &mut value), &Limits::default()).await.unwrap();
+        if name.is_empty() {
             break;
         } else {
             headers.insert(